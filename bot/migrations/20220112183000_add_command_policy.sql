@@ -0,0 +1,10 @@
+-- a room and a space can't share an id, but key on is_space anyway so a rule
+-- set on one can never replace or clear one set on the other
+CREATE TABLE IF NOT EXISTS command_policy
+(
+    scope    TEXT    NOT NULL,
+    is_space BOOLEAN NOT NULL,
+    path     TEXT    NOT NULL,
+    enabled  BOOLEAN NOT NULL,
+    PRIMARY KEY (scope, is_space, path)
+);
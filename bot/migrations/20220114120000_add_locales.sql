@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS locales
+(
+    scope   TEXT    NOT NULL,
+    is_user BOOLEAN NOT NULL,
+    locale  TEXT    NOT NULL,
+    PRIMARY KEY (scope, is_user)
+);
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS command_aliases
+(
+    scope      TEXT    NOT NULL,
+    is_user    BOOLEAN NOT NULL,
+    name       TEXT    NOT NULL,
+    expansion  TEXT    NOT NULL,
+    created_by TEXT    NOT NULL,
+    PRIMARY KEY (scope, is_user, name)
+);
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS tags
+(
+    room_id    TEXT NOT NULL,
+    name       TEXT NOT NULL,
+    content    TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    PRIMARY KEY (room_id, name)
+);
@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS auto_responses
+(
+    id            INTEGER PRIMARY KEY NOT NULL,
+    room_id       TEXT    NOT NULL,
+    pattern       TEXT    NOT NULL,
+    response      TEXT    NOT NULL,
+    cooldown_secs INTEGER NOT NULL,
+    created_by    TEXT    NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS auto_responses_room_id ON auto_responses (room_id);
@@ -0,0 +1,123 @@
+use bleh::framework::{
+    aliases::{Alias, AliasScope, AliasStore},
+    transport,
+};
+use matrix_sdk::ruma::{RoomId, UserId};
+use sqlx::SqlitePool;
+
+/// Keeps aliases in the `command_aliases` table
+pub struct SqliteAliasStore {
+    pool: SqlitePool,
+}
+
+impl SqliteAliasStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+fn scope_parts(scope: &AliasScope) -> (&str, bool) {
+    match scope {
+        AliasScope::Room(room_id) => (room_id.as_str(), false),
+        AliasScope::User(user_id) => (user_id.as_str(), true),
+    }
+}
+
+fn scope_from_parts(id: &str, is_user: bool) -> transport::Result<AliasScope> {
+    Ok(if is_user {
+        AliasScope::User(UserId::parse(id)?)
+    } else {
+        AliasScope::Room(RoomId::parse(id)?)
+    })
+}
+
+#[async_trait::async_trait]
+impl AliasStore for SqliteAliasStore {
+    async fn get(&self, scope: &AliasScope, name: &str) -> transport::Result<Option<Alias>> {
+        let (id, is_user) = scope_parts(scope);
+
+        let row = sqlx::query!(
+            r#"SELECT expansion, created_by FROM command_aliases
+               WHERE scope = ?1 AND is_user = ?2 AND name = ?3"#,
+            id,
+            is_user,
+            name,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match row {
+            Some(row) => Some(Alias {
+                scope: scope.clone(),
+                name: name.to_owned(),
+                expansion: row.expansion,
+                created_by: UserId::parse(row.created_by)?,
+            }),
+            None => None,
+        })
+    }
+
+    async fn list(&self, scopes: &[AliasScope]) -> transport::Result<Vec<Alias>> {
+        let mut aliases = Vec::new();
+
+        for scope in scopes {
+            let (id, is_user) = scope_parts(scope);
+
+            let rows = sqlx::query!(
+                r#"SELECT scope, is_user AS "is_user: bool", name, expansion, created_by
+                   FROM command_aliases WHERE scope = ?1 AND is_user = ?2"#,
+                id,
+                is_user,
+            )
+            .fetch_all(&self.pool)
+            .await?;
+
+            for row in rows {
+                aliases.push(Alias {
+                    scope: scope_from_parts(&row.scope, row.is_user)?,
+                    name: row.name,
+                    expansion: row.expansion,
+                    created_by: UserId::parse(row.created_by)?,
+                });
+            }
+        }
+
+        Ok(aliases)
+    }
+
+    async fn set(&self, alias: Alias) -> transport::Result<()> {
+        let (id, is_user) = scope_parts(&alias.scope);
+        let created_by = alias.created_by.as_str();
+
+        sqlx::query!(
+            r#"INSERT INTO command_aliases ( scope, is_user, name, expansion, created_by )
+               VALUES ( ?1, ?2, ?3, ?4, ?5 )
+               ON CONFLICT ( scope, is_user, name )
+               DO UPDATE SET expansion = ?4, created_by = ?5"#,
+            id,
+            is_user,
+            alias.name,
+            alias.expansion,
+            created_by,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, scope: &AliasScope, name: &str) -> transport::Result<bool> {
+        let (id, is_user) = scope_parts(scope);
+
+        let result = sqlx::query!(
+            "DELETE FROM command_aliases WHERE scope = ?1 AND is_user = ?2 AND name = ?3",
+            id,
+            is_user,
+            name,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,337 @@
+//! Replies sent when a message matches a pattern, no prefix needed, added from
+//! chat with `!respond add "good (morning|night)" Good $1 to you too!`.
+//!
+//! Responses are per room and added by moderators. Each has its own
+//! cooldown, a minute unless changed, so a busy room doesn't get the same
+//! reply over and over, and only a few are sent for any one message.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use regex::Regex;
+use sqlx::SqlitePool;
+
+use bleh::framework::commands::{cmd, Named, Remainder};
+use bleh::framework::context::{BaseContext, Context, ContextActions};
+use bleh::framework::params::{Bounded, REGEX_SIZE_LIMIT};
+use bleh::framework::policy::{self, MODERATOR_LEVEL};
+use bleh::framework::state::State;
+use bleh::framework::triggers::{Cooldowns, Trigger};
+
+const DEFAULT_COOLDOWN_SECS: i64 = 60;
+
+/// How many responses a room can have
+const MAX_RESPONSES: i64 = 50;
+
+/// How many responses are sent for a single message
+const MAX_SENDS_PER_MESSAGE: usize = 3;
+
+/// Compiled patterns keyed by the id of their response, so messages don't
+/// compile every pattern in the room again. Share the same one between
+/// `trigger` and the bot's state.
+#[derive(Debug, Default)]
+pub struct Patterns {
+    compiled: Mutex<HashMap<i64, Regex>>,
+}
+
+impl Patterns {
+    fn get(&self, id: i64, pattern: &str) -> Result<Regex, regex::Error> {
+        if let Some(regex) = self.compiled.lock().unwrap().get(&id) {
+            return Ok(regex.clone());
+        }
+
+        let regex = regex::RegexBuilder::new(pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .dfa_size_limit(REGEX_SIZE_LIMIT)
+            .build()?;
+
+        self.compiled.lock().unwrap().insert(id, regex.clone());
+
+        Ok(regex)
+    }
+
+    /// Drop the pattern of a response that was added or removed, ids can be
+    /// reused once a response is removed
+    fn forget(&self, id: i64) {
+        self.compiled.lock().unwrap().remove(&id);
+    }
+}
+
+/// Whether the author added the response or is a moderator, telling them if
+/// not
+async fn can_change(c: &BaseContext, p: &SqlitePool, id: i64) -> bool {
+    let room_id = c.room_id().as_str();
+
+    let created_by = match sqlx::query!(
+        "SELECT created_by FROM auto_responses WHERE room_id = ?1 AND id = ?2",
+        room_id,
+        id,
+    )
+    .fetch_optional(p)
+    .await
+    {
+        Ok(Some(row)) => row.created_by,
+        Ok(None) => {
+            let _ = c.reply(&format!("There's no response {} here", id)).await;
+            return false;
+        }
+        Err(err) => {
+            let _ = c
+                .reply(&format!("Couldn't load the response: {}", err))
+                .await;
+            return false;
+        }
+    };
+
+    created_by == c.author().as_str() || policy::require_power_level(c, MODERATOR_LEVEL).await
+}
+
+/// reply to messages matching a pattern
+#[bleh::group]
+pub mod respond {
+    use super::*;
+
+    /// reply whenever a message in this room matches a pattern
+    ///
+    /// `$1`, `$2` and so on in the response are replaced with what the
+    /// pattern's groups matched.
+    #[bleh::command(examples = [
+        "!respond add \"good (morning|night)\" Good $1 to you too!",
+        "!respond add (?i)\\bping\\b pong",
+    ])]
+    pub async fn add(
+        c: BaseContext,
+        State(p): State<SqlitePool>,
+        State(patterns): State<Arc<Patterns>>,
+        /// the regex to look for, quoted if it has spaces
+        Named(pattern): Named<regex::Regex, "pattern">,
+        /// what to reply with
+        Named(Remainder(response)): Named<Remainder, "response">,
+    ) {
+        let response = response.trim();
+
+        if response.is_empty() {
+            let _ = c.reply("A response needs something to send").await;
+            return;
+        }
+
+        if !policy::require_power_level(&c, MODERATOR_LEVEL).await {
+            return;
+        }
+
+        let room_id = c.room_id().as_str();
+        let pattern = pattern.as_str();
+        let author = c.author().as_str();
+        // counting in the insert itself, so two at once can't both fit
+        let result = sqlx::query!(
+            r#"INSERT INTO auto_responses ( room_id, pattern, response, cooldown_secs, created_by )
+               SELECT ?1, ?2, ?3, ?4, ?5
+               WHERE ( SELECT COUNT(*) FROM auto_responses WHERE room_id = ?1 ) < ?6"#,
+            room_id,
+            pattern,
+            response,
+            DEFAULT_COOLDOWN_SECS,
+            author,
+            MAX_RESPONSES,
+        )
+        .execute(&p)
+        .await;
+
+        let _ = match result {
+            Ok(done) if done.rows_affected() == 0 => {
+                c.reply(&format!(
+                    "A room can only have {} responses, remove one first",
+                    MAX_RESPONSES
+                ))
+                .await
+            }
+            Ok(done) => {
+                let id = done.last_insert_rowid();
+                patterns.forget(id);
+
+                c.reply(&format!("Added response {}", id)).await
+            }
+            Err(err) => {
+                c.reply(&format!("Couldn't save the response: {}", err))
+                    .await
+            }
+        };
+    }
+
+    /// stop replying with a response
+    #[bleh::command]
+    pub async fn remove(
+        c: BaseContext,
+        State(p): State<SqlitePool>,
+        State(patterns): State<Arc<Patterns>>,
+        /// the number shown by respond list
+        Named(id): Named<i64, "id">,
+    ) {
+        if !can_change(&c, &p, id).await {
+            return;
+        }
+
+        let room_id = c.room_id().as_str();
+        let result = sqlx::query!(
+            "DELETE FROM auto_responses WHERE room_id = ?1 AND id = ?2",
+            room_id,
+            id,
+        )
+        .execute(&p)
+        .await;
+
+        let _ = match result {
+            Ok(_) => {
+                patterns.forget(id);
+                c.react("✅").await
+            }
+            Err(err) => {
+                c.reply(&format!("Couldn't remove the response: {}", err))
+                    .await
+            }
+        };
+    }
+
+    /// change how long a response waits before it's sent again
+    #[bleh::command(examples = ["!respond cooldown 3 600"])]
+    pub async fn cooldown(
+        c: BaseContext,
+        State(p): State<SqlitePool>,
+        /// the number shown by respond list
+        Named(id): Named<i64, "id">,
+        /// how long to wait, in seconds
+        Named(Bounded(secs)): Named<Bounded<u32, 10, 86400>, "seconds">,
+    ) {
+        if !can_change(&c, &p, id).await {
+            return;
+        }
+
+        let room_id = c.room_id().as_str();
+        let result = sqlx::query!(
+            "UPDATE auto_responses SET cooldown_secs = ?3 WHERE room_id = ?1 AND id = ?2",
+            room_id,
+            id,
+            secs,
+        )
+        .execute(&p)
+        .await;
+
+        let _ = match result {
+            Ok(_) => c.react("✅").await,
+            Err(err) => {
+                c.reply(&format!("Couldn't save the response: {}", err))
+                    .await
+            }
+        };
+    }
+
+    /// list the responses in this room
+    #[bleh::command]
+    pub async fn list(c: BaseContext, State(p): State<SqlitePool>) {
+        let room_id = c.room_id().as_str();
+        let rows = sqlx::query!(
+            r#"SELECT id, pattern, response, cooldown_secs FROM auto_responses
+               WHERE room_id = ?1 ORDER BY id"#,
+            room_id,
+        )
+        .fetch_all(&p)
+        .await;
+
+        let _ = match rows {
+            Ok(rows) if rows.is_empty() => c.reply("There are no responses here").await,
+            Ok(rows) => {
+                let lines = rows
+                    .iter()
+                    .map(|row| {
+                        format!(
+                            "{}: /{}/ → {} (every {}s)",
+                            row.id, row.pattern, row.response, row.cooldown_secs
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                c.reply(&lines.join("\n")).await
+            }
+            Err(err) => {
+                c.reply(&format!("Couldn't load the responses: {}", err))
+                    .await
+            }
+        };
+    }
+}
+
+/// Send the room's responses matching a message, for
+/// `DispatcherBuilder::with_trigger`. Disabling `respond` in a room's policy
+/// disables them.
+pub fn trigger(patterns: Arc<Patterns>) -> Trigger<BaseContext> {
+    let cooldowns = Arc::new(Cooldowns::new());
+
+    let command = cmd(
+        move |c: BaseContext,
+              State(p): State<SqlitePool>,
+              Named(Remainder(message)): Named<Remainder, "message">| {
+            let cooldowns = cooldowns.clone();
+            let patterns = patterns.clone();
+
+            async move {
+                let room_id = c.room_id().as_str();
+                let rows = match sqlx::query!(
+                    r#"SELECT id, pattern, response, cooldown_secs FROM auto_responses
+                       WHERE room_id = ?1 ORDER BY id"#,
+                    room_id,
+                )
+                .fetch_all(&p)
+                .await
+                {
+                    Ok(rows) => rows,
+                    Err(err) => {
+                        tracing::warn!(?err, room_id, "Failed to load auto responses");
+                        return;
+                    }
+                };
+
+                let mut sent = 0;
+
+                for row in rows {
+                    if sent == MAX_SENDS_PER_MESSAGE {
+                        break;
+                    }
+
+                    let pattern = match patterns.get(row.id, &row.pattern) {
+                        Ok(pattern) => pattern,
+                        Err(err) => {
+                            tracing::warn!(?err, id = row.id, "Invalid auto response pattern");
+                            continue;
+                        }
+                    };
+
+                    let captures = match pattern.captures(&message) {
+                        Some(captures) => captures,
+                        None => continue,
+                    };
+
+                    let key = format!("{}:{}", room_id, row.id);
+                    let cooldown = Duration::from_secs(row.cooldown_secs.max(0) as u64);
+                    if !cooldowns.try_start(&key, c.transport().now(), cooldown) {
+                        continue;
+                    }
+
+                    let mut response = String::new();
+                    captures.expand(&row.response, &mut response);
+
+                    let _ = c.send(&response).await;
+                    sent += 1;
+                }
+            }
+        },
+    )
+    .with_description("send the responses added with respond add")
+    .with_param_docs(&[None, Some("the message to respond to")]);
+
+    Trigger::regex("auto responses", r"(?s).+", command)
+        .expect("The pattern is valid")
+        .with_path(&["respond"])
+}
@@ -0,0 +1,86 @@
+#![feature(adt_const_params)]
+#![feature(generic_const_exprs)]
+#![feature(const_fn_trait_bound)]
+#![feature(const_raw_ptr_deref)]
+#![feature(const_slice_from_raw_parts)]
+#![feature(const_type_name)]
+
+//! Run the bot's commands from a terminal, without a homeserver.
+//!
+//! ```sh
+//! REPL_USER=@me:localhost cargo run -p bleh-bot --bin bleh-repl
+//! ```
+
+use std::sync::Arc;
+
+use bleh::{
+    framework::{
+        config::{BotConfig, MessageKind},
+        dispatch::DispatcherBuilder,
+    },
+    repl::Repl,
+};
+use bleh_bot::{aliases, autoresponses, commands, locale, policy, tags::TagNames};
+use color_eyre::eyre::Result;
+use envconfig::Envconfig;
+use matrix_sdk::ruma::{RoomId, UserId};
+use sqlx::sqlite::SqlitePoolOptions;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Envconfig)]
+struct Config {
+    #[envconfig(from = "REPL_USER", default = "@user:localhost")]
+    user: String,
+
+    #[envconfig(from = "REPL_ROOM", default = "!repl:localhost")]
+    room: String,
+
+    /// Defaults to an in memory database so nothing is kept between runs
+    #[envconfig(from = "DATABASE_URL", default = "sqlite::memory:")]
+    sqlite_url: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::from_default_env()
+                .add_directive("bleh=info".parse()?)
+                .add_directive("bleh_bot=info".parse()?),
+        )
+        .with_writer(std::io::stderr)
+        .init();
+
+    let config = Config::init_from_env()?;
+
+    // every connection to `sqlite::memory:` gets its own database, so keep
+    // just the one around
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect(&config.sqlite_url)
+        .await?;
+    sqlx::migrate!().run(&pool).await?;
+
+    let tags = Arc::new(TagNames::load(&pool).await?);
+    let patterns = Arc::new(autoresponses::Patterns::default());
+
+    Repl::new(commands::make_commands(tags.clone())?)
+        .with_config(BotConfig::default().with_message_kind(MessageKind::Notice))
+        .with_state(pool.clone())
+        .with_state(tags)
+        .with_policy(Arc::new(policy::SqlitePolicyStore::new(pool.clone())))
+        .with_locale_store(Arc::new(locale::SqliteLocaleStore::new(pool.clone())))
+        .with_aliases(Arc::new(aliases::SqliteAliasStore::new(pool)))
+        .with_state(patterns.clone())
+        .with_trigger(autoresponses::trigger(patterns))
+        .with_author(UserId::parse(config.user.as_str())?)
+        .with_room(RoomId::parse(config.room.as_str())?)
+        .run(tokio::io::BufReader::new(tokio::io::stdin()))
+        .await?;
+
+    Ok(())
+}
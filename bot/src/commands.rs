@@ -0,0 +1,181 @@
+use std::sync::Arc;
+
+use rrule::RRule;
+use sqlx::SqlitePool;
+
+use bleh::framework::commands::{BuildErrors, Group, Named, Remainder};
+use bleh::framework::context::{BaseContext, Context, ContextActions};
+use bleh::framework::extractors::{GroupArg, ReplyContent, ReplyTarget, RoomRef};
+use bleh::framework::params::{Bounded, NonEmptyRemainder};
+use bleh::framework::state::State;
+
+use crate::tags::TagNames;
+
+#[bleh::group(name = "")]
+pub mod root {
+    use super::*;
+
+    /// say hi
+    #[bleh::command]
+    pub async fn hi(c: BaseContext) {
+        let _ = c.send("Hi").await;
+    }
+
+    /// uh oh
+    #[bleh::command]
+    pub async fn fart(c: BaseContext) {
+        let _ = c
+            .send_html(
+                "*farts*",
+                "<h1><span data-mx-color=\"#7a5901\">*farts*</span></h1>",
+            )
+            .await;
+    }
+
+    /// Some dumb recurrence rule thing
+    ///
+    /// Sends the message to this room every time the rule fires. Rules are
+    /// written as in RFC 5545, `FREQ` being required and `BYDAY`, `BYHOUR` and
+    /// `BYMINUTE` narrowing it down.
+    #[bleh::command(
+        category = "reminders",
+        examples = [
+            "!recur \"FREQ=WEEKLY;BYDAY=MO\" standup",
+            "!recur FREQ=DAILY;BYHOUR=9 good morning",
+        ]
+    )]
+    pub async fn recur(
+        c: BaseContext,
+        State(p): State<SqlitePool>,
+        /// an RFC 5545 recurrence rule, e.g. `FREQ=DAILY;BYHOUR=9`
+        Named(rule): Named<String, "rule">,
+        /// the message to send each time
+        Named(Remainder(message)): Named<Remainder, "message">,
+    ) {
+        let _parsed_rule: RRule = match rule.parse() {
+            Ok(rule) => rule,
+            Err(e) => {
+                let _ = c.reply(&format!("Couldn't parse rule: {:?}", e)).await;
+                return;
+            }
+        };
+
+        let room_id = c.room_id().as_ref();
+        let author_id = c.author().as_ref();
+        let id = sqlx::query!(
+            r#"INSERT INTO rrules ( rule, message, channel, userid )
+           VALUES ( ?1, ?2, ?3, ?4 )"#,
+            rule,
+            message,
+            room_id,
+            author_id,
+        )
+        .execute(&p)
+        .await
+        .unwrap()
+        .last_insert_rowid();
+
+        let r = crate::rrules::RRule {
+            id,
+            rule,
+            message,
+            channel: room_id.to_owned(),
+            userid: author_id.to_owned(),
+        };
+
+        let _ = c.react("✅").await;
+
+        tokio::spawn(async move {
+            r.perform(c.transport().clone()).await;
+        });
+    }
+
+    /// quote the message you're replying to
+    #[bleh::command]
+    pub async fn quote(c: BaseContext, target: ReplyTarget) {
+        let quote = match target.content() {
+            ReplyContent::Text(text) => format!("> {}\n- {}", text.body, target.sender()),
+            _ => "I can only quote text messages".to_owned(),
+        };
+
+        let _ = c.send(&quote).await;
+    }
+
+    /// quote some text
+    #[bleh::command(name = "quote", overload)]
+    pub async fn quote_text(
+        c: BaseContext,
+        /// the text to quote
+        Named(NonEmptyRemainder(text)): Named<NonEmptyRemainder, "text">,
+    ) {
+        let _ = c.send(&format!("> {}\n- {}", text, c.author())).await;
+    }
+
+    /// say something a few times
+    #[bleh::command]
+    pub async fn repeat(
+        c: BaseContext,
+        Named(Bounded(times)): Named<Bounded<u8, 1, 5>, "times">,
+        Named(Remainder(text)): Named<Remainder, "text">,
+    ) {
+        for _ in 0..times {
+            let _ = c.send(&text).await;
+        }
+    }
+
+    /// say something louder, pipe into it with `!help recur | !shout`
+    #[bleh::command]
+    pub async fn shout(
+        c: BaseContext,
+        /// what to shout
+        Named(Remainder(text)): Named<Remainder, "text">,
+    ) {
+        let _ = c.send(&text.trim().to_uppercase()).await;
+    }
+
+    /// no idea mate
+    #[bleh::group]
+    pub mod grp {
+        use super::*;
+
+        /// aaa
+        #[bleh::command]
+        pub async fn a(c: BaseContext) {
+            let _ = c.reply("A").await;
+        }
+
+        #[bleh::command]
+        pub async fn b(c: BaseContext, Named(v): Named<String, "v">) {
+            let _ = c.reply(&format!("B: {}", v)).await;
+        }
+    }
+
+    /// things to do with another room
+    #[bleh::group(params(Named<RoomRef, "room">))]
+    pub mod room {
+        use super::*;
+
+        /// show the id of the room
+        #[bleh::command]
+        pub async fn id(c: BaseContext, GroupArg(Named(room)): GroupArg<Named<RoomRef, "room">>) {
+            let _ = c.reply(room.room_id().as_str()).await;
+        }
+    }
+}
+
+/// The bot's command tree, with `tags` answering to the names of tags
+pub fn make_commands(tags: Arc<TagNames>) -> Result<Group<BaseContext>, BuildErrors> {
+    root::make_group()
+        .group
+        .group("commands", bleh::framework::policy::commands())
+        .group("locale", bleh::framework::i18n::commands())
+        .group("alias", bleh::framework::aliases::commands())
+        .add_group(crate::tags::tag::make_group())
+        .add_group(crate::autoresponses::respond::make_group())
+        .fallback(crate::tags::fallback(tags))
+        .command(
+            "help",
+            bleh::framework::help::command().with_aliases(&["h"]),
+        )
+        .build()
+}
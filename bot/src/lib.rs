@@ -0,0 +1,17 @@
+#![feature(adt_const_params)]
+#![feature(generic_const_exprs)]
+#![feature(const_fn_trait_bound)]
+#![feature(const_raw_ptr_deref)]
+#![feature(const_slice_from_raw_parts)]
+#![feature(const_type_name)]
+
+//! The commands this bot runs and the SQLite stores behind them, shared by the
+//! `bleh` and `bleh-repl` binaries.
+
+pub mod aliases;
+pub mod autoresponses;
+pub mod commands;
+pub mod locale;
+pub mod policy;
+pub mod rrules;
+pub mod tags;
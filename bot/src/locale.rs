@@ -0,0 +1,79 @@
+use bleh::framework::{
+    i18n::{LanguageIdentifier, LocaleScope, LocaleStore},
+    transport,
+};
+use sqlx::SqlitePool;
+
+/// Keeps the locales chosen by users and rooms in the `locales` table
+pub struct SqliteLocaleStore {
+    pool: SqlitePool,
+}
+
+impl SqliteLocaleStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+fn scope_parts(scope: &LocaleScope) -> (&str, bool) {
+    match scope {
+        LocaleScope::Room(room_id) => (room_id.as_str(), false),
+        LocaleScope::User(user_id) => (user_id.as_str(), true),
+    }
+}
+
+#[async_trait::async_trait]
+impl LocaleStore for SqliteLocaleStore {
+    async fn get(&self, scope: &LocaleScope) -> transport::Result<Option<LanguageIdentifier>> {
+        let (id, is_user) = scope_parts(scope);
+
+        let row = sqlx::query!(
+            "SELECT locale FROM locales WHERE scope = ?1 AND is_user = ?2",
+            id,
+            is_user,
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match row {
+            Some(row) => Some(row.locale.parse()?),
+            None => None,
+        })
+    }
+
+    async fn set(
+        &self,
+        scope: LocaleScope,
+        locale: Option<LanguageIdentifier>,
+    ) -> transport::Result<()> {
+        let (id, is_user) = scope_parts(&scope);
+
+        match locale {
+            Some(locale) => {
+                let locale = locale.to_string();
+
+                sqlx::query!(
+                    r#"INSERT INTO locales ( scope, is_user, locale )
+                       VALUES ( ?1, ?2, ?3 )
+                       ON CONFLICT ( scope, is_user ) DO UPDATE SET locale = ?3"#,
+                    id,
+                    is_user,
+                    locale,
+                )
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query!(
+                    "DELETE FROM locales WHERE scope = ?1 AND is_user = ?2",
+                    id,
+                    is_user,
+                )
+                .execute(&self.pool)
+                .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+#![feature(adt_const_params)]
+#![feature(generic_const_exprs)]
+#![feature(const_fn_trait_bound)]
+#![feature(const_raw_ptr_deref)]
+#![feature(const_slice_from_raw_parts)]
+#![feature(const_type_name)]
+
+use std::{ffi::OsString, sync::Arc};
+
+use bleh::{
+    framework::{
+        config::{BotConfig, MessageKind},
+        dispatch::DispatcherBuilder,
+        docs,
+        help::{DirectoryRenderer, HelpRenderer},
+        i18n::Locales,
+    },
+    handlers::Bot,
+};
+use bleh_bot::{aliases, autoresponses, commands, locale, policy, rrules, tags::TagNames};
+use color_eyre::eyre::{self, Result};
+use envconfig::Envconfig;
+use matrix_sdk::{
+    config::{ClientConfig, SyncSettings},
+    Client,
+};
+use path_abs::PathAbs;
+use sqlx::sqlite::SqlitePool;
+use tokio::fs;
+use tracing_subscriber::EnvFilter;
+
+#[derive(Envconfig)]
+struct Config {
+    #[envconfig(from = "MATRIX_CONFIG_PATH")]
+    config_path: OsString,
+
+    #[envconfig(from = "MATRIX_HOMESERVER")]
+    homeserver: url::Url,
+
+    #[envconfig(from = "MATRIX_USERNAME")]
+    username: String,
+
+    #[envconfig(from = "MATRIX_PASSWORD")]
+    password: String,
+
+    #[envconfig(from = "MATRIX_DEVICE_ID")]
+    device_id: Option<String>,
+
+    #[envconfig(from = "DATABASE_URL")]
+    sqlite_url: String,
+
+    /// A directory of Fluent messages, one subdirectory per locale
+    #[envconfig(from = "LOCALES_DIR")]
+    locales_dir: Option<OsString>,
+
+    /// A directory of Tera templates to render help with
+    #[envconfig(from = "HELP_TEMPLATES_DIR")]
+    help_templates_dir: Option<OsString>,
+}
+
+fn bot_config() -> BotConfig {
+    BotConfig::default()
+        .with_message_kind(MessageKind::Notice)
+        .with_read_receipts(true)
+}
+
+/// Print the command reference as `json` or `markdown`, for generating docs in CI
+fn export_docs(format: Option<&str>) -> Result<()> {
+    let meta = commands::make_commands(Arc::default())?.meta();
+    let prefix = bot_config().prefix;
+
+    match format {
+        Some("json") => println!("{:#}", docs::json(&meta, &prefix)),
+        Some("markdown") | None => print!("{}", docs::markdown(&meta, &prefix)),
+        Some(other) => eyre::bail!("Unknown docs format {}, expected json or markdown", other),
+    }
+
+    Ok(())
+}
+
+async fn login_and_sync(config: &Config, pool: SqlitePool) -> Result<()> {
+    let dir = PathAbs::new(&config.config_path)?;
+
+    fs::create_dir_all(&dir).await?;
+
+    let client_config = ClientConfig::new().store_path(&dir);
+
+    let client = Client::new_with_config(config.homeserver.clone(), client_config)?;
+
+    let device_id = config.device_id.as_deref();
+
+    if let Some(device_id) = device_id {
+        tracing::info!(device_id, "Restoring device id");
+    }
+
+    client
+        .login(
+            &config.username,
+            &config.password,
+            device_id,
+            Some("TestBot"),
+        )
+        .await?;
+
+    tracing::info!(username = %config.username, "Logged in {}", config.username);
+
+    client.sync_once(SyncSettings::default()).await?;
+
+    if device_id.is_none() {
+        println!(
+            "Generated device id: '{}'",
+            client.device_id().await.unwrap().as_str()
+        );
+    }
+
+    rrules::setup(client.clone(), &pool).await;
+
+    let locales = match &config.locales_dir {
+        Some(dir) => Locales::new().load_dir(dir)?,
+        None => Locales::new(),
+    };
+
+    let tags = Arc::new(TagNames::load(&pool).await?);
+    let patterns = Arc::new(autoresponses::Patterns::default());
+
+    let mut bot = Bot::new(client.clone(), commands::make_commands(tags.clone())?)
+        .with_config(bot_config())
+        .with_state(pool.clone())
+        .with_state(tags)
+        .with_policy(Arc::new(policy::SqlitePolicyStore::new(pool.clone())))
+        .with_locales(locales)
+        .with_locale_store(Arc::new(locale::SqliteLocaleStore::new(pool.clone())))
+        .with_aliases(Arc::new(aliases::SqliteAliasStore::new(pool)))
+        .with_state(patterns.clone())
+        .with_trigger(autoresponses::trigger(patterns));
+
+    if let Some(dir) = &config.help_templates_dir {
+        let renderer: Arc<dyn HelpRenderer> = Arc::new(DirectoryRenderer::load(dir)?);
+        bot = bot.with_state(renderer);
+    }
+
+    bot.register().await;
+
+    let sync_settings = SyncSettings::default().token(client.sync_token().await.unwrap());
+
+    client.sync(sync_settings).await;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    #[cfg(feature = "console")]
+    console_subscriber::init();
+
+    #[cfg(not(feature = "console"))]
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::from_default_env()
+                .add_directive("bleh=info".parse()?)
+                .add_directive("bleh_bot=info".parse()?),
+        )
+        .init();
+
+    let mut args = std::env::args().skip(1);
+    if args.next().as_deref() == Some("export-docs") {
+        return export_docs(args.next().as_deref());
+    }
+
+    let config = Config::init_from_env()?;
+
+    let pool = SqlitePool::connect(&config.sqlite_url).await?;
+
+    login_and_sync(&config, pool).await?;
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+use bleh::framework::{
+    policy::{PolicyRule, PolicyStore, Scope},
+    transport,
+};
+use sqlx::SqlitePool;
+
+/// Keeps command policy in the `command_policy` table
+pub struct SqlitePolicyStore {
+    pool: SqlitePool,
+}
+
+impl SqlitePolicyStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+fn scope_parts(scope: &Scope) -> (&str, bool) {
+    match scope {
+        Scope::Room(room_id) => (room_id.as_str(), false),
+        Scope::Space(room_id) => (room_id.as_str(), true),
+    }
+}
+
+#[async_trait::async_trait]
+impl PolicyStore for SqlitePolicyStore {
+    async fn rules(&self, scopes: &[Scope]) -> transport::Result<Vec<PolicyRule>> {
+        let mut rules = Vec::new();
+
+        for scope in scopes {
+            let (id, is_space) = scope_parts(scope);
+
+            let rows = sqlx::query!(
+                r#"SELECT path, enabled AS "enabled: bool" FROM command_policy
+                   WHERE scope = ?1 AND is_space = ?2"#,
+                id,
+                is_space,
+            )
+            .fetch_all(&self.pool)
+            .await?;
+
+            rules.extend(rows.into_iter().map(|row| PolicyRule {
+                scope: scope.clone(),
+                path: row.path,
+                enabled: row.enabled,
+            }));
+        }
+
+        Ok(rules)
+    }
+
+    async fn set(&self, rule: PolicyRule) -> transport::Result<()> {
+        let (id, is_space) = scope_parts(&rule.scope);
+
+        sqlx::query!(
+            r#"INSERT INTO command_policy ( scope, is_space, path, enabled )
+               VALUES ( ?1, ?2, ?3, ?4 )
+               ON CONFLICT ( scope, is_space, path ) DO UPDATE SET enabled = ?4"#,
+            id,
+            is_space,
+            rule.path,
+            rule.enabled,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn clear(&self, scope: &Scope, path: &str) -> transport::Result<bool> {
+        let (id, is_space) = scope_parts(scope);
+
+        let result = sqlx::query!(
+            "DELETE FROM command_policy WHERE scope = ?1 AND is_space = ?2 AND path = ?3",
+            id,
+            is_space,
+            path,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
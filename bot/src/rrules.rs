@@ -1,5 +1,6 @@
-use std::convert::TryInto;
+use std::{convert::TryInto, sync::Arc};
 
+use bleh::framework::transport::{MatrixTransport, Transport};
 use matrix_sdk::{
     ruma::{events::room::message::RoomMessageEventContent, RoomId, UserId},
     Client,
@@ -16,15 +17,16 @@ pub struct RRule {
 }
 
 impl RRule {
-    pub async fn perform(&self, client: Client) {
+    /// Send the message at every occurrence of the rule, `transport` must be
+    /// for the room the rule was created in
+    pub async fn perform(&self, transport: Arc<dyn Transport>) {
         let rrule: rrule::RRule = self
             .rule
             .parse()
             .expect("These should be validated earlier");
-        let room_id = RoomId::parse(self.channel.as_str()).unwrap();
         let user_id = UserId::parse(self.userid.as_str()).unwrap();
 
-        let now = chrono::offset::Utc::now();
+        let now = transport.now();
         for dt in &rrule {
             if dt < now {
                 continue;
@@ -32,23 +34,27 @@ impl RRule {
 
             println!("rrule until: {}", dt);
 
-            let utcnow = chrono::offset::Utc::now().timestamp();
+            let utcnow = transport.now().timestamp();
             let delta =
                 std::time::Duration::from_secs((dt.timestamp() - utcnow).try_into().unwrap());
             tokio::time::sleep(delta).await;
 
-            let joined = match client.get_joined_room(&room_id) {
-                Some(joined) => joined,
-                None => return,
-            };
-
             let m = RoomMessageEventContent::text_plain(&format!(
                 "{}: {}",
                 user_id.as_str(),
                 self.message
             ));
 
-            let _ = joined.send(m, None).await;
+            let m = serde_json::to_value(m).unwrap();
+
+            if let Err(err) = transport.send_event("m.room.message", m).await {
+                tracing::warn!(
+                    ?err,
+                    id = self.id,
+                    "Couldn't send recurring message, stopping"
+                );
+                return;
+            }
         }
     }
 }
@@ -60,9 +66,15 @@ pub async fn setup(client: Client, pool: &SqlitePool) {
         .unwrap();
 
     for r in rows {
-        let client = client.clone();
+        let room_id = RoomId::parse(r.channel.as_str()).unwrap();
+        let room = match client.get_joined_room(&room_id) {
+            Some(room) => room,
+            None => continue,
+        };
+        let transport = Arc::new(MatrixTransport::new(client.clone(), room));
+
         tokio::spawn(async move {
-            r.perform(client).await;
+            r.perform(transport).await;
         });
     }
 }
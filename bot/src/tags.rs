@@ -0,0 +1,308 @@
+//! Text saved from chat and sent with its own command, `!tag add rules <text>`
+//! and then `!rules`. Tags are per room and written in markdown.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, RwLock},
+};
+
+use matrix_sdk::ruma::RoomId;
+use pulldown_cmark::Event;
+use sqlx::SqlitePool;
+
+use bleh::framework::commands::{cmd, ErasedCommand, FallbackNames, Named, Remainder};
+use bleh::framework::context::{BaseContext, Context, ContextActions};
+use bleh::framework::policy::{self, MODERATOR_LEVEL};
+use bleh::framework::registry::registered_root;
+use bleh::framework::state::State;
+
+/// The names of every room's tags, kept in memory so the fallback can tell
+/// which messages are tags without a query. Share the same one between
+/// `fallback` and the bot's state.
+#[derive(Debug, Default)]
+pub struct TagNames {
+    rooms: RwLock<HashMap<Box<RoomId>, BTreeSet<String>>>,
+}
+
+impl TagNames {
+    pub async fn load(p: &SqlitePool) -> sqlx::Result<Self> {
+        let rows = sqlx::query!("SELECT room_id, name FROM tags")
+            .fetch_all(p)
+            .await?;
+
+        let tags = Self::default();
+
+        for row in rows {
+            match RoomId::parse(row.room_id.as_str()) {
+                Ok(room_id) => tags.insert(&room_id, &row.name),
+                Err(err) => tracing::warn!(?err, room_id = %row.room_id, "Invalid room id in tags"),
+            }
+        }
+
+        Ok(tags)
+    }
+
+    fn insert(&self, room_id: &RoomId, name: &str) {
+        self.rooms
+            .write()
+            .unwrap()
+            .entry(room_id.to_owned())
+            .or_default()
+            .insert(name.to_owned());
+    }
+
+    fn remove(&self, room_id: &RoomId, name: &str) {
+        if let Some(names) = self.rooms.write().unwrap().get_mut(room_id) {
+            names.remove(name);
+        }
+    }
+}
+
+impl FallbackNames for TagNames {
+    fn contains(&self, room_id: &RoomId, name: &str) -> bool {
+        let rooms = self.rooms.read().unwrap();
+
+        rooms
+            .get(room_id)
+            .map_or(false, |names| names.contains(name))
+    }
+
+    fn names(&self, room_id: &RoomId) -> Vec<String> {
+        let rooms = self.rooms.read().unwrap();
+
+        rooms
+            .get(room_id)
+            .map(|names| names.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Tags are written by anyone in the room, so HTML in them is sent as text
+fn render(markdown: &str) -> String {
+    let events = pulldown_cmark::Parser::new(markdown).map(|event| match event {
+        Event::Html(html) => Event::Text(html),
+        event => event,
+    });
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+async fn content(p: &SqlitePool, room_id: &str, name: &str) -> sqlx::Result<Option<String>> {
+    let row = sqlx::query!(
+        "SELECT content FROM tags WHERE room_id = ?1 AND name = ?2",
+        room_id,
+        name,
+    )
+    .fetch_optional(p)
+    .await?;
+
+    Ok(row.map(|row| row.content))
+}
+
+/// Whether the author made the tag or is a moderator, telling them if not
+async fn can_change(c: &BaseContext, p: &SqlitePool, name: &str) -> bool {
+    let room_id = c.room_id().as_str();
+
+    let created_by = match sqlx::query!(
+        "SELECT created_by FROM tags WHERE room_id = ?1 AND name = ?2",
+        room_id,
+        name,
+    )
+    .fetch_optional(p)
+    .await
+    {
+        Ok(Some(row)) => row.created_by,
+        Ok(None) => {
+            let _ = c.reply(&format!("There's no tag called {}", name)).await;
+            return false;
+        }
+        Err(err) => {
+            let _ = c.reply(&format!("Couldn't load the tag: {}", err)).await;
+            return false;
+        }
+    };
+
+    created_by == c.author().as_str() || policy::require_power_level(c, MODERATOR_LEVEL).await
+}
+
+/// save text to send with its own command
+#[bleh::group]
+pub mod tag {
+    use super::*;
+
+    /// add a tag to this room
+    #[bleh::command(examples = ["!tag add rules Be **nice**, no spam"])]
+    pub async fn add(
+        c: BaseContext,
+        State(p): State<SqlitePool>,
+        State(tags): State<Arc<TagNames>>,
+        /// the command the tag is sent with
+        Named(name): Named<String, "name">,
+        /// what to send, in markdown
+        Named(Remainder(content)): Named<Remainder, "content">,
+    ) {
+        let content = content.trim();
+
+        if name.contains(char::is_whitespace) || registered_root(&c).get(&name).is_some() {
+            let _ = c.reply(&format!("{} can't be used as a tag", name)).await;
+            return;
+        }
+
+        if content.is_empty() {
+            let _ = c.reply("A tag needs something to send").await;
+            return;
+        }
+
+        let room_id = c.room_id().as_str();
+        let author = c.author().as_str();
+        let result = sqlx::query!(
+            r#"INSERT INTO tags ( room_id, name, content, created_by )
+               VALUES ( ?1, ?2, ?3, ?4 )
+               ON CONFLICT ( room_id, name ) DO NOTHING"#,
+            room_id,
+            name,
+            content,
+            author,
+        )
+        .execute(&p)
+        .await;
+
+        let _ = match result {
+            Ok(done) if done.rows_affected() == 0 => {
+                c.reply(&format!(
+                    "There's already a tag called {}, use tag edit to change it",
+                    name
+                ))
+                .await
+            }
+            Ok(_) => {
+                tags.insert(c.room_id(), &name);
+                c.react("✅").await
+            }
+            Err(err) => c.reply(&format!("Couldn't save the tag: {}", err)).await,
+        };
+    }
+
+    /// change what a tag sends
+    #[bleh::command]
+    pub async fn edit(
+        c: BaseContext,
+        State(p): State<SqlitePool>,
+        /// the tag to change
+        Named(name): Named<String, "name">,
+        /// what to send instead, in markdown
+        Named(Remainder(content)): Named<Remainder, "content">,
+    ) {
+        let content = content.trim();
+
+        if content.is_empty() {
+            let _ = c.reply("A tag needs something to send").await;
+            return;
+        }
+
+        if !can_change(&c, &p, &name).await {
+            return;
+        }
+
+        let room_id = c.room_id().as_str();
+        let result = sqlx::query!(
+            "UPDATE tags SET content = ?3 WHERE room_id = ?1 AND name = ?2",
+            room_id,
+            name,
+            content,
+        )
+        .execute(&p)
+        .await;
+
+        let _ = match result {
+            Ok(_) => c.react("✅").await,
+            Err(err) => c.reply(&format!("Couldn't save the tag: {}", err)).await,
+        };
+    }
+
+    /// remove a tag from this room
+    #[bleh::command]
+    pub async fn remove(
+        c: BaseContext,
+        State(p): State<SqlitePool>,
+        State(tags): State<Arc<TagNames>>,
+        /// the tag to remove
+        Named(name): Named<String, "name">,
+    ) {
+        if !can_change(&c, &p, &name).await {
+            return;
+        }
+
+        let room_id = c.room_id().as_str();
+        let result = sqlx::query!(
+            "DELETE FROM tags WHERE room_id = ?1 AND name = ?2",
+            room_id,
+            name,
+        )
+        .execute(&p)
+        .await;
+
+        let _ = match result {
+            Ok(_) => {
+                tags.remove(c.room_id(), &name);
+                c.react("✅").await
+            }
+            Err(err) => c.reply(&format!("Couldn't remove the tag: {}", err)).await,
+        };
+    }
+
+    /// list the tags in this room
+    #[bleh::command]
+    pub async fn list(c: BaseContext, State(p): State<SqlitePool>) {
+        let room_id = c.room_id().as_str();
+        let rows = sqlx::query!(
+            "SELECT name FROM tags WHERE room_id = ?1 ORDER BY name",
+            room_id,
+        )
+        .fetch_all(&p)
+        .await;
+
+        let _ = match rows {
+            Ok(rows) if rows.is_empty() => c.reply("There are no tags here").await,
+            Ok(rows) => {
+                let prefix = &c.config().prefix;
+                let names = rows
+                    .iter()
+                    .map(|row| format!("{}{}", prefix, row.name))
+                    .collect::<Vec<_>>();
+
+                c.reply(&names.join(", ")).await
+            }
+            Err(err) => c.reply(&format!("Couldn't load the tags: {}", err)).await,
+        };
+    }
+}
+
+/// The root group's fallback, sending the tag named by the first word so tags
+/// work like commands. Anything that isn't a tag in `tags` is ignored, as it
+/// would be without a fallback, and help lists the room's tags.
+pub fn fallback(tags: Arc<TagNames>) -> ErasedCommand<BaseContext> {
+    cmd(
+        |c: BaseContext,
+         State(p): State<SqlitePool>,
+         Named(Remainder(input)): Named<Remainder, "tag">| async move {
+            let name = match input.split_whitespace().next() {
+                Some(name) => name,
+                None => return,
+            };
+
+            match content(&p, c.room_id().as_str(), name).await {
+                Ok(Some(content)) => {
+                    let _ = c.send_html(&content, &render(&content)).await;
+                }
+                Ok(None) => {}
+                Err(err) => tracing::warn!(?err, name, "Failed to load tag"),
+            }
+        },
+    )
+    .with_description("send a tag added with tag add")
+    .with_param_docs(&[None, Some("the name of the tag")])
+    .with_names(tags)
+}
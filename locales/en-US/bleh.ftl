@@ -0,0 +1,62 @@
+## Messages sent by the framework itself. Bots can override any of these, and
+## translate command and group descriptions with `command-<path>` and
+## `group-<path>` messages, the path joined with `-`, such as
+##
+##     command-commands-enable = enable a command or group in this room
+##         .long = Rules apply to a command and everything under it.
+
+error-parse = Couldn't parse `{ $param }`: { $error }
+error-resolve = Couldn't resolve `{ $param }`: { $error }
+error-no-match = None of the forms of this command matched:
+error-no-match-form = ({ $usage }): { $error }
+error-step = Step { $step } (`{ $command }`) failed: { $error }
+error-unknown-step = Step { $step } (`{ $command }`) isn't a command
+error-chain-too-long = Only { $max } commands can be chained in one message
+error-alias-too-deep = `{ $name }` uses aliases more than { $max } deep
+
+help-not-found = Couldn't find { $path }
+help-label-command = Command:
+help-label-group = Group:
+help-label-usage = Usage:
+help-label-params = Params:
+help-label-no-params = No parameters
+help-label-aliases = Aliases:
+help-label-description = Description:
+help-label-examples = Examples:
+help-label-category = Category:
+help-label-deprecated = Deprecated:
+help-label-since = Since
+help-label-subcommands = Subcommands:
+help-label-commands = Commands:
+help-label-no-subcommands = No subcommands
+
+policy-power-level = You need a power level of at least { $level } to do that
+policy-power-level-failed = Couldn't check your power level: { $error }
+policy-space-power-level = You need a power level of at least { $level } in { $space } to do that
+policy-no-such-command = There's no command or group `{ $path }`
+policy-no-space = This room isn't part of a space
+policy-spaces-failed = Couldn't find this room's spaces: { $error }
+policy-update-failed = Couldn't update the policy: { $error }
+policy-load-failed = Couldn't load the policy: { $error }
+policy-all-enabled = Every command is enabled here
+policy-everything = everything
+policy-scope-room = this room
+policy-scope-space = space { $space }
+policy-rule-enabled = enabled { $path } ({ $scope })
+policy-rule-disabled = disabled { $path } ({ $scope })
+
+locale-current = The locale here is { $locale }
+locale-available = Available locales: { $available }
+locale-unavailable = { $locale } isn't available, try one of: { $available }
+locale-save-failed = Couldn't save the locale: { $error }
+
+alias-invalid-name = `{ $name }` can't be used as an alias, names are a single word
+alias-is-command = `{ $name }` is already a command
+alias-empty = An alias needs something to stand for
+alias-too-long = An alias can stand for at most { $max } commands
+alias-not-found = There's no alias called `{ $name }`
+alias-save-failed = Couldn't save the alias: { $error }
+alias-load-failed = Couldn't load aliases: { $error }
+alias-none = There are no aliases here
+alias-entry-room = { $name } → { $expansion }
+alias-entry-user = { $name } → { $expansion } (yours)
@@ -0,0 +1,418 @@
+//! Attribute macros for declaring commands, re-exported from `bleh`.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    Attribute, FnArg, Ident, Item, ItemFn, ItemMod, Lit, LitBool, LitStr, Meta, Token, Type,
+};
+
+/// A single `key = value` or `flag` argument to one of the attributes
+enum Arg {
+    Str(Ident, LitStr),
+    Bool(Ident, LitBool),
+    List(Ident, Vec<LitStr>),
+    Type(Ident, Type),
+    Types(Ident, Vec<Type>),
+}
+
+impl Arg {
+    fn key(&self) -> &Ident {
+        match self {
+            Arg::Str(k, _)
+            | Arg::Bool(k, _)
+            | Arg::List(k, _)
+            | Arg::Type(k, _)
+            | Arg::Types(k, _) => k,
+        }
+    }
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let key: Ident = input.parse()?;
+
+        if input.peek(syn::token::Paren) {
+            let content;
+            syn::parenthesized!(content in input);
+            let types = Punctuated::<Type, Token![,]>::parse_terminated(&content)?;
+            return Ok(Arg::Types(key, types.into_iter().collect()));
+        }
+
+        if !input.peek(Token![=]) {
+            return Ok(Arg::Bool(key.clone(), LitBool::new(true, key.span())));
+        }
+
+        input.parse::<Token![=]>()?;
+
+        if input.peek(LitStr) {
+            Ok(Arg::Str(key, input.parse()?))
+        } else if input.peek(LitBool) {
+            Ok(Arg::Bool(key, input.parse()?))
+        } else if input.peek(syn::token::Bracket) {
+            let content;
+            syn::bracketed!(content in input);
+            let items = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+            Ok(Arg::List(key, items.into_iter().collect()))
+        } else {
+            Ok(Arg::Type(key, input.parse()?))
+        }
+    }
+}
+
+struct Args(Vec<Arg>);
+
+impl Parse for Args {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let args = Punctuated::<Arg, Token![,]>::parse_terminated(input)?;
+        Ok(Args(args.into_iter().collect()))
+    }
+}
+
+impl Args {
+    /// Error on any argument not in `allowed`
+    fn check(&self, allowed: &[&str]) -> syn::Result<()> {
+        for arg in &self.0 {
+            if !allowed.iter().any(|a| arg.key() == a) {
+                return Err(syn::Error::new(
+                    arg.key().span(),
+                    format!("unknown argument, expected one of: {}", allowed.join(", ")),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find(&self, key: &str) -> Option<&Arg> {
+        self.0.iter().find(|a| a.key() == key)
+    }
+
+    fn str(&self, key: &str) -> syn::Result<Option<String>> {
+        match self.find(key) {
+            Some(Arg::Str(_, s)) => Ok(Some(s.value())),
+            Some(a) => Err(syn::Error::new(a.key().span(), "expected a string")),
+            None => Ok(None),
+        }
+    }
+
+    fn bool(&self, key: &str) -> syn::Result<Option<bool>> {
+        match self.find(key) {
+            Some(Arg::Bool(_, b)) => Ok(Some(b.value)),
+            Some(a) => Err(syn::Error::new(a.key().span(), "expected a bool")),
+            None => Ok(None),
+        }
+    }
+
+    fn list(&self, key: &str) -> syn::Result<Vec<String>> {
+        match self.find(key) {
+            Some(Arg::List(_, l)) => Ok(l.iter().map(LitStr::value).collect()),
+            Some(Arg::Str(_, s)) => Ok(vec![s.value()]),
+            Some(a) => Err(syn::Error::new(
+                a.key().span(),
+                "expected a list of strings",
+            )),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn types(&self, key: &str) -> syn::Result<Vec<Type>> {
+        match self.find(key) {
+            Some(Arg::Types(_, t)) => Ok(t.clone()),
+            Some(a) => Err(syn::Error::new(a.key().span(), "expected a list of types")),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn ty(&self, key: &str) -> syn::Result<Option<Type>> {
+        match self.find(key) {
+            Some(Arg::Type(_, t)) => Ok(Some(t.clone())),
+            Some(a) => Err(syn::Error::new(a.key().span(), "expected a type")),
+            None => Ok(None),
+        }
+    }
+}
+
+fn is_doc(attr: &Attribute) -> bool {
+    attr.path.is_ident("doc")
+}
+
+/// Join `///` comments into a single string, returns `None` if there are none
+fn doc_string(attrs: &[Attribute]) -> Option<String> {
+    let lines = attrs
+        .iter()
+        .filter(|a| is_doc(a))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(Meta::NameValue(nv)) => match nv.lit {
+                Lit::Str(s) => Some(s.value().trim().to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let doc = lines.join("\n").trim().to_owned();
+
+    if doc.is_empty() {
+        None
+    } else {
+        Some(doc)
+    }
+}
+
+/// Whether an attribute is `#[name]` or `#[some::path::name(...)]`
+fn is_attr(attr: &Attribute, name: &str) -> bool {
+    attr.path.segments.last().map_or(false, |s| s.ident == name)
+}
+
+/// The context type of a command, taken from its first parameter
+fn context_type(func: &ItemFn) -> syn::Result<Type> {
+    match func.sig.inputs.first() {
+        Some(FnArg::Typed(pat)) => Ok((*pat.ty).clone()),
+        _ => Err(syn::Error::new(
+            func.sig.span(),
+            "commands take their context as the first parameter",
+        )),
+    }
+}
+
+fn option_str(s: Option<&str>) -> proc_macro2::TokenStream {
+    match s {
+        Some(s) => quote!(::core::option::Option::Some(#s)),
+        None => quote!(::core::option::Option::None),
+    }
+}
+
+fn command_impl(args: Args, mut func: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    args.check(&[
+        "name",
+        "description",
+        "aliases",
+        "typing",
+        "overload",
+        "examples",
+        "category",
+        "since",
+        "deprecated",
+    ])?;
+
+    if func.sig.asyncness.is_none() {
+        return Err(syn::Error::new(
+            func.sig.fn_token.span,
+            "commands must be `async fn`s",
+        ));
+    }
+
+    let context = context_type(&func)?;
+    let ident = func.sig.ident.clone();
+    let vis = func.vis.clone();
+
+    let name = args
+        .str("name")?
+        .unwrap_or_else(|| ident.to_string().trim_start_matches("r#").to_owned());
+    // the first paragraph of the doc comment is the description, the rest is
+    // the long description
+    let (description, long_description) = match args.str("description")? {
+        Some(d) => (Some(d), None),
+        None => match doc_string(&func.attrs) {
+            Some(doc) => match doc.split_once("\n\n") {
+                Some((short, long)) => (Some(short.to_owned()), Some(long.trim().to_owned())),
+                None => (Some(doc), None),
+            },
+            None => (None, None),
+        },
+    };
+    let aliases = args.list("aliases")?;
+    let examples = args.list("examples")?;
+
+    // doc comments aren't allowed on parameters once the macro has run, so
+    // strip them out after collecting them
+    let mut param_docs = Vec::new();
+    for input in func.sig.inputs.iter_mut().skip(1) {
+        if let FnArg::Typed(pat) = input {
+            param_docs.push(option_str(doc_string(&pat.attrs).as_deref()));
+            pat.attrs.retain(|a| !is_doc(a));
+        }
+    }
+
+    let outer_attrs = func
+        .attrs
+        .iter()
+        .filter(|a| is_doc(a) || is_attr(a, "allow") || is_attr(a, "cfg"))
+        .cloned()
+        .collect::<Vec<_>>();
+    func.attrs.retain(|a| !is_doc(a));
+    func.vis = syn::Visibility::Inherited;
+
+    let description = match description {
+        Some(d) => quote!(.with_description(#d)),
+        None => quote!(),
+    };
+    let typing = match args.bool("typing")? {
+        Some(t) => quote!(.with_typing(#t)),
+        None => quote!(),
+    };
+    let long_description = match long_description {
+        Some(d) => quote!(.long_description(#d)),
+        None => quote!(),
+    };
+    let optional = |key: &str, method: proc_macro2::TokenStream| -> syn::Result<_> {
+        Ok(match args.str(key)? {
+            Some(v) => quote!(.#method(#v)),
+            None => quote!(),
+        })
+    };
+    let category = optional("category", quote!(category))?;
+    let since = optional("since", quote!(since))?;
+    let deprecated = optional("deprecated", quote!(deprecated))?;
+    let overload = match args.bool("overload")? {
+        Some(true) => quote!(.as_overload()),
+        _ => quote!(),
+    };
+
+    Ok(quote! {
+        #(#outer_attrs)*
+        #vis fn #ident() -> ::bleh::framework::commands::CommandDef<#context> {
+            #func
+
+            ::bleh::framework::commands::CommandDef::new(
+                #name,
+                ::bleh::framework::commands::cmd(#ident)
+                    #description
+                    #long_description
+                    .with_aliases(&[#(#aliases),*])
+                    .with_param_docs(&[#(#param_docs),*])
+                    #(.example(#examples))*
+                    #category
+                    #since
+                    #deprecated
+                    #typing,
+            )
+            #overload
+        }
+    })
+}
+
+fn group_impl(args: Args, mut module: ItemMod) -> syn::Result<proc_macro2::TokenStream> {
+    args.check(&["name", "description", "context", "params", "help"])?;
+
+    let items = match &mut module.content {
+        Some((_, items)) => items,
+        None => {
+            return Err(syn::Error::new(
+                module.span(),
+                "groups must be inline modules",
+            ))
+        }
+    };
+
+    let mut context = args.ty("context")?;
+    let mut adds = args
+        .types("params")?
+        .into_iter()
+        .map(|ty| quote!(let builder = builder.with_param::<#ty>();))
+        .collect::<Vec<_>>();
+
+    if args.bool("help")? == Some(true) {
+        adds.push(quote!(let builder = builder.with_help();));
+    }
+
+    for item in items.iter() {
+        match item {
+            Item::Fn(func) if func.attrs.iter().any(|a| is_attr(a, "command")) => {
+                if context.is_none() {
+                    context = Some(context_type(func)?);
+                }
+
+                let ident = &func.sig.ident;
+                adds.push(quote!(let builder = builder.add(#ident());));
+            }
+            Item::Mod(sub) if sub.attrs.iter().any(|a| is_attr(a, "group")) => {
+                let ident = &sub.ident;
+                adds.push(quote!(let builder = builder.add_group(#ident::make_group());));
+            }
+            _ => {}
+        }
+    }
+
+    let context = context.ok_or_else(|| {
+        syn::Error::new(
+            module.ident.span(),
+            "can't infer the context type of a group without commands, add `context = ...`",
+        )
+    })?;
+
+    let name = args
+        .str("name")?
+        .unwrap_or_else(|| module.ident.to_string().trim_start_matches("r#").to_owned());
+    let description = match args
+        .str("description")?
+        .or_else(|| doc_string(&module.attrs))
+    {
+        Some(d) => quote!(.with_description(#d)),
+        None => quote!(),
+    };
+
+    items.push(syn::parse_quote! {
+        /// The group declared by this module, generated by `#[bleh::group]`
+        pub fn make_group() -> ::bleh::framework::commands::GroupDef<#context> {
+            let builder = ::bleh::framework::commands::GroupBuilder::new();
+
+            #(#adds)*
+
+            ::bleh::framework::commands::GroupDef::new(#name, builder #description)
+        }
+    });
+
+    Ok(quote!(#module))
+}
+
+/// Declare a command from an `async fn` whose first parameter is the context.
+///
+/// The function is replaced by one of the same name returning a
+/// `CommandDef`. The first paragraph of the function's doc comment is used as
+/// the description and the rest as the long description, doc comments on
+/// parameters document them.
+///
+/// ```ignore
+/// /// Say hi
+/// #[bleh::command(aliases = ["hello"])]
+/// async fn hi(c: BaseContext, /// who to greet
+///                             Named(who): Named<String, "who">) { ... }
+/// ```
+///
+/// Arguments: `name` (defaults to the function name), `description`,
+/// `aliases`, `examples`, `category`, `since`, `deprecated`, `typing` and
+/// `overload`, which adds the function as another signature of an earlier
+/// command with the same `name` in the group. Each signature is tried in order
+/// until one parses.
+#[proc_macro_attribute]
+pub fn command(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+    let func = parse_macro_input!(item as ItemFn);
+
+    command_impl(args, func)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Collect the `#[command]`s and nested `#[group]`s of an inline module into a
+/// group, adding a `make_group()` function returning a `GroupDef` to it.
+///
+/// Arguments: `name` (defaults to the module name), `description` (defaults
+/// to the module's doc comment), `params(T, ...)`, parameters taken before the
+/// name of a subcommand which subcommands get with `GroupArg<T>`, `help`,
+/// which adds the framework's `help` command, and `context`, which is only
+/// needed when the module has no commands of its own.
+#[proc_macro_attribute]
+pub fn group(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(args as Args);
+    let module = parse_macro_input!(item as ItemMod);
+
+    group_impl(args, module)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,461 @@
+//! Shortcuts defined from chat, such as
+//! `!alias add standup recur "FREQ=DAILY;BYHOUR=9" standup time!`.
+//!
+//! The dispatcher replaces an alias with its expansion before looking the
+//! command up. `$1` to `$9` in the expansion are replaced with the words given
+//! after the alias and `$@` with all of them, and if it uses neither they're
+//! appended to it. An expansion can chain several commands with `;` and `|`
+//! when quoted, making it a macro, and can use other aliases up to
+//! `BotConfig::max_alias_depth` deep. Aliases chosen by a user win over the
+//! room's, and commands win over both.
+
+use std::{collections::HashMap, sync::Arc, sync::Mutex};
+
+use matrix_sdk::ruma::{RoomId, UserId};
+
+use crate::framework::{
+    chain,
+    commands::{cmd, GroupBuilder, Named, Remainder},
+    context::{Context, ContextActions},
+    policy::{self, MODERATOR_LEVEL},
+    registry,
+    state::State,
+    transport,
+};
+
+/// Who an alias was defined for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AliasScope {
+    Room(Box<RoomId>),
+    User(Box<UserId>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alias {
+    pub scope: AliasScope,
+    pub name: String,
+    /// What the alias stands for, without the prefix
+    pub expansion: String,
+    pub created_by: Box<UserId>,
+}
+
+/// Where aliases are kept
+#[async_trait::async_trait]
+pub trait AliasStore: Send + Sync + 'static {
+    async fn get(&self, scope: &AliasScope, name: &str) -> transport::Result<Option<Alias>>;
+
+    /// Every alias defined for any of `scopes`
+    async fn list(&self, scopes: &[AliasScope]) -> transport::Result<Vec<Alias>>;
+
+    /// Add an alias, replacing one with the same name in the same scope
+    async fn set(&self, alias: Alias) -> transport::Result<()>;
+
+    /// Returns whether there was an alias to remove
+    async fn remove(&self, scope: &AliasScope, name: &str) -> transport::Result<bool>;
+}
+
+/// An alias store that forgets everything when the bot stops
+#[derive(Default)]
+pub struct MemoryAliasStore {
+    aliases: Mutex<HashMap<(AliasScope, String), Alias>>,
+}
+
+impl MemoryAliasStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl AliasStore for MemoryAliasStore {
+    async fn get(&self, scope: &AliasScope, name: &str) -> transport::Result<Option<Alias>> {
+        let aliases = self.aliases.lock().unwrap();
+
+        Ok(aliases.get(&(scope.clone(), name.to_owned())).cloned())
+    }
+
+    async fn list(&self, scopes: &[AliasScope]) -> transport::Result<Vec<Alias>> {
+        let aliases = self.aliases.lock().unwrap();
+
+        Ok(aliases
+            .values()
+            .filter(|a| scopes.contains(&a.scope))
+            .cloned()
+            .collect())
+    }
+
+    async fn set(&self, alias: Alias) -> transport::Result<()> {
+        self.aliases
+            .lock()
+            .unwrap()
+            .insert((alias.scope.clone(), alias.name.clone()), alias);
+
+        Ok(())
+    }
+
+    async fn remove(&self, scope: &AliasScope, name: &str) -> transport::Result<bool> {
+        let mut aliases = self.aliases.lock().unwrap();
+
+        Ok(aliases.remove(&(scope.clone(), name.to_owned())).is_some())
+    }
+}
+
+/// The alias `author` would get for `name` in `room_id`, theirs or the room's
+pub async fn find(
+    store: &dyn AliasStore,
+    room_id: &RoomId,
+    author: &UserId,
+    name: &str,
+) -> transport::Result<Option<Alias>> {
+    if let Some(alias) = store
+        .get(&AliasScope::User(author.to_owned()), name)
+        .await?
+    {
+        return Ok(Some(alias));
+    }
+
+    store.get(&AliasScope::Room(room_id.to_owned()), name).await
+}
+
+/// The words of `args`, quoted strings keeping their quotes
+fn words(mut args: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+
+    loop {
+        args = args.trim_start();
+        if args.is_empty() {
+            return words;
+        }
+
+        let end = match args.strip_prefix('"') {
+            Some(rest) => rest.find('"').map_or(args.len(), |i| i + 2),
+            None => args.find(char::is_whitespace).unwrap_or(args.len()),
+        };
+
+        words.push(&args[..end]);
+        args = &args[end..];
+    }
+}
+
+/// Fill in the placeholders of an expansion with the arguments given to the
+/// alias
+pub fn substitute(expansion: &str, args: &str) -> String {
+    let args = args.trim();
+    let words = words(args);
+
+    let mut out = String::new();
+    let mut used = false;
+    let mut chars = expansion.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c == '$' {
+            match chars.peek().copied() {
+                Some('@') => {
+                    chars.next();
+                    out.push_str(args);
+                    used = true;
+                    continue;
+                }
+                Some(d @ '1'..='9') => {
+                    chars.next();
+                    let n = d as usize - '1' as usize;
+                    out.push_str(words.get(n).copied().unwrap_or_default());
+                    used = true;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+
+        out.push(c);
+    }
+
+    if !used && !args.is_empty() {
+        out.push(' ');
+        out.push_str(args);
+    }
+
+    out
+}
+
+/// An expansion given as a single quoted string, as needed to chain commands
+/// in one, is stored without the quotes
+fn unquote(expansion: &str) -> &str {
+    let expansion = expansion.trim();
+
+    match expansion
+        .strip_prefix('"')
+        .and_then(|e| e.strip_suffix('"'))
+    {
+        Some(inner) if !inner.contains('"') => inner,
+        _ => expansion,
+    }
+}
+
+async fn add_alias<C: Context + Sync>(
+    c: &C,
+    store: &dyn AliasStore,
+    scope: AliasScope,
+    name: String,
+    expansion: String,
+) {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        let _ = c
+            .reply(&c.tr("alias-invalid-name", &[("name", name.into())]))
+            .await;
+        return;
+    }
+
+    // disabled commands are left out of `c.root()` but can come back
+    if registry::registered_root(c).get(&name).is_some() {
+        let _ = c
+            .reply(&c.tr("alias-is-command", &[("name", name.into())]))
+            .await;
+        return;
+    }
+
+    let prefix = &c.config().prefix;
+    let expansion = unquote(&expansion);
+    let expansion = expansion.strip_prefix(prefix.as_str()).unwrap_or(expansion);
+
+    if expansion.is_empty() {
+        let _ = c.reply(&c.tr("alias-empty", &[])).await;
+        return;
+    }
+
+    let max = c.config().max_chain_length;
+    let commands = chain::split(&format!("{}{}", prefix, expansion), prefix)
+        .map_or(1, |chain| chain.iter().map(Vec::len).sum());
+
+    if commands > max {
+        let _ = c
+            .reply(&c.tr("alias-too-long", &[("max", max.into())]))
+            .await;
+        return;
+    }
+
+    // only whoever made a room's alias or a moderator can replace it
+    if let (AliasScope::Room(_), Ok(Some(existing))) = (&scope, store.get(&scope, &name).await) {
+        if existing.created_by.as_ref() != c.author()
+            && !policy::require_power_level(c, MODERATOR_LEVEL).await
+        {
+            return;
+        }
+    }
+
+    let alias = Alias {
+        scope,
+        name,
+        expansion: expansion.to_owned(),
+        created_by: c.author().to_owned(),
+    };
+
+    match store.set(alias).await {
+        Ok(()) => {
+            let _ = c.react("✅").await;
+        }
+        Err(err) => {
+            let _ = c
+                .reply(&c.tr("alias-save-failed", &[("error", err.to_string().into())]))
+                .await;
+        }
+    }
+}
+
+async fn remove_alias<C: Context + Sync>(
+    c: &C,
+    store: &dyn AliasStore,
+    scope: AliasScope,
+    name: String,
+) {
+    let existing = match store.get(&scope, &name).await {
+        Ok(Some(existing)) => existing,
+        Ok(None) => {
+            let _ = c
+                .reply(&c.tr("alias-not-found", &[("name", name.into())]))
+                .await;
+            return;
+        }
+        Err(err) => {
+            let _ = c
+                .reply(&c.tr("alias-load-failed", &[("error", err.to_string().into())]))
+                .await;
+            return;
+        }
+    };
+
+    if existing.created_by.as_ref() != c.author()
+        && !policy::require_power_level(c, MODERATOR_LEVEL).await
+    {
+        return;
+    }
+
+    match store.remove(&scope, &name).await {
+        Ok(_) => {
+            let _ = c.react("✅").await;
+        }
+        Err(err) => {
+            let _ = c
+                .reply(&c.tr("alias-save-failed", &[("error", err.to_string().into())]))
+                .await;
+        }
+    }
+}
+
+/// `add`, `remove` and `list` commands for the aliases of the room they're
+/// used in, and `me add` and `me remove` for the author's own. Add these to
+/// the command tree under a name such as `alias`. They need
+/// `DispatcherBuilder::with_aliases` to have been used.
+pub fn commands<C: Context + Clone + Send + Sync + 'static>() -> GroupBuilder<C> {
+    type Store = State<Arc<dyn AliasStore>>;
+    type Name = Named<String, "name">;
+    type Expansion = Named<Remainder, "expansion">;
+
+    const ADD_DOCS: &[Option<&str>] = &[
+        None,
+        Some("the name to invoke the alias with"),
+        Some("the command it stands for, quoted to chain several"),
+    ];
+
+    GroupBuilder::new()
+        .with_description("define shortcuts for commands")
+        .command(
+            "add",
+            cmd(
+                |c: C, State(store): Store, Named(name): Name, Named(Remainder(e)): Expansion| async move {
+                    let scope = AliasScope::Room(c.room_id().to_owned());
+                    add_alias(&c, store.as_ref(), scope, name, e).await;
+                },
+            )
+            .with_description("add an alias for everyone in this room")
+            .with_param_docs(ADD_DOCS)
+            .example("!alias add standup recur \"FREQ=DAILY;BYHOUR=9\" standup time!")
+            .example("!alias add greet \"hi; !quote $@\""),
+        )
+        .command(
+            "remove",
+            cmd(|c: C, State(store): Store, Named(name): Name| async move {
+                let scope = AliasScope::Room(c.room_id().to_owned());
+                remove_alias(&c, store.as_ref(), scope, name).await;
+            })
+            .with_description("remove an alias from this room"),
+        )
+        .command(
+            "list",
+            cmd(|c: C, State(store): Store| async move {
+                let scopes = [
+                    AliasScope::User(c.author().to_owned()),
+                    AliasScope::Room(c.room_id().to_owned()),
+                ];
+
+                let mut aliases = match store.list(&scopes).await {
+                    Ok(aliases) => aliases,
+                    Err(err) => {
+                        let _ = c
+                            .reply(&c.tr("alias-load-failed", &[("error", err.to_string().into())]))
+                            .await;
+                        return;
+                    }
+                };
+
+                if aliases.is_empty() {
+                    let _ = c.reply(&c.tr("alias-none", &[])).await;
+                    return;
+                }
+
+                aliases.sort_by(|a, b| a.name.cmp(&b.name));
+
+                let lines = aliases
+                    .iter()
+                    .map(|a| {
+                        let id = match a.scope {
+                            AliasScope::Room(_) => "alias-entry-room",
+                            AliasScope::User(_) => "alias-entry-user",
+                        };
+
+                        c.tr(
+                            id,
+                            &[
+                                ("name", a.name.as_str().into()),
+                                ("expansion", a.expansion.as_str().into()),
+                            ],
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                let _ = c.reply(&lines.join("\n")).await;
+            })
+            .with_description("list the aliases you can use here"),
+        )
+        .group(
+            "me",
+            GroupBuilder::new()
+                .with_description("manage aliases only you can use")
+                .command(
+                    "add",
+                    cmd(
+                        |c: C, State(store): Store, Named(name): Name, Named(Remainder(e)): Expansion| async move {
+                            let scope = AliasScope::User(c.author().to_owned());
+                            add_alias(&c, store.as_ref(), scope, name, e).await;
+                        },
+                    )
+                    .with_description("add an alias that works for you in every room")
+                    .with_param_docs(ADD_DOCS),
+                )
+                .command(
+                    "remove",
+                    cmd(|c: C, State(store): Store, Named(name): Name| async move {
+                        let scope = AliasScope::User(c.author().to_owned());
+                        remove_alias(&c, store.as_ref(), scope, name).await;
+                    })
+                    .with_description("remove one of your aliases"),
+                ),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{substitute, unquote};
+
+    #[test]
+    fn numbered_placeholders() {
+        assert_eq!(substitute("say $2 then $1", "a b c"), "say b then a");
+    }
+
+    #[test]
+    fn missing_arguments_are_empty() {
+        assert_eq!(substitute("say [$1] [$3]", "a"), "say [a] []");
+    }
+
+    #[test]
+    fn all_arguments() {
+        assert_eq!(substitute("quote $@ !", "  a  b "), "quote a  b !");
+    }
+
+    #[test]
+    fn arguments_are_appended_without_placeholders() {
+        assert_eq!(
+            substitute("recur FREQ=DAILY", "standup"),
+            "recur FREQ=DAILY standup"
+        );
+        assert_eq!(substitute("ping", ""), "ping");
+    }
+
+    #[test]
+    fn quoted_arguments_are_one_word() {
+        assert_eq!(substitute("say $1|$2", "\"a b\" c"), "say \"a b\"|c");
+    }
+
+    #[test]
+    fn other_dollars_are_kept() {
+        assert_eq!(substitute("cost $0 $x $", ""), "cost $0 $x $");
+        assert_eq!(substitute("cost $0", "5"), "cost $0 5");
+    }
+
+    #[test]
+    fn unquoting() {
+        assert_eq!(unquote(" \"hi; !quote $@\" "), "hi; !quote $@");
+        assert_eq!(unquote("say \"a\""), "say \"a\"");
+        assert_eq!(unquote("\"a\" \"b\""), "\"a\" \"b\"");
+    }
+}
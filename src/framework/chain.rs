@@ -0,0 +1,304 @@
+//! Several commands in one message.
+//!
+//! Commands separated by `;` or newlines run one after the other, and
+//! `!a | !b` runs `a` and appends what it would have sent to `b`'s arguments,
+//! usually its `Remainder`. A separator only counts when the text after it
+//! starts with the prefix and isn't inside a quoted string, so
+//! `!say a; b` still says `a; b`, and lines that don't start with the prefix
+//! continue the command on the line before them.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use matrix_sdk::ruma::{
+    events::{
+        room::message::{MessageType, RoomMessageEventContent},
+        MessageEvent,
+    },
+    EventId, MxcUri, RoomAliasId, RoomId, UserId,
+};
+use mime::Mime;
+
+use crate::framework::transport::{Result, Transport};
+
+/// Commands whose output feeds the next, each including the prefix
+pub type Pipeline<'a> = Vec<&'a str>;
+
+/// Split a message into the pipelines to run in order, returns `None` if the
+/// message doesn't start with the prefix
+pub fn split<'a>(body: &'a str, prefix: &str) -> Option<Vec<Pipeline<'a>>> {
+    if !body.starts_with(prefix) {
+        return None;
+    }
+
+    let mut chain = Vec::new();
+    let mut pipeline = Vec::new();
+    let mut start = 0;
+    // the end of the quoted string being skipped over, if any
+    let mut quoted_until = 0;
+
+    for (i, ch) in body.char_indices() {
+        if i < quoted_until {
+            continue;
+        }
+
+        match ch {
+            // like the `String` parameter, a quote only starts a string at the
+            // start of a word and when there's another to close it, so the
+            // `"` in `!say 5" tall; !hi` doesn't hide the separator
+            '"' if body[..i].ends_with(char::is_whitespace) => {
+                if let Some(len) = body[i + 1..].find('"') {
+                    quoted_until = i + 1 + len + 1;
+                }
+            }
+            ';' | '|' | '\n' => {
+                let next = body[i + 1..].trim_start_matches(|c: char| c == ' ' || c == '\t');
+
+                if !next.starts_with(prefix) {
+                    continue;
+                }
+
+                pipeline.push(body[start..i].trim());
+                if ch != '|' {
+                    chain.push(std::mem::take(&mut pipeline));
+                }
+
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+
+    pipeline.push(body[start..].trim());
+    chain.push(pipeline);
+
+    Some(chain)
+}
+
+/// A transport that keeps the messages a command sends instead of sending
+/// them, for feeding into the next command of a pipeline. Edits and
+/// redactions of kept messages apply to them, everything else goes to the
+/// room.
+pub struct CaptureTransport {
+    inner: Arc<dyn Transport>,
+    next_id: AtomicU64,
+    /// Bodies of the kept messages, in the order they were sent
+    messages: Mutex<Vec<(Box<EventId>, String)>>,
+}
+
+impl CaptureTransport {
+    pub fn new(inner: Arc<dyn Transport>) -> Self {
+        Self {
+            inner,
+            next_id: AtomicU64::new(0),
+            messages: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Everything the command sent, one message per line
+    pub fn output(&self) -> String {
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, body)| body.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn is_captured(&self, event_id: &EventId) -> bool {
+        self.messages
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|(id, _)| id.as_ref() == event_id)
+    }
+
+    fn new_event_id(&self) -> Result<Box<EventId>> {
+        let n = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        Ok(EventId::parse(format!("$pipe{}:localhost", n))?)
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for CaptureTransport {
+    async fn send_event(
+        &self,
+        event_type: &str,
+        content: serde_json::Value,
+    ) -> Result<Box<EventId>> {
+        let relates_to = content["m.relates_to"]["event_id"]
+            .as_str()
+            .and_then(|id| EventId::parse(id).ok());
+
+        match (event_type, relates_to) {
+            ("m.room.message", Some(target))
+                if content["m.relates_to"]["rel_type"] == "m.replace" =>
+            {
+                let body = content["m.new_content"]["body"]
+                    .as_str()
+                    .unwrap_or_default();
+
+                let mut messages = self.messages.lock().unwrap();
+                if let Some((_, old)) = messages.iter_mut().find(|(id, _)| *id == target) {
+                    *old = body.to_owned();
+                    return self.new_event_id();
+                }
+            }
+            ("m.room.message", _) => {
+                let event_id = self.new_event_id()?;
+                let body = content["body"].as_str().unwrap_or_default().to_owned();
+
+                self.messages.lock().unwrap().push((event_id.clone(), body));
+                return Ok(event_id);
+            }
+            // reactions to kept messages have nowhere to go
+            (_, Some(target)) if self.is_captured(&target) => return self.new_event_id(),
+            _ => {}
+        }
+
+        self.inner.send_event(event_type, content).await
+    }
+
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> Result<()> {
+        if self.is_captured(event_id) {
+            self.messages
+                .lock()
+                .unwrap()
+                .retain(|(id, _)| id.as_ref() != event_id);
+            return Ok(());
+        }
+
+        self.inner.redact(event_id, reason).await
+    }
+
+    async fn typing(&self, timeout: Option<Duration>) -> Result<()> {
+        self.inner.typing(timeout).await
+    }
+
+    async fn read_receipt(&self, event_id: &EventId) -> Result<()> {
+        self.inner.read_receipt(event_id).await
+    }
+
+    async fn fetch_message(
+        &self,
+        event_id: &EventId,
+    ) -> Result<Option<MessageEvent<RoomMessageEventContent>>> {
+        if self.is_captured(event_id) {
+            return Ok(None);
+        }
+
+        self.inner.fetch_message(event_id).await
+    }
+
+    async fn upload(&self, mimetype: &Mime, data: &[u8]) -> Result<Box<MxcUri>> {
+        self.inner.upload(mimetype, data).await
+    }
+
+    async fn download(&self, msgtype: &MessageType) -> Result<Option<Vec<u8>>> {
+        self.inner.download(msgtype).await
+    }
+
+    async fn resolve_room_alias(&self, alias: &RoomAliasId) -> Result<Box<RoomId>> {
+        self.inner.resolve_room_alias(alias).await
+    }
+
+    async fn profile(&self, user_id: &UserId) -> Result<(Option<String>, Option<Box<MxcUri>>)> {
+        self.inner.profile(user_id).await
+    }
+
+    async fn power_level(&self, user_id: &UserId) -> Result<i64> {
+        self.inner.power_level(user_id).await
+    }
+
+    async fn parent_spaces(&self) -> Result<Vec<Box<RoomId>>> {
+        self.inner.parent_spaces().await
+    }
+
+    async fn space_power_level(&self, space_id: &RoomId, user_id: &UserId) -> Result<i64> {
+        self.inner.space_power_level(space_id, user_id).await
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.inner.now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::split;
+
+    #[test]
+    fn not_a_command() {
+        assert_eq!(split("hi; !ping", "!"), None);
+    }
+
+    #[test]
+    fn single_command() {
+        assert_eq!(split("!say hi", "!"), Some(vec![vec!["!say hi"]]));
+    }
+
+    #[test]
+    fn sequences_and_pipelines() {
+        assert_eq!(
+            split("!a 1; !b | !c\n!d", "!"),
+            Some(vec![vec!["!a 1"], vec!["!b", "!c"], vec!["!d"]])
+        );
+    }
+
+    #[test]
+    fn separators_not_followed_by_the_prefix() {
+        assert_eq!(
+            split("!say a; b | c\nd", "!"),
+            Some(vec![vec!["!say a; b | c\nd"]])
+        );
+    }
+
+    #[test]
+    fn quoted_separators() {
+        assert_eq!(
+            split(r#"!say "a; !b" c; !d"#, "!"),
+            Some(vec![vec![r#"!say "a; !b" c"#], vec!["!d"]])
+        );
+    }
+
+    #[test]
+    fn quote_inside_a_word() {
+        assert_eq!(
+            split(r#"!say 5" tall; !hi"#, "!"),
+            Some(vec![vec![r#"!say 5" tall"#], vec!["!hi"]])
+        );
+    }
+
+    #[test]
+    fn unclosed_quote() {
+        assert_eq!(
+            split(r#"!say "hi; !ping"#, "!"),
+            Some(vec![vec![r#"!say "hi"#], vec!["!ping"]])
+        );
+    }
+
+    #[test]
+    fn backslashes_dont_escape_quotes() {
+        // as with `String`, `"a \"` is a whole quoted string
+        assert_eq!(
+            split(r#"!say "a \" ; !b""#, "!"),
+            Some(vec![vec![r#"!say "a \""#], vec![r#"!b""#]])
+        );
+    }
+
+    #[test]
+    fn longer_prefix() {
+        assert_eq!(
+            split("bot: a | bot: b", "bot: "),
+            Some(vec![vec!["bot: a", "bot: b"]])
+        );
+    }
+}
@@ -1,25 +1,73 @@
-use std::{collections::HashMap, error::Error, future::Future, pin::Pin, str::FromStr, sync::Arc};
+use std::{
+    any::Any,
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    error::Error,
+    future::Future,
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+};
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+pub type ResolveError = Box<dyn Error + Send + Sync>;
 
 pub trait Parameter<C>
 where
-    Self: Sized,
+    Self: Sized + Send,
 {
     const INFO: &'static str;
     const VISIBLE: bool;
+    /// The name shown for the parameter in usage lines, `Named` sets this
+    const USAGE_NAME: Option<&'static str> = None;
+    /// Whether the parameter takes any number of values, shown as `<name...>`
+    const REPEATED: bool = false;
+
+    /// What `parse` gives, `Self` unless something has to be fetched before
+    /// the parameter is complete
+    type Parsed: Send;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>>;
+
+    /// Run once every parameter of a command has been parsed, before the
+    /// command is invoked. Parameters that need to fetch something from the
+    /// homeserver do it here.
+    fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        Self::Parsed: 'c,
+        C: Sync;
 
-    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>>;
     fn meta() -> ParameterMeta {
         ParameterMeta {
             info: Self::INFO,
             visible: Self::VISIBLE,
+            doc: None,
+            name: Self::USAGE_NAME,
+            repeated: Self::REPEATED,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct ParameterMeta {
     pub info: &'static str,
     pub visible: bool,
+    pub doc: Option<&'static str>,
+    pub name: Option<&'static str>,
+    pub repeated: bool,
+}
+
+impl ParameterMeta {
+    /// How the parameter is shown in usage lines, such as `<message...>`
+    pub fn usage(&self) -> String {
+        format!(
+            "<{}{}>",
+            self.name.unwrap_or(self.info),
+            if self.repeated { "..." } else { "" }
+        )
+    }
 }
 
 macro_rules! p_via_nom {
@@ -28,6 +76,8 @@ macro_rules! p_via_nom {
             const INFO: &'static str = stringify!($T);
             const VISIBLE: bool = true;
 
+            type Parsed = Self;
+
             fn parse<'a>(_ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
                 nom::sequence::delimited(
                     nom::character::complete::multispace0,
@@ -36,6 +86,14 @@ macro_rules! p_via_nom {
                 )(input)
                 .map_err(|e: nom::Err<nom::error::Error<_>>| e.into())
             }
+
+            fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+            where
+                Self: 'c,
+                C: Sync,
+            {
+                Box::pin(async move { Ok(parsed) })
+            }
         }
     };
 }
@@ -49,6 +107,77 @@ p_via_nom!(i16, nom::character::complete::i16);
 p_via_nom!(u8, nom::character::complete::u8);
 p_via_nom!(i8, nom::character::complete::i8);
 p_via_nom!(f32, nom::number::complete::float);
+p_via_nom!(f64, nom::number::complete::double);
+p_via_nom!(
+    usize,
+    nom::combinator::map_res(nom::character::complete::u64, usize::try_from)
+);
+p_via_nom!(
+    isize,
+    nom::combinator::map_res(nom::character::complete::i64, isize::try_from)
+);
+
+// yes/no, on/off or true/false
+p_via_nom!(
+    bool,
+    nom::combinator::map_opt(
+        nom::bytes::complete::take_till1(|c: char| c.is_whitespace()),
+        |word: &str| match word.to_ascii_lowercase().as_str() {
+            "yes" | "on" | "true" => Some(true),
+            "no" | "off" | "false" => Some(false),
+            _ => None,
+        }
+    )
+);
+
+// a single character on its own
+p_via_nom!(
+    char,
+    nom::combinator::map_opt(
+        nom::bytes::complete::take_till1(|c: char| c.is_whitespace()),
+        |word: &str| {
+            let mut chars = word.chars();
+            chars.next().filter(|_| chars.next().is_none())
+        }
+    )
+);
+
+macro_rules! p_non_zero {
+    ($T:ident, $Inner:ty) => {
+        impl<C> Parameter<C> for std::num::$T {
+            const INFO: &'static str = stringify!($T);
+            const VISIBLE: bool = true;
+
+            type Parsed = Self;
+
+            fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+                let (input, v) = <$Inner as Parameter<C>>::parse(ctx, input)?;
+                let v = Self::new(v).ok_or("Expected a number other than zero")?;
+
+                Ok((input, v))
+            }
+
+            fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+            where
+                Self: 'c,
+                C: Sync,
+            {
+                Box::pin(async move { Ok(parsed) })
+            }
+        }
+    };
+}
+
+p_non_zero!(NonZeroU8, u8);
+p_non_zero!(NonZeroU16, u16);
+p_non_zero!(NonZeroU32, u32);
+p_non_zero!(NonZeroU64, u64);
+p_non_zero!(NonZeroUsize, usize);
+p_non_zero!(NonZeroI8, i8);
+p_non_zero!(NonZeroI16, i16);
+p_non_zero!(NonZeroI32, i32);
+p_non_zero!(NonZeroI64, i64);
+p_non_zero!(NonZeroIsize, isize);
 
 p_via_nom!(
     String,
@@ -72,15 +201,26 @@ impl<C> Parameter<C> for Remainder {
     const INFO: &'static str = "String*";
 
     const VISIBLE: bool = true;
+    const REPEATED: bool = true;
+
+    type Parsed = Self;
 
     fn parse<'a>(_ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
         Ok(("", Self(input.to_string())))
     }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
 }
 
 pub struct ViaFromStr<T>(pub T);
 
-impl<T: FromStr, C> Parameter<C> for ViaFromStr<T>
+impl<T: FromStr + Send, C> Parameter<C> for ViaFromStr<T>
 where
     <T as FromStr>::Err: Error + 'static,
 {
@@ -88,39 +228,81 @@ where
 
     const VISIBLE: bool = true;
 
+    type Parsed = Self;
+
     fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
         let (input, chunk) = <String as Parameter<C>>::parse(ctx, input)?;
         let res = chunk.parse()?;
         Ok((input, ViaFromStr(res)))
     }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
 }
 
+/// Takes as many `T`s as parse, so it can only be the last parameter. See
+/// `params::Sep` and `params::AtMost` for lists that can be followed by more.
 impl<T: Parameter<C>, C> Parameter<C> for Vec<T> {
     const INFO: &'static str = str_concat(str_concat("Vec<", T::INFO).as_str(), ">").as_str();
 
     const VISIBLE: bool = T::VISIBLE;
+    const REPEATED: bool = true;
+
+    type Parsed = Vec<T::Parsed>;
 
-    fn parse<'a>(ctx: &C, mut input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+    fn parse<'a>(
+        ctx: &C,
+        mut input: &'a str,
+    ) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>> {
         let mut out = Vec::new();
 
         while let Ok((new_input, v)) = T::parse(ctx, input) {
+            // something that parses without taking any input, such as an
+            // empty `Sep`, would match forever
+            if new_input.len() == input.len() {
+                break;
+            }
+
             input = new_input;
             out.push(v);
         }
 
         Ok((input, out))
     }
+
+    fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        Self::Parsed: 'c,
+        C: Sync,
+    {
+        Box::pin(async move {
+            let mut out = Vec::with_capacity(parsed.len());
+
+            for v in parsed {
+                out.push(T::resolve(v, ctx).await?);
+            }
+
+            Ok(out)
+        })
+    }
 }
 
+#[derive(Clone)]
 pub struct Named<T, const NAME: &'static str>(pub T);
 
-struct HelpMe {
+pub(crate) struct HelpMe {
     s: [u8; 255],
     l: usize,
 }
 
 impl HelpMe {
-    const fn as_str(&self) -> &str {
+    pub(crate) const fn as_str(&self) -> &str {
         unsafe {
             let s = &*std::ptr::slice_from_raw_parts(&self.s as *const u8, self.l);
             std::str::from_utf8_unchecked(s)
@@ -156,13 +338,109 @@ const fn str_concat(l: &'static str, r: &'static str) -> HelpMe {
     }
 }
 
+/// Like `str_concat` for any number of strings
+pub(crate) const fn str_join(parts: &[&str]) -> HelpMe {
+    let mut out = [0u8; 255];
+    let mut len = 0;
+
+    let mut i = 0;
+    while i < parts.len() {
+        let part = parts[i].as_bytes();
+        assert!(len + part.len() < out.len());
+
+        let mut j = 0;
+        while j < part.len() {
+            out[len] = part[j];
+            len += 1;
+            j += 1;
+        }
+
+        i += 1;
+    }
+
+    HelpMe { s: out, l: len }
+}
+
+/// Format an integer at compile time, for `INFO`s that mention const generics
+pub(crate) const fn int_str(n: i64) -> HelpMe {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    let mut m = n.unsigned_abs();
+
+    loop {
+        digits[len] = b'0' + (m % 10) as u8;
+        len += 1;
+        m /= 10;
+
+        if m == 0 {
+            break;
+        }
+    }
+
+    let mut out = [0u8; 255];
+    let mut l = 0;
+
+    if n < 0 {
+        out[0] = b'-';
+        l = 1;
+    }
+
+    while len > 0 {
+        len -= 1;
+        out[l] = digits[len];
+        l += 1;
+    }
+
+    HelpMe { s: out, l }
+}
+
+/// Encode a char as UTF-8 at compile time
+pub(crate) const fn char_str(c: char) -> HelpMe {
+    let c = c as u32;
+    let mut out = [0u8; 255];
+
+    let l = if c < 0x80 {
+        out[0] = c as u8;
+        1
+    } else if c < 0x800 {
+        out[0] = 0xc0 | (c >> 6) as u8;
+        out[1] = 0x80 | (c & 0x3f) as u8;
+        2
+    } else if c < 0x10000 {
+        out[0] = 0xe0 | (c >> 12) as u8;
+        out[1] = 0x80 | ((c >> 6) & 0x3f) as u8;
+        out[2] = 0x80 | (c & 0x3f) as u8;
+        3
+    } else {
+        out[0] = 0xf0 | (c >> 18) as u8;
+        out[1] = 0x80 | ((c >> 12) & 0x3f) as u8;
+        out[2] = 0x80 | ((c >> 6) & 0x3f) as u8;
+        out[3] = 0x80 | (c & 0x3f) as u8;
+        4
+    };
+
+    HelpMe { s: out, l }
+}
+
 impl<T: Parameter<C>, C, const NAME: &'static str> Parameter<C> for Named<T, NAME> {
     const INFO: &'static str = str_concat(str_concat(NAME, ": ").as_str(), T::INFO).as_str();
     const VISIBLE: bool = T::VISIBLE;
+    const USAGE_NAME: Option<&'static str> = Some(NAME);
+    const REPEATED: bool = T::REPEATED;
 
-    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
-        let (input, v) = T::parse(ctx, input)?;
-        Ok((input, Named(v)))
+    type Parsed = T::Parsed;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>> {
+        T::parse(ctx, input)
+    }
+
+    fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        Self::Parsed: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(Named(T::resolve(parsed, ctx).await?)) })
     }
 }
 
@@ -189,94 +467,483 @@ impl<C> ReifyParameterMeta<C> for frunk::HNil {
     fn reify_inner(_out: &mut Vec<ParameterMeta>) {}
 }
 
+/// Why a command couldn't be run
+#[derive(Debug)]
+pub enum CommandError {
+    /// A parameter couldn't be parsed from the message
+    Parse { param: &'static str, error: String },
+    /// A parameter was parsed but couldn't be resolved
+    Resolve {
+        param: &'static str,
+        error: ResolveError,
+    },
+    /// None of the overloads of a command could be parsed, along with the
+    /// usage of each and why it failed
+    NoMatch(Vec<(String, CommandError)>),
+    /// A command chained with others failed
+    Step {
+        step: usize,
+        command: String,
+        error: Box<CommandError>,
+    },
+    /// A command chained with others doesn't exist or is disabled
+    UnknownStep { step: usize, command: String },
+    /// A message chained more commands than `BotConfig::max_chain_length`
+    ChainTooLong { max: usize },
+    /// An alias expanded to more aliases than `BotConfig::max_alias_depth`
+    AliasTooDeep { name: String, max: usize },
+}
+
+impl CommandError {
+    pub fn parse(param: &'static str, error: Box<dyn Error + '_>) -> Self {
+        CommandError::Parse {
+            param,
+            error: error.to_string(),
+        }
+    }
+
+    pub fn resolve(param: &'static str, error: ResolveError) -> Self {
+        CommandError::Resolve { param, error }
+    }
+
+    /// The error as shown to users in `locale`, `Display` always uses English
+    pub fn localise(
+        &self,
+        locales: &crate::framework::i18n::Locales,
+        locale: &crate::framework::i18n::LanguageIdentifier,
+    ) -> String {
+        match self {
+            CommandError::Parse { param, error } => locales.tr(
+                locale,
+                "error-parse",
+                &[("param", (*param).into()), ("error", error.as_str().into())],
+            ),
+            CommandError::Resolve { param, error } => locales.tr(
+                locale,
+                "error-resolve",
+                &[
+                    ("param", (*param).into()),
+                    ("error", error.to_string().into()),
+                ],
+            ),
+            CommandError::NoMatch(candidates) => {
+                let mut out = locales.tr(locale, "error-no-match", &[]);
+
+                for (usage, error) in candidates {
+                    out.push_str("\n  - ");
+                    out.push_str(&locales.tr(
+                        locale,
+                        "error-no-match-form",
+                        &[
+                            ("usage", usage.as_str().into()),
+                            ("error", error.localise(locales, locale).into()),
+                        ],
+                    ));
+                }
+
+                out
+            }
+            CommandError::Step {
+                step,
+                command,
+                error,
+            } => locales.tr(
+                locale,
+                "error-step",
+                &[
+                    ("step", (*step).into()),
+                    ("command", command.as_str().into()),
+                    ("error", error.localise(locales, locale).into()),
+                ],
+            ),
+            CommandError::UnknownStep { step, command } => locales.tr(
+                locale,
+                "error-unknown-step",
+                &[
+                    ("step", (*step).into()),
+                    ("command", command.as_str().into()),
+                ],
+            ),
+            CommandError::ChainTooLong { max } => {
+                locales.tr(locale, "error-chain-too-long", &[("max", (*max).into())])
+            }
+            CommandError::AliasTooDeep { name, max } => locales.tr(
+                locale,
+                "error-alias-too-deep",
+                &[("name", name.as_str().into()), ("max", (*max).into())],
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommandError::Parse { param, error } => {
+                write!(f, "Couldn't parse `{}`: {}", param, error)
+            }
+            CommandError::Resolve { param, error } => {
+                write!(f, "Couldn't resolve `{}`: {}", param, error)
+            }
+            CommandError::NoMatch(candidates) => {
+                write!(f, "None of the forms of this command matched:")?;
+
+                for (usage, error) in candidates {
+                    write!(f, "\n  - ({}): {}", usage, error)?;
+                }
+
+                Ok(())
+            }
+            CommandError::Step {
+                step,
+                command,
+                error,
+            } => write!(f, "Step {} (`{}`) failed: {}", step, command, error),
+            CommandError::UnknownStep { step, command } => {
+                write!(f, "Step {} (`{}`) isn't a command", step, command)
+            }
+            CommandError::ChainTooLong { max } => {
+                write!(f, "Only {} commands can be chained in one message", max)
+            }
+            CommandError::AliasTooDeep { name, max } => {
+                write!(f, "`{}` uses aliases more than {} deep", name, max)
+            }
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+/// One signature of a command
 #[derive(derivative::Derivative)]
 #[derivative(Clone(bound = ""))]
 #[allow(clippy::type_complexity)]
-pub struct ErasedCommand<C> {
-    pub meta: CommandMeta,
-    invoke: Arc<
-        dyn for<'a> Fn(
-                C,
-                &'a str,
-            ) -> Result<
-                (&'a str, Pin<Box<dyn Future<Output = ()> + Send + 'static>>),
-                Box<dyn Error + 'a>,
-            > + Send
+struct Overload<C> {
+    parse: Arc<
+        dyn for<'a> Fn(&C, &'a str) -> Result<(&'a str, ParsedValue), CommandError>
+            + Send
             + Sync
             + 'static,
     >,
+    run: Arc<dyn Fn(C, ParsedValue) -> BoxFuture<'static, Result<(), CommandError>> + Send + Sync>,
+}
+
+/// A command's parameters, parsed by one of its overloads
+pub struct ParsedCommand {
+    overload: usize,
+    value: ParsedValue,
+}
+
+#[derive(derivative::Derivative)]
+#[derivative(Clone(bound = ""))]
+pub struct ErasedCommand<C> {
+    pub meta: CommandMeta,
+    /// Overrides `BotConfig::typing` for this command
+    pub typing: Option<bool>,
+    overloads: Vec<Overload<C>>,
 }
 
 impl<C> ErasedCommand<C> {
-    pub async fn invoke<'i>(&self, ctx: C, input: &'i str) -> Result<(), Box<dyn Error + 'i>> {
-        let (_, fut) = (self.invoke)(ctx, input)?;
+    /// Parse the input with each overload in turn, preferring the first that
+    /// consumes all of it, then the first that parses at all
+    pub fn parse(&self, ctx: &C, input: &str) -> Result<ParsedCommand, CommandError> {
+        let mut first = None;
+        let mut errors = Vec::new();
+
+        for (overload, o) in self.overloads.iter().enumerate() {
+            match (o.parse)(ctx, input) {
+                Ok((rest, value)) if rest.trim().is_empty() => {
+                    return Ok(ParsedCommand { overload, value })
+                }
+                Ok((_, value)) => {
+                    if first.is_none() {
+                        first = Some(ParsedCommand { overload, value });
+                    }
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+
+        if let Some(parsed) = first {
+            return Ok(parsed);
+        }
 
-        fut.await;
+        if errors.len() == 1 {
+            return Err(errors.pop().unwrap());
+        }
 
-        Ok(())
+        Err(CommandError::NoMatch(
+            self.meta.usages().into_iter().zip(errors).collect(),
+        ))
+    }
+
+    /// Resolve the parameters and then run the command
+    pub async fn run(&self, ctx: C, parsed: ParsedCommand) -> Result<(), CommandError> {
+        (self.overloads[parsed.overload].run)(ctx, parsed.value).await
+    }
+
+    /// Parse the input, resolve the parameters and then run the command
+    pub async fn invoke(&self, ctx: C, input: &str) -> Result<(), CommandError> {
+        let parsed = self.parse(&ctx, input)?;
+
+        self.run(ctx, parsed).await
+    }
+
+    /// Add another signature to this command, overloads are tried in the order
+    /// they were added. The other command's description and aliases are ignored.
+    pub fn overload(mut self, other: ErasedCommand<C>) -> Self {
+        self.meta.overloads.push(other.meta.params);
+        self.meta.overloads.extend(other.meta.overloads);
+        self.overloads.extend(other.overloads);
+        self
     }
 
     pub fn with_description(mut self, description: &str) -> Self {
         self.meta.description = Some(description.to_owned());
         self
     }
+
+    pub fn with_typing(mut self, typing: bool) -> Self {
+        self.typing = Some(typing);
+        self
+    }
+
+    /// Other names the command can be invoked by in the group it's added to
+    pub fn with_aliases(mut self, aliases: &[&str]) -> Self {
+        self.meta.aliases = aliases.iter().map(|&a| a.to_owned()).collect();
+        self
+    }
+
+    /// Add an example invocation, shown in help
+    pub fn example(mut self, example: &str) -> Self {
+        self.meta.examples.push(example.to_owned());
+        self
+    }
+
+    /// Documentation shown after the description in the command's own help
+    pub fn long_description(mut self, long_description: &str) -> Self {
+        self.meta.long_description = Some(long_description.to_owned());
+        self
+    }
+
+    pub fn category(mut self, category: &str) -> Self {
+        self.meta.category = Some(category.to_owned());
+        self
+    }
+
+    /// The version of the bot the command was added in
+    pub fn since(mut self, version: &str) -> Self {
+        self.meta.since = Some(version.to_owned());
+        self
+    }
+
+    /// Mark the command as going away, `note` saying what to use instead
+    pub fn deprecated(mut self, note: &str) -> Self {
+        self.meta.deprecated = Some(note.to_owned());
+        self
+    }
+
+    /// Keep the command usable in rooms whose policy disables it, for commands
+    /// that manage the policy itself
+    pub fn exempt_from_policy(mut self) -> Self {
+        self.meta.policy_exempt = true;
+        self
+    }
+
+    /// Only run the fallback for input starting with one of `names`, such as
+    /// tags added from chat. Anything else is treated as if there were no
+    /// fallback, and help lists the names of the room it's asked in.
+    pub fn with_names(mut self, names: Arc<dyn FallbackNames>) -> Self {
+        self.meta.names = Some(names);
+        self
+    }
+
+    /// Document each parameter, in the order they appear in the command
+    pub fn with_param_docs(mut self, docs: &[Option<&'static str>]) -> Self {
+        for (param, &doc) in self.meta.params.iter_mut().zip(docs) {
+            param.doc = doc;
+        }
+        self
+    }
 }
 
-#[derive(Clone)]
+#[derive(Clone, serde::Serialize)]
 pub struct CommandMeta {
+    /// A one line summary
     pub description: Option<String>,
+    /// Shown after the summary in the command's own help
+    pub long_description: Option<String>,
+    /// Used to group commands in documentation
+    pub category: Option<String>,
+    /// The version the command was added in
+    pub since: Option<String>,
+    /// Set for commands that will be removed, with what to use instead
+    pub deprecated: Option<String>,
+    pub aliases: Vec<String>,
     pub params: Vec<ParameterMeta>,
+    /// The parameters of each signature after the first
+    pub overloads: Vec<Vec<ParameterMeta>>,
+    /// Example invocations, shown in help
+    pub examples: Vec<String>,
+    pub policy_exempt: bool,
+    /// The names a fallback answers to, see `ErasedCommand::with_names`
+    #[serde(skip)]
+    pub names: Option<Arc<dyn FallbackNames>>,
+}
+
+/// The names a fallback answers to when they're added at runtime, so it
+/// doesn't take every message starting with the prefix
+pub trait FallbackNames: Send + Sync {
+    fn contains(&self, room_id: &matrix_sdk::ruma::RoomId, name: &str) -> bool;
+
+    /// Every name in `room_id`, sorted
+    fn names(&self, room_id: &matrix_sdk::ruma::RoomId) -> Vec<String>;
 }
 
 impl CommandMeta {
+    /// Whether a fallback would take `input`, always true for fallbacks
+    /// without `FallbackNames`
+    pub fn accepts(&self, room_id: &matrix_sdk::ruma::RoomId, input: &str) -> bool {
+        match &self.names {
+            Some(names) => {
+                next_word(input).map_or(false, |(name, _)| names.contains(room_id, name))
+            }
+            None => true,
+        }
+    }
+
     pub fn visible_params(&self) -> impl Iterator<Item = &str> {
         self.params.iter().filter(|m| m.visible).map(|m| m.info)
     }
 
+    /// Visible parameters along with their documentation, if any
+    pub fn documented_params(&self) -> impl Iterator<Item = String> + '_ {
+        self.params
+            .iter()
+            .filter(|m| m.visible)
+            .map(|m| match m.doc {
+                Some(doc) => format!("{} - {}", m.info, doc),
+                None => m.info.to_owned(),
+            })
+    }
+
     pub fn format_params(&self) -> String {
         self.visible_params().collect::<Vec<_>>().join(", ")
     }
+
+    /// The visible parameters of every signature, formatted like `format_params`
+    pub fn usages(&self) -> Vec<String> {
+        std::iter::once(&self.params)
+            .chain(&self.overloads)
+            .map(|params| {
+                params
+                    .iter()
+                    .filter(|m| m.visible)
+                    .map(|m| m.info)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect()
+    }
+
+    /// A line for each signature showing how to invoke the command, such as
+    /// `!recur <rule> <message...>` given `!recur`
+    pub fn usage_lines(&self, invocation: &str) -> Vec<String> {
+        std::iter::once(&self.params)
+            .chain(&self.overloads)
+            .map(|params| {
+                std::iter::once(invocation.to_owned())
+                    .chain(params.iter().filter(|m| m.visible).map(|m| m.usage()))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
 }
 
+/// Commands are run in two phases: `parse` synchronously splits the input into
+/// parameters, then `resolve` lets each parameter do any async work it needs
+/// (fetching events, resolving aliases, ...) before `invoke` runs the command.
 #[async_trait::async_trait]
 pub trait Command<P, C> {
-    fn parse<'a>(ctx: &'_ C, input: &'a str) -> Result<(&'a str, P), Box<dyn Error + 'a>>;
+    /// The parameters as `parse` gives them, before they're resolved
+    type Parsed: Send + 'static;
+
+    fn parse<'a>(ctx: &'_ C, input: &'a str) -> Result<(&'a str, Self::Parsed), CommandError>;
+
+    async fn resolve(ctx: &C, params: Self::Parsed) -> Result<P, CommandError>;
 
     async fn invoke(self, ctx: C, params: P);
 
     fn into_erased(self, description: Option<String>) -> ErasedCommand<C>
     where
         Self: Sized + Clone + Send + Sync + 'static,
-        P: ReifyParameterMeta<C>,
+        P: ReifyParameterMeta<C> + Send + 'static,
+        C: Send + Sync + 'static,
     {
+        let cmd = self;
+
         ErasedCommand {
             meta: CommandMeta {
                 description,
+                long_description: None,
+                category: None,
+                since: None,
+                deprecated: None,
+                aliases: Vec::new(),
                 params: P::reify(),
+                overloads: Vec::new(),
+                examples: Vec::new(),
+                policy_exempt: false,
+                names: None,
             },
-            invoke: Arc::new(move |ctx, input| {
-                let (input, params): (&str, P) = Self::parse(&ctx, input)?;
-
-                Ok((input, self.clone().invoke(ctx, params)))
-            }),
+            typing: None,
+            overloads: vec![Overload {
+                parse: Arc::new(parse_erased::<Self, P, C>),
+                run: Arc::new(move |ctx, params| {
+                    let params = *params
+                        .downcast::<Self::Parsed>()
+                        .expect("Commands are run with the params their overload parsed");
+                    let cmd = cmd.clone();
+
+                    Box::pin(async move {
+                        let params = Self::resolve(&ctx, params).await?;
+                        cmd.invoke(ctx, params).await;
+
+                        Ok(())
+                    })
+                }),
+            }],
         }
     }
 }
 
+fn parse_erased<'a, Cmd: Command<P, C>, P, C>(
+    ctx: &C,
+    input: &'a str,
+) -> Result<(&'a str, ParsedValue), CommandError> {
+    let (input, params) = Cmd::parse(ctx, input)?;
+
+    Ok((input, Box::new(params)))
+}
+
 #[async_trait::async_trait]
 impl<F, C, Fut> Command<frunk::HList![], C> for F
 where
     F: FnOnce(C) -> Fut + Clone + Send + Sync + 'static,
-    C: Send + 'static,
+    C: Send + Sync + 'static,
     Fut: std::future::Future<Output = ()> + Send,
 {
-    fn parse<'a>(
-        _: &'_ C,
-        input: &'a str,
-    ) -> Result<(&'a str, frunk::HList![]), Box<dyn Error + 'a>> {
+    type Parsed = frunk::HList![];
+
+    fn parse<'a>(_: &'_ C, input: &'a str) -> Result<(&'a str, frunk::HList![]), CommandError> {
         Ok((input, frunk::hlist!()))
     }
 
+    async fn resolve(_: &C, params: frunk::HList![]) -> Result<frunk::HList![], CommandError> {
+        Ok(params)
+    }
+
     async fn invoke(self, ctx: C, _: frunk::HList![]) {
         self(ctx).await;
     }
@@ -291,18 +958,33 @@ macro_rules! doit {
         #[allow(non_snake_case)]
         impl<F, C, Fut, $($Y),*> Command<frunk::HList![$($Y),*], C> for F
             where F: FnOnce(C, $($Y),*) -> Fut + Clone + Send + Sync + 'static,
-                  C: Send + 'static,
+                  C: Send + Sync + 'static,
                   Fut: std::future::Future<Output = ()> + Send,
-                  $($Y: Parameter<C> + Send + 'static),*
+                  $($Y: Parameter<C> + Send + 'static, <$Y as Parameter<C>>::Parsed: 'static),*
         {
-            fn parse<'a>(ctx: &'_ C, input: &'a str) -> Result<(&'a str, frunk::HList![$($Y),*]), Box<dyn Error + 'a>> {
+            type Parsed = frunk::HList![$(<$Y as Parameter<C>>::Parsed),*];
+
+            fn parse<'a>(ctx: &'_ C, input: &'a str) -> Result<(&'a str, Self::Parsed), CommandError> {
                 $(
-                    let (input, $Y) = $Y::parse(ctx, input)?;
+                    let (input, $Y) = <$Y as Parameter<C>>::parse(ctx, input)
+                        .map_err(|e| CommandError::parse(<$Y as Parameter<C>>::INFO, e))?;
                 )*
 
                 Ok((input, frunk::hlist![$($Y),*]))
             }
 
+            async fn resolve(ctx: &C, params: Self::Parsed) -> Result<frunk::HList![$($Y),*], CommandError> {
+                let frunk::hlist_pat!($($Y),*) = params;
+
+                $(
+                    let $Y = <$Y as Parameter<C>>::resolve($Y, ctx)
+                        .await
+                        .map_err(|e| CommandError::resolve(<$Y as Parameter<C>>::INFO, e))?;
+                )*
+
+                Ok(frunk::hlist![$($Y),*])
+            }
+
             async fn invoke(self, ctx: C, params: frunk::HList![$($Y),*]) {
                 let frunk::hlist_pat!($($Y),*) = params;
 
@@ -314,34 +996,233 @@ macro_rules! doit {
 
 doit!(dummy, T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
 
+type ParsedValue = Box<dyn Any + Send>;
+
+/// A resolved group parameter, kept in the context's `GroupArgs`
+pub type GroupValue = Box<dyn Any + Send + Sync>;
+
+/// A parameter taken by a group before the name of a subcommand, its value is
+/// available to the subcommand through the `GroupArg<T>` extractor
 #[derive(derivative::Derivative)]
-#[derivative(Clone(bound = ""), Default(bound = ""))]
-pub struct Group<C> {
-    pub description: Option<String>,
-    pub inner: HashMap<String, GroupOrCommand<C>>,
-    pub fallback: Option<ErasedCommand<C>>,
+#[derivative(Clone(bound = ""))]
+#[allow(clippy::type_complexity)]
+pub struct GroupParam<C> {
+    pub meta: ParameterMeta,
+    parse: for<'a> fn(&C, &'a str) -> Result<(&'a str, ParsedValue), CommandError>,
+    resolve: for<'c> fn(ParsedValue, &'c C) -> BoxFuture<'c, Result<GroupValue, CommandError>>,
 }
 
-impl<C> Group<C> {
-    pub fn with_description(mut self, description: &str) -> Self {
-        self.description = Some(description.to_owned());
-        self
+fn parse_group_param<'a, C, T>(
+    ctx: &C,
+    input: &'a str,
+) -> Result<(&'a str, ParsedValue), CommandError>
+where
+    T: Parameter<C>,
+    T::Parsed: 'static,
+{
+    let (input, value) = T::parse(ctx, input).map_err(|e| CommandError::parse(T::INFO, e))?;
+
+    Ok((input, Box::new(value)))
+}
+
+fn resolve_group_param<'c, C, T>(
+    value: ParsedValue,
+    ctx: &'c C,
+) -> BoxFuture<'c, Result<GroupValue, CommandError>>
+where
+    C: Sync,
+    T: Parameter<C> + Sync + 'static,
+    T::Parsed: 'static,
+{
+    let value = *value
+        .downcast::<T::Parsed>()
+        .expect("Group params are resolved by the param that parsed them");
+
+    Box::pin(async move {
+        let value = T::resolve(value, ctx)
+            .await
+            .map_err(|e| CommandError::resolve(T::INFO, e))?;
+
+        Ok(Box::new(value) as GroupValue)
+    })
+}
+
+impl<C: Sync> GroupParam<C> {
+    pub fn new<T>() -> Self
+    where
+        T: Parameter<C> + Sync + 'static,
+        T::Parsed: 'static,
+    {
+        Self {
+            meta: T::meta(),
+            parse: parse_group_param::<C, T>,
+            resolve: resolve_group_param::<C, T>,
+        }
     }
 }
 
-#[derive(Clone)]
-pub struct GroupMeta {
-    pub description: Option<String>,
-    pub inner: HashMap<String, GroupOrCommandMeta>,
-    pub fallback: Option<CommandMeta>,
+/// A group parameter that has been parsed but not resolved yet
+pub struct ParsedGroupParam<'g, C> {
+    param: &'g GroupParam<C>,
+    value: ParsedValue,
 }
 
-impl GroupMeta {
-    /// Find a command or group given a path, this doesn't peek into Group.fallback
+impl<'g, C> ParsedGroupParam<'g, C> {
+    pub async fn resolve(self, ctx: &C) -> Result<GroupValue, CommandError> {
+        (self.param.resolve)(self.value, ctx).await
+    }
+}
+
+/// The result of looking up a command in a message
+pub struct FoundCommand<'g, C> {
+    /// The path of the command with any aliases replaced by the names they refer to
+    pub path: Vec<&'g str>,
+    pub command: &'g ErasedCommand<C>,
+    /// The command's parameters, ready to be passed to `ErasedCommand::run`
+    pub parsed: ParsedCommand,
+    /// The parameters of each group on the way to the command, outermost first
+    pub group_params: Vec<ParsedGroupParam<'g, C>>,
+}
+
+/// A command found in a message whose parameters, or those of a group on the
+/// way to it, couldn't be parsed
+pub struct NotParsed<'g> {
+    /// The path of the command, or of the group whose parameter didn't parse
+    pub path: Vec<&'g str>,
+    /// Whether the command is exempt from policy, see `ErasedCommand::exempt_from_policy`
+    pub policy_exempt: bool,
+    pub error: CommandError,
+}
+
+impl<'g> NotParsed<'g> {
+    fn prepend(mut self, name: &'g str) -> Self {
+        self.path.insert(0, name);
+        self
+    }
+}
+
+#[derive(derivative::Derivative)]
+#[derivative(Clone(bound = ""), Default(bound = ""))]
+pub struct Group<C> {
+    pub description: Option<String>,
+    /// Parsed after the group's name and before the name of a subcommand
+    pub params: Vec<GroupParam<C>>,
+    pub inner: HashMap<String, GroupOrCommand<C>>,
+    /// Maps each alias to the name of the command it refers to
+    pub aliases: HashMap<String, String>,
+    pub fallback: Option<ErasedCommand<C>>,
+}
+
+impl<C> Group<C> {
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+}
+
+/// The metadata of a group, kept sorted by name so help lists things in a
+/// stable order
+#[derive(Clone, serde::Serialize)]
+pub struct GroupMeta {
+    pub description: Option<String>,
+    pub params: Vec<ParameterMeta>,
+    pub inner: BTreeMap<String, GroupOrCommandMeta>,
+    pub aliases: BTreeMap<String, String>,
+    pub fallback: Option<CommandMeta>,
+}
+
+impl GroupMeta {
+    /// The group's own parameters, given before the name of a subcommand
+    pub fn visible_params(&self) -> impl Iterator<Item = &str> {
+        self.params.iter().filter(|m| m.visible).map(|m| m.info)
+    }
+
+    /// The usage of each of the group's visible parameters, such as `<room>`
+    pub fn param_usages(&self) -> impl Iterator<Item = String> + '_ {
+        self.params.iter().filter(|m| m.visible).map(|m| m.usage())
+    }
+
+    /// How to invoke something in the group, such as `!room <room> ...`
+    /// given `!room`
+    pub fn usage_line(&self, invocation: &str) -> String {
+        std::iter::once(invocation.to_owned())
+            .chain(self.param_usages())
+            .chain(std::iter::once("...".to_owned()))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// How to get to the command or group at `path` from this group, such as
+    /// `!room <room> id` for `room id` given `!`. The parameters of a group at
+    /// the end of `path` aren't included, `usage_line` adds those.
+    pub fn invocation(&self, prefix: &str, path: &[&str]) -> String {
+        let (words, _) = self.invocation_words(path);
+
+        format!("{}{}", prefix, words.join(" "))
+    }
+
+    /// How to invoke the fallback of the group at `path`, which comes after
+    /// the group's own parameters
+    pub fn fallback_invocation(&self, prefix: &str, path: &[&str]) -> String {
+        let (mut words, group) = self.invocation_words(path);
+        words.extend(group.into_iter().flat_map(GroupMeta::param_usages));
+
+        format!("{}{}", prefix, words.join(" "))
+    }
+
+    /// The words of `invocation`, along with the group at the end of `path`
+    /// if there's one there
+    fn invocation_words(&self, path: &[&str]) -> (Vec<String>, Option<&GroupMeta>) {
+        let mut words = Vec::new();
+        let mut group = Some(self);
+
+        for name in path {
+            words.extend(group.into_iter().flat_map(GroupMeta::param_usages));
+            words.push(name.to_string());
+
+            group = match group.and_then(|g| g.get(name)) {
+                Some(GroupOrCommandMeta::Group(g)) => Some(g),
+                _ => None,
+            };
+        }
+
+        (words, group)
+    }
+
+    /// Look up a direct child by name or alias
+    pub fn get(&self, name: &str) -> Option<&GroupOrCommandMeta> {
+        self.inner
+            .get(name)
+            .or_else(|| self.inner.get(self.aliases.get(name)?))
+    }
+
+    /// The path of the command or group at `path` with any aliases replaced by
+    /// the names they refer to, `None` if there's nothing there
+    pub fn canonical_path(&self, path: &[&str]) -> Option<Vec<String>> {
+        match *path {
+            [] => Some(Vec::new()),
+            [x, ref xs @ ..] => {
+                let name = match self.aliases.get(x) {
+                    Some(target) if !self.inner.contains_key(x) => target.as_str(),
+                    _ => x,
+                };
+
+                let rest = match self.inner.get(name)? {
+                    GroupOrCommandMeta::Command(_) if xs.is_empty() => Vec::new(),
+                    GroupOrCommandMeta::Command(_) => return None,
+                    GroupOrCommandMeta::Group(g) => g.canonical_path(xs)?,
+                };
+
+                Some(std::iter::once(name.to_owned()).chain(rest).collect())
+            }
+        }
+    }
+
+    /// Find a command or group given a path, this doesn't peek into Group.fallback
     pub fn find_thing<'a>(&'a self, path: &[&str]) -> Option<GroupOrCommandMetaRef<'a>> {
         match *path {
             [] => Some(GroupOrCommandMetaRef::Group(self)),
-            [x, ref xs @ ..] => match self.inner.get(x) {
+            [x, ref xs @ ..] => match self.get(x) {
                 Some(GroupOrCommandMeta::Command(c)) => Some(GroupOrCommandMetaRef::Command(c)),
                 Some(GroupOrCommandMeta::Group(g)) => g.find_thing(xs),
                 None => Some(GroupOrCommandMetaRef::Group(self)),
@@ -359,60 +1240,263 @@ fn next_word(s: &str) -> Option<(&str, &str)> {
 }
 
 impl<C> Group<C> {
-    fn add_command(&mut self, name: &str, command: ErasedCommand<C>) {
-        match self.inner.get_mut(name) {
-            Some(GroupOrCommand::Command(_)) => {
-                panic!("Command {} already exists", name)
-            }
-            Some(GroupOrCommand::Group(Group {
-                fallback: Some(_), ..
-            })) => {
-                panic!("Fallback command for group {} already exists", name)
-            }
-            Some(GroupOrCommand::Group(Group { fallback, .. })) => {
-                *fallback = Some(command);
-                return;
-            }
-            None => (),
-        };
-
+    /// Look up a direct child by name or alias
+    pub fn get(&self, name: &str) -> Option<&GroupOrCommand<C>> {
         self.inner
-            .insert(name.to_owned(), GroupOrCommand::Command(command));
+            .get(name)
+            .or_else(|| self.inner.get(self.aliases.get(name)?))
     }
 
-    fn add_group(&mut self, name: &str, group: Group<C>) {
-        match self.inner.get_mut(name) {
-            Some(GroupOrCommand::Group(_)) => {
-                panic!("Group with name {} already exists", name)
-            }
-            Some(v @ &mut GroupOrCommand::Command(_)) => {
-                if group.fallback.is_some() {
-                    panic!("Can't add group {}, a command already exists and this group has it's fallback set", name);
-                }
-
-                let c = std::mem::replace(v, GroupOrCommand::Group(group));
-                v.as_group_mut().unwrap().fallback = c.into_command().ok();
-                return;
-            }
-            None => (),
-        }
+    /// Look up a direct child by name or alias, also returning its name
+    fn get_named(&self, name: &str) -> Option<(&str, &GroupOrCommand<C>)> {
+        let name = match self.aliases.get(name) {
+            Some(target) if !self.inner.contains_key(name) => target.as_str(),
+            _ => name,
+        };
 
-        self.inner
-            .insert(name.to_owned(), GroupOrCommand::Group(group));
+        self.inner.get_key_value(name).map(|(k, v)| (k.as_str(), v))
     }
 
     pub fn find_command_parsing<'a, 'b>(
         &'a self,
         input: &'b str,
     ) -> Option<(&'a ErasedCommand<C>, &'b str)> {
+        self.find_command_path(input)
+            .map(|(_, cmd, rest)| (cmd, rest))
+    }
+
+    /// Like `find_command_parsing`, also returning the path of the command
+    /// found with any aliases replaced by the names they refer to. Group
+    /// parameters aren't parsed, use `find_command_with_params` for groups
+    /// that take them.
+    pub fn find_command_path<'a, 'b>(
+        &'a self,
+        input: &'b str,
+    ) -> Option<(Vec<&'a str>, &'a ErasedCommand<C>, &'b str)> {
         if let Some((x, xs)) = next_word(input) {
-            match self.inner.get(x) {
-                Some(GroupOrCommand::Command(c)) => Some((c, xs)),
-                Some(GroupOrCommand::Group(g)) => g.find_command_parsing(xs),
-                None => self.fallback.as_ref().map(|f| (f, input)),
+            match self.get_named(x) {
+                Some((name, GroupOrCommand::Command(c))) => Some((vec![name], c, xs)),
+                Some((name, GroupOrCommand::Group(g))) => {
+                    let (mut path, c, rest) = g.find_command_path(xs)?;
+                    path.insert(0, name);
+                    Some((path, c, rest))
+                }
+                None => self.fallback.as_ref().map(|f| (vec![], f, input)),
             }
         } else {
-            self.fallback.as_ref().map(|f| (f, input))
+            self.fallback.as_ref().map(|f| (vec![], f, input))
+        }
+    }
+
+    /// Find the command in a message and parse its parameters along with
+    /// those of any groups on the way. If a subcommand or subgroup fails to
+    /// parse, the group's fallback is tried on the same input before giving up.
+    /// Returns `None` if there's no command, and the error of the first thing
+    /// tried if nothing parsed.
+    pub fn find_command_with_params<'g>(
+        &'g self,
+        ctx: &C,
+        input: &str,
+    ) -> Option<Result<FoundCommand<'g, C>, NotParsed<'g>>>
+    where
+        C: crate::framework::context::Context,
+    {
+        let mut group_params = Vec::new();
+
+        self.find_command_with_params_inner(ctx, input, &mut group_params)
+            .map(|found| {
+                found.map(|(path, command, parsed)| FoundCommand {
+                    path,
+                    command,
+                    parsed,
+                    group_params,
+                })
+            })
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn find_command_with_params_inner<'g>(
+        &'g self,
+        ctx: &C,
+        mut input: &str,
+        group_params: &mut Vec<ParsedGroupParam<'g, C>>,
+    ) -> Option<Result<(Vec<&'g str>, &'g ErasedCommand<C>, ParsedCommand), NotParsed<'g>>>
+    where
+        C: crate::framework::context::Context,
+    {
+        let not_parsed = |path: Vec<&'g str>, command: Option<&ErasedCommand<C>>| {
+            move |error| NotParsed {
+                path,
+                policy_exempt: command.map_or(false, |c| c.meta.policy_exempt),
+                error,
+            }
+        };
+
+        for param in &self.params {
+            match (param.parse)(ctx, input) {
+                Ok((rest, value)) => {
+                    input = rest;
+                    group_params.push(ParsedGroupParam { param, value });
+                }
+                Err(e) => return Some(Err(not_parsed(vec![], None)(e))),
+            }
+        }
+
+        let fallback = |group_params: &mut Vec<ParsedGroupParam<'g, C>>, mark: usize| {
+            // anything pushed by a subgroup we backed out of isn't ours
+            group_params.truncate(mark);
+
+            self.fallback
+                .as_ref()
+                .filter(|f| f.meta.accepts(ctx.room_id(), input))
+                .map(|f| {
+                    f.parse(ctx, input)
+                        .map(|parsed| (vec![], f, parsed))
+                        .map_err(not_parsed(vec![], Some(f)))
+                })
+        };
+        let mark = group_params.len();
+
+        let found = match next_word(input) {
+            Some((x, xs)) => match self.get_named(x) {
+                Some((name, GroupOrCommand::Command(c))) => Some(
+                    c.parse(ctx, xs)
+                        .map(|parsed| (vec![name], c, parsed))
+                        .map_err(not_parsed(vec![name], Some(c))),
+                ),
+                Some((name, GroupOrCommand::Group(g))) => g
+                    .find_command_with_params_inner(ctx, xs, group_params)
+                    .map(|found| match found {
+                        Ok((mut path, c, parsed)) => {
+                            path.insert(0, name);
+                            Ok((path, c, parsed))
+                        }
+                        Err(e) => Err(e.prepend(name)),
+                    }),
+                None => return fallback(group_params, mark),
+            },
+            None => return fallback(group_params, mark),
+        };
+
+        match found {
+            Some(Ok(found)) => Some(Ok(found)),
+            Some(Err(e)) => match fallback(group_params, mark) {
+                Some(Ok(found)) => Some(Ok(found)),
+                _ => Some(Err(e)),
+            },
+            None => fallback(group_params, mark),
+        }
+    }
+
+    /// Find a group given a path, this doesn't follow aliases. Groups on the
+    /// way that are shared with another tree are copied first.
+    pub fn find_group_mut(&mut self, path: &[&str]) -> Option<&mut Group<C>> {
+        match *path {
+            [] => Some(self),
+            [x, ref xs @ ..] => match self.inner.get_mut(x) {
+                Some(GroupOrCommand::Group(g)) => Arc::make_mut(g).find_group_mut(xs),
+                _ => None,
+            },
+        }
+    }
+
+    /// Remove a direct child along with its aliases
+    pub fn remove(&mut self, name: &str) -> Option<GroupOrCommand<C>> {
+        let removed = self.inner.remove(name)?;
+        self.aliases.retain(|_, target| target != name);
+
+        Some(removed)
+    }
+
+    /// Add a direct child to a built group, checking it against what's
+    /// already there the way `GroupBuilder` would. Only the new entry is
+    /// built, the rest of the group is left alone.
+    pub(crate) fn insert(
+        &mut self,
+        prefix: &str,
+        name: &str,
+        entry: BuilderEntry<C>,
+        errors: &mut Vec<BuildError>,
+    ) {
+        let path = join_path(prefix, name);
+
+        if !valid_name(name) {
+            errors.push(BuildError::InvalidName { path });
+            return;
+        }
+
+        if let Some(target) = self.aliases.get(name) {
+            errors.push(BuildError::AliasClash {
+                path: join_path(prefix, target),
+                alias: name.to_owned(),
+            });
+            return;
+        }
+
+        let aliases = match &entry {
+            BuilderEntry::Command(c) => c.meta.aliases.clone(),
+            BuilderEntry::Group(_) => Vec::new(),
+        };
+
+        match (entry, self.inner.get_mut(name)) {
+            (BuilderEntry::Command(_), Some(GroupOrCommand::Command(_))) => {
+                errors.push(BuildError::DuplicateCommand { path });
+                return;
+            }
+            (BuilderEntry::Group(_), Some(GroupOrCommand::Group(_))) => {
+                errors.push(BuildError::DuplicateGroup { path });
+                return;
+            }
+            // as when building, a command sharing a name with a group becomes
+            // its fallback
+            (BuilderEntry::Command(_), Some(GroupOrCommand::Group(sub)))
+                if sub.fallback.is_some() =>
+            {
+                errors.push(BuildError::ShadowedByFallback { path });
+                return;
+            }
+            (BuilderEntry::Command(command), Some(GroupOrCommand::Group(sub))) => {
+                Arc::make_mut(sub).fallback = Some(command);
+            }
+            (BuilderEntry::Group(builder), Some(GroupOrCommand::Command(_))) => {
+                let mut sub = builder.build_at(&path, errors);
+
+                if sub.fallback.is_some() {
+                    errors.push(BuildError::ShadowedByFallback { path });
+                    return;
+                }
+
+                if let Some(GroupOrCommand::Command(command)) = self.inner.remove(name) {
+                    sub.fallback = Some(command);
+                }
+
+                self.inner
+                    .insert(name.to_owned(), GroupOrCommand::Group(Arc::new(sub)));
+            }
+            (BuilderEntry::Group(builder), None) => {
+                let sub = builder.build_at(&path, errors);
+                self.inner
+                    .insert(name.to_owned(), GroupOrCommand::Group(Arc::new(sub)));
+            }
+            (BuilderEntry::Command(command), None) => {
+                self.inner
+                    .insert(name.to_owned(), GroupOrCommand::Command(command));
+            }
+        }
+
+        for alias in aliases {
+            if !valid_name(&alias)
+                || self.inner.contains_key(&alias)
+                || self.aliases.contains_key(&alias)
+            {
+                errors.push(BuildError::AliasClash {
+                    path: path.clone(),
+                    alias,
+                });
+                continue;
+            }
+
+            self.aliases.insert(alias, name.to_owned());
         }
     }
 
@@ -420,7 +1504,7 @@ impl<C> Group<C> {
     pub fn find_command(&self, path: &[&str]) -> Option<&ErasedCommand<C>> {
         match *path {
             [] => self.fallback.as_ref(),
-            [x, ref xs @ ..] => match self.inner.get(x) {
+            [x, ref xs @ ..] => match self.get(x) {
                 Some(GroupOrCommand::Command(c)) => Some(c),
                 Some(GroupOrCommand::Group(g)) => g.find_command(xs),
                 None => self.fallback.as_ref(),
@@ -432,7 +1516,7 @@ impl<C> Group<C> {
     pub fn find_thing<'a>(&'a self, path: &[&str]) -> Option<GroupOrCommandRef<'a, C>> {
         match *path {
             [] => Some(GroupOrCommandRef::Group(self)),
-            [x, ref xs @ ..] => match self.inner.get(x) {
+            [x, ref xs @ ..] => match self.get(x) {
                 Some(GroupOrCommand::Command(c)) => Some(GroupOrCommandRef::Command(c)),
                 Some(GroupOrCommand::Group(g)) => g.find_thing(xs),
                 None => Some(GroupOrCommandRef::Group(self)),
@@ -443,11 +1527,17 @@ impl<C> Group<C> {
     pub fn meta(&self) -> GroupMeta {
         GroupMeta {
             description: self.description.clone(),
+            params: self.params.iter().map(|p| p.meta.clone()).collect(),
             inner: self
                 .inner
                 .iter()
                 .map(|(k, v)| (k.clone(), v.meta()))
                 .collect(),
+            aliases: self
+                .aliases
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
             fallback: self.fallback.as_ref().map(|c| c.meta.clone()),
         }
     }
@@ -458,7 +1548,8 @@ impl<C> Group<C> {
 #[derive(enum_as_inner::EnumAsInner)]
 pub enum GroupOrCommand<C> {
     Command(ErasedCommand<C>),
-    Group(Group<C>),
+    /// Shared so copies of the tree only copy the groups that change
+    Group(Arc<Group<C>>),
 }
 
 impl<C> GroupOrCommand<C> {
@@ -478,7 +1569,8 @@ pub enum GroupOrCommandRef<'a, C> {
     Group(&'a Group<C>),
 }
 
-#[derive(Clone, enum_as_inner::EnumAsInner)]
+#[derive(Clone, enum_as_inner::EnumAsInner, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum GroupOrCommandMeta {
     Command(CommandMeta),
     Group(GroupMeta),
@@ -490,10 +1582,139 @@ pub enum GroupOrCommandMetaRef<'a> {
     Group(&'a GroupMeta),
 }
 
+/// A problem found while building a group, `path` is the full path of the
+/// command or group it concerns
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    DuplicateCommand {
+        path: String,
+    },
+    DuplicateGroup {
+        path: String,
+    },
+    DuplicateFallback {
+        path: String,
+    },
+    /// A command has the same name as a group that already has a fallback, so
+    /// it could never be invoked
+    ShadowedByFallback {
+        path: String,
+    },
+    /// An alias is already used as a name or by another alias
+    AliasClash {
+        path: String,
+        alias: String,
+    },
+    /// Names can't be matched if they're empty or contain whitespace
+    InvalidName {
+        path: String,
+    },
+    EmptyGroup {
+        path: String,
+    },
+    /// A fallback runs when no name matches, so aliases for it would never be
+    /// looked up
+    FallbackAlias {
+        path: String,
+    },
+    /// A group to change doesn't exist
+    NoSuchGroup {
+        path: String,
+    },
+}
+
+fn display_path(path: &str) -> &str {
+    if path.is_empty() {
+        "the root group"
+    } else {
+        path
+    }
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::DuplicateCommand { path } => {
+                write!(f, "Command `{}` is defined more than once", path)
+            }
+            BuildError::DuplicateGroup { path } => {
+                write!(f, "Group `{}` is defined more than once", path)
+            }
+            BuildError::DuplicateFallback { path } => {
+                write!(f, "{} has more than one fallback", display_path(path))
+            }
+            BuildError::ShadowedByFallback { path } => write!(
+                f,
+                "Command `{}` is unreachable, group `{}` already has a fallback",
+                path, path
+            ),
+            BuildError::AliasClash { path, alias } => write!(
+                f,
+                "Alias `{}` of `{}` clashes with an existing name",
+                alias, path
+            ),
+            BuildError::InvalidName { path } => write!(f, "`{}` isn't a valid name", path),
+            BuildError::NoSuchGroup { path } => write!(f, "There is no group `{}`", path),
+            BuildError::FallbackAlias { path } => write!(
+                f,
+                "The fallback of {} can't have aliases",
+                display_path(path)
+            ),
+            BuildError::EmptyGroup { path } => write!(
+                f,
+                "{} has no commands, groups or fallback",
+                display_path(path)
+            ),
+        }
+    }
+}
+
+/// Every problem found while building a group
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildErrors(pub Vec<BuildError>);
+
+impl std::fmt::Display for BuildErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Couldn't build the command tree:")?;
+
+        for error in &self.0 {
+            writeln!(f, "  - {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for BuildErrors {}
+
+pub(crate) enum BuilderEntry<C> {
+    Command(ErasedCommand<C>),
+    Group(GroupBuilder<C>),
+}
+
+/// Collects commands and groups, checking for conflicts once `build` is called
 #[derive(derivative::Derivative)]
-#[derivative(Clone(bound = ""), Default(bound = ""))]
+#[derivative(Default(bound = ""))]
 pub struct GroupBuilder<C> {
-    root: Group<C>,
+    description: Option<String>,
+    params: Vec<GroupParam<C>>,
+    fallbacks: Vec<ErasedCommand<C>>,
+    entries: Vec<(String, BuilderEntry<C>)>,
+    /// Makes the command added as `help` when building, given the group's
+    /// path, unless something else has that name
+    help: Option<fn(&[&str]) -> ErasedCommand<C>>,
+}
+
+fn join_path(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{} {}", prefix, name)
+    }
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(char::is_whitespace)
 }
 
 impl<C> GroupBuilder<C> {
@@ -501,25 +1722,463 @@ impl<C> GroupBuilder<C> {
         GroupBuilder::default()
     }
 
-    pub fn group(&mut self, name: &str, grp: Group<C>) -> &mut Self {
-        self.root.add_group(name, grp);
+    pub fn with_description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    /// Add a parameter parsed before the name of a subcommand, subcommands can
+    /// get its value with `GroupArg<T>`
+    pub fn with_param<T>(mut self) -> Self
+    where
+        T: Parameter<C> + Sync + 'static,
+        T::Parsed: 'static,
+        C: Sync,
+    {
+        self.params.push(GroupParam::new::<T>());
+        self
+    }
+
+    /// Add a group, a command with the same name becomes its fallback
+    pub fn group(mut self, name: &str, grp: impl Into<GroupBuilder<C>>) -> Self {
+        self.entries
+            .push((name.to_owned(), BuilderEntry::Group(grp.into())));
+        self
+    }
+
+    pub fn command(mut self, name: &str, cmd: ErasedCommand<C>) -> Self {
+        self.entries
+            .push((name.to_owned(), BuilderEntry::Command(cmd)));
         self
     }
 
-    pub fn command(&mut self, name: &str, cmd: ErasedCommand<C>) -> &mut Self {
-        self.root.add_command(name, cmd);
+    /// Add another signature to the command already added under `name`, or
+    /// add it as a new command if there isn't one
+    pub fn overload(mut self, name: &str, cmd: ErasedCommand<C>) -> Self {
+        let existing = self.entries.iter_mut().rev().find_map(|(n, e)| match e {
+            BuilderEntry::Command(c) if n == name => Some(c),
+            _ => None,
+        });
+
+        match existing {
+            Some(existing) => {
+                *existing = existing.clone().overload(cmd);
+                self
+            }
+            None => self.command(name, cmd),
+        }
+    }
+
+    /// Add the framework's `help` command to this group when it's built, unless
+    /// a command or group called `help` is added too. See `framework::help`.
+    pub fn with_help(mut self) -> Self
+    where
+        C: crate::framework::context::Context + Clone + Send + Sync + 'static,
+    {
+        self.help = Some(crate::framework::help::command_in::<C>);
+        self
+    }
+
+    /// The command run when no subcommand matches
+    pub fn fallback(mut self, cmd: ErasedCommand<C>) -> Self {
+        self.fallbacks.push(cmd);
+        self
+    }
+
+    /// Add a command declared with `#[bleh::command]`
+    pub fn add(self, def: CommandDef<C>) -> Self {
+        if def.overload {
+            self.overload(&def.name, def.command)
+        } else {
+            self.command(&def.name, def.command)
+        }
+    }
+
+    /// Add a group declared with `#[bleh::group]`
+    pub fn add_group(self, def: GroupDef<C>) -> Self {
+        self.group(&def.name, def.group)
+    }
+
+    /// Build the group, reporting every conflict in the tree rather than just
+    /// the first
+    pub fn build(self) -> Result<Group<C>, BuildErrors> {
+        let mut errors = Vec::new();
+        let group = self.build_at("", &mut errors);
+
+        if errors.is_empty() {
+            Ok(group)
+        } else {
+            Err(BuildErrors(errors))
+        }
+    }
+
+    pub(crate) fn build_at(mut self, prefix: &str, errors: &mut Vec<BuildError>) -> Group<C> {
+        if let Some(help) = self.help.take() {
+            if !self.entries.iter().any(|(name, _)| name == "help") {
+                let path = prefix.split_whitespace().collect::<Vec<_>>();
+                self.entries
+                    .push(("help".to_owned(), BuilderEntry::Command(help(&path))));
+            }
+        }
+
+        let mut fallbacks = self.fallbacks.into_iter();
+        let mut group = Group {
+            description: self.description,
+            params: self.params,
+            inner: HashMap::new(),
+            aliases: HashMap::new(),
+            fallback: fallbacks.next(),
+        };
+
+        if fallbacks.next().is_some() {
+            errors.push(BuildError::DuplicateFallback {
+                path: prefix.to_owned(),
+            });
+        }
+
+        if matches!(&group.fallback, Some(f) if !f.meta.aliases.is_empty()) {
+            errors.push(BuildError::FallbackAlias {
+                path: prefix.to_owned(),
+            });
+        }
+
+        let (groups, commands): (Vec<_>, Vec<_>) = self
+            .entries
+            .into_iter()
+            .partition(|(_, e)| matches!(e, BuilderEntry::Group(_)));
+
+        // groups go first so a command sharing a name becomes its fallback
+        // regardless of the order they were added in
+        for (name, entry) in groups {
+            let path = join_path(prefix, &name);
+
+            if !valid_name(&name) {
+                errors.push(BuildError::InvalidName { path });
+                continue;
+            }
+
+            let sub = match entry {
+                BuilderEntry::Group(sub) => sub.build_at(&path, errors),
+                BuilderEntry::Command(_) => unreachable!(),
+            };
+
+            if group.inner.contains_key(&name) {
+                errors.push(BuildError::DuplicateGroup { path });
+                continue;
+            }
+
+            group
+                .inner
+                .insert(name, GroupOrCommand::Group(Arc::new(sub)));
+        }
+
+        let mut aliases = Vec::new();
+
+        for (name, entry) in commands {
+            let path = join_path(prefix, &name);
+
+            if !valid_name(&name) {
+                errors.push(BuildError::InvalidName { path });
+                continue;
+            }
+
+            let command = match entry {
+                BuilderEntry::Command(command) => command,
+                BuilderEntry::Group(_) => unreachable!(),
+            };
+
+            for alias in &command.meta.aliases {
+                aliases.push((alias.clone(), name.clone()));
+            }
+
+            match group.inner.get_mut(&name) {
+                Some(GroupOrCommand::Command(_)) => {
+                    errors.push(BuildError::DuplicateCommand { path });
+                }
+                Some(GroupOrCommand::Group(sub)) if sub.fallback.is_some() => {
+                    errors.push(BuildError::ShadowedByFallback { path });
+                }
+                Some(GroupOrCommand::Group(sub)) => {
+                    Arc::make_mut(sub).fallback = Some(command);
+                }
+                None => {
+                    group.inner.insert(name, GroupOrCommand::Command(command));
+                }
+            }
+        }
+
+        for (alias, name) in aliases {
+            if !valid_name(&alias)
+                || group.inner.contains_key(&alias)
+                || group.aliases.contains_key(&alias)
+            {
+                errors.push(BuildError::AliasClash {
+                    path: join_path(prefix, &name),
+                    alias,
+                });
+                continue;
+            }
+
+            group.aliases.insert(alias, name);
+        }
+
+        if group.inner.is_empty() && group.fallback.is_none() {
+            errors.push(BuildError::EmptyGroup {
+                path: prefix.to_owned(),
+            });
+        }
+
+        group
+    }
+}
+
+impl<C> From<Group<C>> for GroupBuilder<C> {
+    fn from(group: Group<C>) -> Self {
+        let entries = group
+            .inner
+            .into_iter()
+            .map(|(name, entry)| {
+                let entry = match entry {
+                    GroupOrCommand::Command(c) => BuilderEntry::Command(c),
+                    GroupOrCommand::Group(g) => {
+                        let g = Arc::try_unwrap(g).unwrap_or_else(|g| (*g).clone());
+                        BuilderEntry::Group(g.into())
+                    }
+                };
+                (name, entry)
+            })
+            .collect();
+
+        Self {
+            description: group.description,
+            params: group.params,
+            fallbacks: group.fallback.into_iter().collect(),
+            entries,
+            help: None,
+        }
+    }
+}
+
+/// A command along with the name it's registered under, produced by
+/// `#[bleh::command]`
+pub struct CommandDef<C> {
+    pub name: String,
+    pub command: ErasedCommand<C>,
+    /// Whether this is another signature of a command of the same name
+    pub overload: bool,
+}
+
+impl<C> CommandDef<C> {
+    pub fn new(name: &str, command: ErasedCommand<C>) -> Self {
+        Self {
+            name: name.to_owned(),
+            command,
+            overload: false,
+        }
+    }
+
+    /// Add this to an earlier command of the same name as another signature
+    /// instead of conflicting with it
+    pub fn as_overload(mut self) -> Self {
+        self.overload = true;
         self
     }
+}
 
-    pub fn done(&mut self) -> Group<C> {
-        self.clone().root
+/// A group along with the name it's registered under, produced by
+/// `#[bleh::group]`. It's left unbuilt so conflicts are reported with their
+/// full path.
+pub struct GroupDef<C> {
+    pub name: String,
+    pub group: GroupBuilder<C>,
+}
+
+impl<C> GroupDef<C> {
+    pub fn new(name: &str, group: GroupBuilder<C>) -> Self {
+        Self {
+            name: name.to_owned(),
+            group,
+        }
     }
 }
 
 pub fn cmd<Cmd, P, C>(cmd: Cmd) -> ErasedCommand<C>
 where
     Cmd: Command<P, C> + Clone + Send + Sync + 'static,
-    P: ReifyParameterMeta<C>,
+    P: ReifyParameterMeta<C> + Send + 'static,
+    C: Send + Sync + 'static,
 {
     cmd.into_erased(None)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn noop() -> ErasedCommand<()> {
+        cmd(|_: ()| async {})
+    }
+
+    fn errors(builder: GroupBuilder<()>) -> Vec<BuildError> {
+        match builder.build() {
+            Ok(_) => Vec::new(),
+            Err(BuildErrors(errors)) => errors,
+        }
+    }
+
+    fn path(path: &str) -> String {
+        path.to_owned()
+    }
+
+    #[test]
+    fn builds_without_conflicts() {
+        let builder = GroupBuilder::new()
+            .command("a", noop().with_aliases(&["b"]))
+            .group("g", GroupBuilder::new().command("c", noop()))
+            .command("g", noop());
+
+        assert_eq!(errors(builder), vec![]);
+    }
+
+    #[test]
+    fn duplicates() {
+        let builder = GroupBuilder::new()
+            .command("a", noop())
+            .command("a", noop())
+            .group("g", GroupBuilder::new().command("c", noop()))
+            .group("g", GroupBuilder::new().command("c", noop()))
+            .fallback(noop())
+            .fallback(noop());
+
+        assert_eq!(
+            errors(builder),
+            vec![
+                BuildError::DuplicateFallback { path: path("") },
+                BuildError::DuplicateGroup { path: path("g") },
+                BuildError::DuplicateCommand { path: path("a") },
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowed_by_fallback() {
+        let builder = GroupBuilder::new()
+            .group("g", GroupBuilder::new().fallback(noop()))
+            .command("g", noop());
+
+        assert_eq!(
+            errors(builder),
+            vec![BuildError::ShadowedByFallback { path: path("g") }]
+        );
+    }
+
+    #[test]
+    fn alias_clashes() {
+        let builder = GroupBuilder::new()
+            .command("a", noop())
+            .command("b", noop().with_aliases(&["a", "c"]))
+            .command("d", noop().with_aliases(&["c", "two words"]));
+
+        assert_eq!(
+            errors(builder),
+            vec![
+                BuildError::AliasClash {
+                    path: path("b"),
+                    alias: path("a"),
+                },
+                BuildError::AliasClash {
+                    path: path("d"),
+                    alias: path("c"),
+                },
+                BuildError::AliasClash {
+                    path: path("d"),
+                    alias: path("two words"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_names() {
+        let builder = GroupBuilder::new()
+            .command("", noop())
+            .command("a b", noop())
+            .group("c d", GroupBuilder::new().command("e", noop()));
+
+        assert_eq!(
+            errors(builder),
+            vec![
+                BuildError::InvalidName { path: path("c d") },
+                BuildError::InvalidName { path: path("") },
+                BuildError::InvalidName { path: path("a b") },
+            ]
+        );
+    }
+
+    #[test]
+    fn fallback_aliases() {
+        let builder = GroupBuilder::new()
+            .command("a", noop())
+            .fallback(noop().with_aliases(&["b"]));
+
+        assert_eq!(
+            errors(builder),
+            vec![BuildError::FallbackAlias { path: path("") }]
+        );
+    }
+
+    #[test]
+    fn nested_errors_have_full_paths() {
+        let builder = GroupBuilder::new().group(
+            "g",
+            GroupBuilder::new()
+                .group("empty", GroupBuilder::new())
+                .command("c", noop())
+                .command("c", noop()),
+        );
+
+        assert_eq!(
+            errors(builder),
+            vec![
+                BuildError::EmptyGroup {
+                    path: path("g empty"),
+                },
+                BuildError::DuplicateCommand { path: path("g c") },
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_root() {
+        assert_eq!(
+            errors(GroupBuilder::new()),
+            vec![BuildError::EmptyGroup { path: path("") }]
+        );
+    }
+
+    #[test]
+    fn display() {
+        let errors = BuildErrors(vec![
+            BuildError::EmptyGroup { path: path("") },
+            BuildError::AliasClash {
+                path: path("g b"),
+                alias: path("a"),
+            },
+        ]);
+
+        assert_eq!(
+            errors.to_string(),
+            "Couldn't build the command tree:\n  \
+             - the root group has no commands, groups or fallback\n  \
+             - Alias `a` of `g b` clashes with an existing name\n"
+        );
+    }
+
+    #[test]
+    fn strings_keep_backslashes() {
+        let (rest, s) = <String as Parameter<()>>::parse(&(), r#""C:\" d"#).unwrap();
+
+        assert_eq!(s, r"C:\");
+        assert_eq!(rest, "d");
+    }
+}
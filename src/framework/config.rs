@@ -0,0 +1,96 @@
+use matrix_sdk::ruma::events::room::message::{
+    EmoteMessageEventContent, MessageType, NoticeMessageEventContent, TextMessageEventContent,
+};
+
+/// The `msgtype` used for messages the bot sends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    Text,
+    Notice,
+    Emote,
+}
+
+impl MessageKind {
+    pub fn plain(self, body: &str) -> MessageType {
+        match self {
+            MessageKind::Text => MessageType::Text(TextMessageEventContent::plain(body)),
+            MessageKind::Notice => MessageType::Notice(NoticeMessageEventContent::plain(body)),
+            MessageKind::Emote => MessageType::Emote(EmoteMessageEventContent::plain(body)),
+        }
+    }
+
+    pub fn html(self, plain: &str, html: &str) -> MessageType {
+        match self {
+            MessageKind::Text => MessageType::Text(TextMessageEventContent::html(plain, html)),
+            MessageKind::Notice => {
+                MessageType::Notice(NoticeMessageEventContent::html(plain, html))
+            }
+            MessageKind::Emote => MessageType::Emote(EmoteMessageEventContent::html(plain, html)),
+        }
+    }
+}
+
+/// Bot-wide settings shared by every handler and context
+#[derive(Debug, Clone)]
+pub struct BotConfig {
+    pub prefix: String,
+    /// The message type used by `send`, `reply` and everything the framework
+    /// sends on its own (help, errors)
+    pub message_kind: MessageKind,
+    /// Show a typing indicator while commands run, commands can override this
+    /// with `ErasedCommand::with_typing`
+    pub typing: bool,
+    /// Send a read receipt for every message that invokes a command
+    pub read_receipts: bool,
+    /// The most commands one message can run with `;`, newlines and `|`, see
+    /// `framework::chain`
+    pub max_chain_length: usize,
+    /// How deeply aliases can use other aliases, see `framework::aliases`
+    pub max_alias_depth: usize,
+}
+
+impl Default for BotConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "!".to_owned(),
+            message_kind: MessageKind::Text,
+            typing: false,
+            read_receipts: false,
+            max_chain_length: 5,
+            max_alias_depth: 4,
+        }
+    }
+}
+
+impl BotConfig {
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    pub fn with_message_kind(mut self, kind: MessageKind) -> Self {
+        self.message_kind = kind;
+        self
+    }
+
+    pub fn with_typing(mut self, typing: bool) -> Self {
+        self.typing = typing;
+        self
+    }
+
+    pub fn with_read_receipts(mut self, read_receipts: bool) -> Self {
+        self.read_receipts = read_receipts;
+        self
+    }
+
+    /// Set to 1 to turn off chaining
+    pub fn with_max_chain_length(mut self, max_chain_length: usize) -> Self {
+        self.max_chain_length = max_chain_length;
+        self
+    }
+
+    pub fn with_max_alias_depth(mut self, max_alias_depth: usize) -> Self {
+        self.max_alias_depth = max_alias_depth;
+        self
+    }
+}
@@ -1,77 +1,338 @@
 use std::sync::Arc;
 
-use matrix_sdk::{
-    room::Joined,
-    ruma::{
-        api::client::r0::message::send_message_event,
-        events::{room::message::RoomMessageEventContent, AnyMessageEventContent, SyncMessageEvent},
-        UserId,
+use chrono::{DateTime, Utc};
+use fluent_bundle::FluentValue;
+use matrix_sdk::ruma::{
+    events::{
+        room::message::{MessageType, Relation, RoomMessageEventContent},
+        MessageEvent, SyncMessageEvent,
     },
-    Client,
+    EventId, RoomId, UserId,
 };
+use mime::Mime;
 
-use crate::framework::commands::GroupMeta;
+use crate::framework::{
+    commands::GroupMeta,
+    config::{BotConfig, MessageKind},
+    extractors::GroupArgs,
+    i18n::{LanguageIdentifier, Locales},
+    media::{self, Media},
+    state::StateMap,
+    transport::{self, Transport},
+};
 
 use crate as bleh;
 
 #[ambassador::delegatable_trait]
 pub trait Context: Sized {
-    fn client(&self) -> &matrix_sdk::Client;
+    fn transport(&self) -> &std::sync::Arc<dyn bleh::framework::transport::Transport>;
     fn author(&self) -> &matrix_sdk::ruma::UserId;
-    fn room(&self) -> &matrix_sdk::room::Joined;
+    fn room_id(&self) -> &matrix_sdk::ruma::RoomId;
     fn original_event(
         &self,
     ) -> &matrix_sdk::ruma::events::SyncMessageEvent<
         matrix_sdk::ruma::events::room::message::RoomMessageEventContent,
     >;
+    /// The root of the `m.thread` the command was sent in, if any
+    fn thread_root(&self) -> Option<&matrix_sdk::ruma::EventId>;
     fn root(&self) -> &bleh::framework::commands::GroupMeta;
+    fn config(&self) -> &bleh::framework::config::BotConfig;
+    fn state(&self) -> &bleh::framework::state::StateMap;
+    /// Values of the group parameters given before the command's name
+    fn group_args(&self) -> &bleh::framework::extractors::GroupArgs;
+    /// The locale to answer in, chosen by the author or for the room
+    fn locale(&self) -> &bleh::framework::i18n::LanguageIdentifier;
+}
+
+async fn send(
+    transport: &Arc<dyn Transport>,
+    kind: MessageKind,
+    event_type: &str,
+    content: serde_json::Value,
+) -> transport::Result<SentMessage> {
+    let event_id = transport.send_event(event_type, content).await?;
+
+    Ok(SentMessage::new(transport.clone(), event_id).with_kind(kind))
+}
+
+async fn react(
+    transport: &Arc<dyn Transport>,
+    kind: MessageKind,
+    event_id: &EventId,
+    key: &str,
+) -> transport::Result<SentMessage> {
+    let content = serde_json::json!({
+        "m.relates_to": {
+            "rel_type": "m.annotation",
+            "event_id": event_id,
+            "key": key,
+        }
+    });
+
+    send(transport, kind, "m.reaction", content).await
+}
+
+async fn edit(
+    transport: &Arc<dyn Transport>,
+    kind: MessageKind,
+    event_id: &EventId,
+    msgtype: MessageType,
+) -> transport::Result<SentMessage> {
+    let new_content = serde_json::to_value(RoomMessageEventContent::new(msgtype))?;
+
+    // clients that don't understand edits show the fallback body
+    let mut content = new_content.clone();
+    for field in ["body", "formatted_body"] {
+        if let Some(text) = new_content[field].as_str() {
+            content[field] = format!("* {}", text).into();
+        }
+    }
+    content["m.new_content"] = new_content;
+    content["m.relates_to"] = serde_json::json!({
+        "rel_type": "m.replace",
+        "event_id": event_id,
+    });
+
+    send(transport, kind, "m.room.message", content).await
+}
+
+/// An event the bot has sent, kept around so it can be updated or cleaned up later
+#[derive(Clone)]
+pub struct SentMessage {
+    pub transport: Arc<dyn Transport>,
+    pub event_id: Box<EventId>,
+    /// The message type `edit_plain` and `edit_html` use
+    pub kind: MessageKind,
+}
+
+impl SentMessage {
+    pub fn new(transport: Arc<dyn Transport>, event_id: Box<EventId>) -> Self {
+        Self {
+            transport,
+            event_id,
+            kind: MessageKind::Text,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: MessageKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub async fn react(&self, key: &str) -> transport::Result<SentMessage> {
+        react(&self.transport, self.kind, &self.event_id, key).await
+    }
+
+    /// Replace the content of this message, the returned handle refers to the
+    /// edit event, keep using this one to edit again
+    pub async fn edit(&self, msgtype: MessageType) -> transport::Result<SentMessage> {
+        edit(&self.transport, self.kind, &self.event_id, msgtype).await
+    }
+
+    pub async fn edit_plain(&self, msg: &str) -> transport::Result<SentMessage> {
+        self.edit(self.kind.plain(msg)).await
+    }
+
+    pub async fn edit_html(&self, plain: &str, html: &str) -> transport::Result<SentMessage> {
+        self.edit(self.kind.html(plain, html)).await
+    }
+
+    pub async fn redact(&self, reason: Option<&str>) -> transport::Result<()> {
+        self.transport.redact(&self.event_id, reason).await
+    }
 }
 
 #[async_trait::async_trait]
 pub trait ContextActions: Context {
-    async fn send(&self, msg: &str) -> matrix_sdk::Result<send_message_event::Response> {
-        let m = RoomMessageEventContent::text_plain(msg);
+    /// Send a message to the room the command was invoked in
+    async fn send_message(&self, msgtype: MessageType) -> transport::Result<SentMessage> {
+        let content = serde_json::to_value(RoomMessageEventContent::new(msgtype))?;
 
-        self.room().send(m, None).await
+        let kind = self.config().message_kind;
+
+        send(self.transport(), kind, "m.room.message", content).await
     }
 
-    async fn reply(&self, msg: &str) -> matrix_sdk::Result<send_message_event::Response> {
-        let m = RoomMessageEventContent::text_reply_plain(
-            msg,
-            &self
-                .original_event()
-                .clone()
-                .into_full_event(self.room().room_id().to_owned()),
-        );
+    /// Reply to the invoking message, staying inside its thread if it was sent in one
+    async fn reply_message(&self, msgtype: MessageType) -> transport::Result<SentMessage> {
+        let original = &self.original_event().event_id;
+        let mut content = serde_json::to_value(RoomMessageEventContent::new(msgtype))?;
+
+        content["m.relates_to"] = match self.thread_root() {
+            Some(thread_root) => serde_json::json!({
+                "rel_type": "m.thread",
+                "event_id": thread_root,
+                "m.in_reply_to": { "event_id": original },
+            }),
+            None => serde_json::json!({
+                "m.in_reply_to": { "event_id": original },
+            }),
+        };
+
+        let kind = self.config().message_kind;
 
-        self.room().send(m, None).await
+        send(self.transport(), kind, "m.room.message", content).await
     }
 
-    async fn send_html(
+    /// React to the invoking message
+    async fn react(&self, key: &str) -> transport::Result<SentMessage> {
+        react(
+            self.transport(),
+            self.config().message_kind,
+            &self.original_event().event_id,
+            key,
+        )
+        .await
+    }
+
+    /// Edit a message previously sent by the bot
+    async fn edit(
         &self,
+        event_id: &EventId,
+        new_content: MessageType,
+    ) -> transport::Result<SentMessage> {
+        edit(
+            self.transport(),
+            self.config().message_kind,
+            event_id,
+            new_content,
+        )
+        .await
+    }
+
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> transport::Result<()> {
+        self.transport().redact(event_id, reason).await
+    }
+
+    /// Upload and send an image, with a thumbnail and blurhash
+    async fn send_image(
+        &self,
+        name: &str,
+        mimetype: &Mime,
+        data: Vec<u8>,
+    ) -> transport::Result<SentMessage> {
+        let msgtype = media::upload_image(self.transport().as_ref(), name, mimetype, data).await?;
+
+        self.send_message(msgtype).await
+    }
+
+    async fn send_file(
+        &self,
+        name: &str,
+        mimetype: &Mime,
+        data: Vec<u8>,
+    ) -> transport::Result<SentMessage> {
+        let msgtype = media::upload_file(self.transport().as_ref(), name, mimetype, data).await?;
+
+        self.send_message(msgtype).await
+    }
+
+    /// The event the invoking message is a reply to
+    fn in_reply_to(&self) -> Option<&EventId> {
+        match &self.original_event().content.relates_to {
+            Some(Relation::Reply { in_reply_to }) => Some(&in_reply_to.event_id),
+            _ => None,
+        }
+    }
+
+    /// Fetch the message the invoking message is a reply to
+    async fn replied_to(&self) -> transport::Result<Option<MessageEvent<RoomMessageEventContent>>> {
+        match self.in_reply_to() {
+            Some(event_id) => self.transport().fetch_message(event_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Download the media attached to a message. Commands are only run from
+    /// `m.text` messages, so media to work on is given by replying to it, see
+    /// `replied_to_media`
+    async fn fetch_media(&self, msgtype: &MessageType) -> transport::Result<Option<Media>> {
+        media::download(self.transport().as_ref(), msgtype).await
+    }
+
+    /// Download the media attached to the message the invoking message is a reply to
+    async fn replied_to_media(&self) -> transport::Result<Option<Media>> {
+        match self.replied_to().await? {
+            Some(event) => self.fetch_media(&event.content.msgtype).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn send_as(&self, kind: MessageKind, msg: &str) -> transport::Result<SentMessage> {
+        self.send_message(kind.plain(msg)).await
+    }
+
+    async fn reply_as(&self, kind: MessageKind, msg: &str) -> transport::Result<SentMessage> {
+        self.reply_message(kind.plain(msg)).await
+    }
+
+    async fn send_html_as(
+        &self,
+        kind: MessageKind,
         plain: &str,
         html: &str,
-    ) -> matrix_sdk::Result<send_message_event::Response> {
-        let m = AnyMessageEventContent::RoomMessage(RoomMessageEventContent::text_html(plain, html));
-
-        self.room().send(m, None).await
+    ) -> transport::Result<SentMessage> {
+        self.send_message(kind.html(plain, html)).await
     }
 
-    async fn reply_html(
+    async fn reply_html_as(
         &self,
+        kind: MessageKind,
         plain: &str,
         html: &str,
-    ) -> matrix_sdk::Result<send_message_event::Response> {
-        let m = AnyMessageEventContent::RoomMessage(RoomMessageEventContent::text_reply_html(
-            plain,
-            html,
-            &self
-                .original_event()
-                .clone()
-                .into_full_event(self.room().room_id().to_owned()),
-        ));
-
-        self.room().send(m, None).await
+    ) -> transport::Result<SentMessage> {
+        self.reply_message(kind.html(plain, html)).await
+    }
+
+    async fn send(&self, msg: &str) -> transport::Result<SentMessage> {
+        self.send_as(self.config().message_kind, msg).await
+    }
+
+    async fn reply(&self, msg: &str) -> transport::Result<SentMessage> {
+        self.reply_as(self.config().message_kind, msg).await
+    }
+
+    async fn send_html(&self, plain: &str, html: &str) -> transport::Result<SentMessage> {
+        self.send_html_as(self.config().message_kind, plain, html)
+            .await
+    }
+
+    async fn reply_html(&self, plain: &str, html: &str) -> transport::Result<SentMessage> {
+        self.reply_html_as(self.config().message_kind, plain, html)
+            .await
+    }
+
+    async fn send_notice(&self, msg: &str) -> transport::Result<SentMessage> {
+        self.send_as(MessageKind::Notice, msg).await
+    }
+
+    async fn reply_notice(&self, msg: &str) -> transport::Result<SentMessage> {
+        self.reply_as(MessageKind::Notice, msg).await
+    }
+
+    async fn send_emote(&self, msg: &str) -> transport::Result<SentMessage> {
+        self.send_as(MessageKind::Emote, msg).await
+    }
+
+    async fn reply_emote(&self, msg: &str) -> transport::Result<SentMessage> {
+        self.reply_as(MessageKind::Emote, msg).await
+    }
+
+    /// The power level of a user in the room the command was invoked in
+    async fn power_level(&self, user_id: &UserId) -> transport::Result<i64> {
+        self.transport().power_level(user_id).await
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.transport().now()
+    }
+
+    /// Translate a message into the locale of the context, see `i18n`
+    fn tr(&self, id: &str, args: &[(&str, FluentValue)]) -> String {
+        match self.state().get::<Arc<Locales>>() {
+            Some(locales) => locales.tr(self.locale(), id, args),
+            None => Locales::builtin().tr(self.locale(), id, args),
+        }
     }
 }
 
@@ -79,31 +340,56 @@ impl<T: Context> ContextActions for T {}
 
 #[derive(Clone)]
 pub struct BaseContext {
-    pub client: Client,
+    pub transport: Arc<dyn Transport>,
     pub author: Box<UserId>,
-    pub room: Joined,
+    pub room_id: Box<RoomId>,
     pub original_event: SyncMessageEvent<RoomMessageEventContent>,
+    pub thread_root: Option<Box<EventId>>,
     pub root: Arc<GroupMeta>,
+    pub config: Arc<BotConfig>,
+    pub state: Arc<StateMap>,
+    pub group_args: Arc<GroupArgs>,
+    pub locale: LanguageIdentifier,
 }
 
 impl Context for BaseContext {
-    fn client(&self) -> &Client {
-        &self.client
+    fn transport(&self) -> &Arc<dyn Transport> {
+        &self.transport
     }
 
     fn author(&self) -> &UserId {
         &self.author
     }
 
-    fn room(&self) -> &Joined {
-        &self.room
+    fn room_id(&self) -> &RoomId {
+        &self.room_id
     }
 
     fn original_event(&self) -> &SyncMessageEvent<RoomMessageEventContent> {
         &self.original_event
     }
 
+    fn thread_root(&self) -> Option<&EventId> {
+        self.thread_root.as_deref()
+    }
+
     fn root(&self) -> &GroupMeta {
         &self.root
     }
+
+    fn config(&self) -> &BotConfig {
+        &self.config
+    }
+
+    fn state(&self) -> &StateMap {
+        &self.state
+    }
+
+    fn group_args(&self) -> &GroupArgs {
+        &self.group_args
+    }
+
+    fn locale(&self) -> &LanguageIdentifier {
+        &self.locale
+    }
 }
@@ -0,0 +1,578 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use matrix_sdk::ruma::{
+    events::{
+        room::message::{MessageType, RoomMessageEventContent, TextMessageEventContent},
+        SyncMessageEvent,
+    },
+    EventId, RoomId,
+};
+
+use crate::framework::{
+    aliases::{self, AliasStore},
+    chain::{self, CaptureTransport, Pipeline},
+    commands::{BoxFuture, CommandError, Group},
+    config::BotConfig,
+    context::{BaseContext, ContextActions},
+    i18n::{self, LocaleStore, Locales},
+    policy::{Policy, PolicyStore},
+    registry::{Registry, Snapshot},
+    state::StateMap,
+    transport::Transport,
+    triggers::Trigger,
+};
+
+/// How long each typing notice lasts, it's refreshed at half of this so it
+/// doesn't lapse between requests
+const TYPING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Keep a typing notice up until `fut` completes
+async fn with_typing<F: Future>(transport: &dyn Transport, fut: F) -> F::Output {
+    let refresh = async {
+        loop {
+            if let Err(err) = transport.typing(Some(TYPING_TIMEOUT)).await {
+                tracing::warn!(?err, "Failed to send typing notice");
+            }
+
+            tokio::time::sleep(TYPING_TIMEOUT / 2).await;
+        }
+    };
+
+    let out = tokio::select! {
+        out = fut => out,
+        _ = refresh => unreachable!(),
+    };
+
+    if let Err(err) = transport.typing(None).await {
+        tracing::warn!(?err, "Failed to clear typing notice");
+    }
+
+    out
+}
+
+/// A message that might contain a command, along with the transport for the
+/// room it was sent in
+pub struct Incoming {
+    pub transport: Arc<dyn Transport>,
+    pub room_id: Box<RoomId>,
+    pub event: SyncMessageEvent<RoomMessageEventContent>,
+    pub thread_root: Option<Box<EventId>>,
+}
+
+/// What's looked up once per message and shared by every command in it
+struct MessageScope<C> {
+    snapshot: Arc<Snapshot<C>>,
+    policy: Option<Policy>,
+    /// The context commands are built from, sending to the room
+    base_ctx: BaseContext,
+}
+
+/// Finds and runs the command in a message, independent of where the message
+/// came from
+pub struct Dispatcher<C> {
+    config: Arc<BotConfig>,
+    state: Arc<StateMap>,
+    registry: Arc<Registry<C>>,
+    policy: Option<Arc<dyn PolicyStore>>,
+    locales: Arc<Locales>,
+    locale_store: Option<Arc<dyn LocaleStore>>,
+    aliases: Option<Arc<dyn AliasStore>>,
+    triggers: Vec<Trigger<C>>,
+    build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
+}
+
+/// The builder methods of `Dispatcher`, also available on everything that
+/// wraps one such as `handlers::Bot`, `repl::Repl` and `testing::TestBot`
+pub trait DispatcherBuilder<C>: Sized {
+    /// The dispatcher being built
+    fn dispatcher_mut(&mut self) -> &mut Dispatcher<C>;
+
+    fn with_config(mut self, config: BotConfig) -> Self {
+        self.dispatcher_mut().config = Arc::new(config);
+        self
+    }
+
+    /// Make a value available to commands through the `State<T>` parameter
+    fn with_state<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        Arc::get_mut(&mut self.dispatcher_mut().state)
+            .expect("State is only added before the dispatcher is shared")
+            .insert(value);
+        self
+    }
+
+    /// Enforce per-room and per-space command policy, the store is also made
+    /// available to the commands in `policy::commands`
+    fn with_policy(mut self, store: Arc<dyn PolicyStore>) -> Self {
+        self.dispatcher_mut().policy = Some(store.clone());
+        self.with_state(store)
+    }
+
+    /// Messages to use instead of the framework's English ones
+    fn with_locales(mut self, locales: Locales) -> Self {
+        let locales = Arc::new(locales);
+        self.dispatcher_mut().locales = locales.clone();
+        self.with_state(locales)
+    }
+
+    /// Let users and rooms choose their locale, the store is also made
+    /// available to the commands in `i18n::commands`
+    fn with_locale_store(mut self, store: Arc<dyn LocaleStore>) -> Self {
+        self.dispatcher_mut().locale_store = Some(store.clone());
+        self.with_state(store)
+    }
+
+    /// Expand aliases defined from chat, the store is also made available to
+    /// the commands in `aliases::commands`
+    fn with_aliases(mut self, store: Arc<dyn AliasStore>) -> Self {
+        self.dispatcher_mut().aliases = Some(store.clone());
+        self.with_state(store)
+    }
+
+    /// Run a command when a message without the prefix matches a pattern,
+    /// see `framework::triggers`
+    fn with_trigger(mut self, trigger: Trigger<C>) -> Self {
+        self.dispatcher_mut().triggers.push(trigger);
+        self
+    }
+}
+
+impl<C> DispatcherBuilder<C> for Dispatcher<C> {
+    fn dispatcher_mut(&mut self) -> &mut Dispatcher<C> {
+        self
+    }
+}
+
+impl<C: Send + Sync + 'static> Dispatcher<C> {
+    pub fn new(
+        commands: Group<C>,
+        build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
+    ) -> Self {
+        let registry = Arc::new(Registry::new(commands));
+        let locales = Arc::new(Locales::new());
+
+        let mut state = StateMap::new();
+        state.insert(registry.clone());
+        state.insert(locales.clone());
+
+        Self {
+            config: Arc::new(BotConfig::default()),
+            state: Arc::new(state),
+            registry,
+            policy: None,
+            locales,
+            locale_store: None,
+            aliases: None,
+            triggers: Vec::new(),
+            build_context,
+        }
+    }
+
+    pub fn config(&self) -> &BotConfig {
+        &self.config
+    }
+
+    /// The command tree, changes to it apply to the next message dispatched
+    pub fn registry(&self) -> &Arc<Registry<C>> {
+        &self.registry
+    }
+
+    /// The policy for the room a message was sent in, if it can't be loaded
+    /// every command is allowed
+    async fn load_policy(&self, store: &dyn PolicyStore, incoming: &Incoming) -> Policy {
+        let spaces = match incoming.transport.parent_spaces().await {
+            Ok(spaces) => spaces,
+            Err(err) => {
+                tracing::warn!(?err, room = %incoming.room_id, "Failed to fetch parent spaces");
+                Vec::new()
+            }
+        };
+
+        match Policy::load(store, &incoming.room_id, spaces).await {
+            Ok(policy) => policy,
+            Err(err) => {
+                tracing::warn!(?err, room = %incoming.room_id, "Failed to load command policy");
+                Policy::default()
+            }
+        }
+    }
+
+    /// Look up everything commands in a message share
+    async fn scope(&self, incoming: &Incoming) -> MessageScope<C> {
+        let snapshot = self.registry.snapshot();
+
+        let policy = match &self.policy {
+            Some(store) => Some(self.load_policy(store.as_ref(), incoming).await),
+            None => None,
+        };
+
+        let root = match &policy {
+            Some(policy) => Arc::new(policy.filter_meta(&snapshot.meta)),
+            None => snapshot.meta.clone(),
+        };
+
+        let locale = match &self.locale_store {
+            Some(store) => {
+                i18n::choose(
+                    &self.locales,
+                    store.as_ref(),
+                    &incoming.event.sender,
+                    &incoming.room_id,
+                )
+                .await
+            }
+            None => self.locales.default_locale().clone(),
+        };
+
+        MessageScope {
+            snapshot,
+            policy,
+            base_ctx: BaseContext {
+                transport: incoming.transport.clone(),
+                author: incoming.event.sender.clone(),
+                room_id: incoming.room_id.clone(),
+                original_event: incoming.event.clone(),
+                thread_root: incoming.thread_root.clone(),
+                root,
+                config: self.config.clone(),
+                state: self.state.clone(),
+                group_args: Arc::default(),
+                locale,
+            },
+        }
+    }
+
+    /// Find and run a single command, `text` starting with the prefix.
+    /// Returns `None` if it isn't a command or is disabled.
+    async fn run_step(
+        &self,
+        scope: &MessageScope<C>,
+        transport: Arc<dyn Transport>,
+        text: &str,
+        first: bool,
+    ) -> Option<Result<(), CommandError>> {
+        let rest = text.strip_prefix(self.config.prefix.as_str())?;
+
+        let base_ctx = BaseContext {
+            transport: transport.clone(),
+            group_args: Arc::default(),
+            ..scope.base_ctx.clone()
+        };
+
+        let ctx = (self.build_context)(base_ctx.clone());
+
+        // disabled commands are ignored as if they didn't exist, even when
+        // what follows them doesn't parse
+        let disabled = |path: &[&str], policy_exempt: bool| match &scope.policy {
+            Some(policy) => !policy_exempt && !policy.allows(path),
+            None => false,
+        };
+
+        let found = match scope
+            .snapshot
+            .commands
+            .find_command_with_params(&ctx, rest)?
+        {
+            Ok(found) if disabled(&found.path, found.command.meta.policy_exempt) => return None,
+            Ok(found) => found,
+            Err(not_parsed) if disabled(&not_parsed.path, not_parsed.policy_exempt) => return None,
+            Err(not_parsed) => return Some(Err(not_parsed.error)),
+        };
+
+        if self.config.read_receipts && first {
+            let event_id = &base_ctx.original_event.event_id;
+
+            if let Err(err) = transport.read_receipt(event_id).await {
+                tracing::warn!(?err, room = %base_ctx.room_id, "Failed to send read receipt");
+            }
+        }
+
+        let cmd = found.command;
+        let group_args = base_ctx.group_args.clone();
+
+        let invocation = async move {
+            for param in found.group_params {
+                group_args.push(param.resolve(&ctx).await?);
+            }
+
+            cmd.run(ctx, found.parsed).await
+        };
+
+        let result = if cmd.typing.unwrap_or(self.config.typing) {
+            with_typing(transport.as_ref(), invocation).await
+        } else {
+            invocation.await
+        };
+
+        Some(result)
+    }
+
+    async fn report(&self, scope: &MessageScope<C>, error: &CommandError) {
+        let ctx = &scope.base_ctx;
+        let _ = ctx.reply(&error.localise(&self.locales, &ctx.locale)).await;
+    }
+
+    /// Replace the aliases in a chain with what they stand for, an alias
+    /// expanding to a chain taking the place of the command it replaced.
+    /// `count` is the number of commands expanded so far, expanding stops as
+    /// soon as there are more than `BotConfig::max_chain_length` so aliases
+    /// using each other several times can't make it look up a huge number.
+    fn expand<'a>(
+        &'a self,
+        scope: &'a MessageScope<C>,
+        store: &'a dyn AliasStore,
+        chain: Vec<Pipeline<'a>>,
+        depth: usize,
+        count: &'a mut usize,
+    ) -> BoxFuture<'a, Result<Vec<Vec<String>>, CommandError>> {
+        Box::pin(async move {
+            let mut out: Vec<Vec<String>> = Vec::new();
+
+            for pipeline in chain {
+                out.push(Vec::new());
+
+                for command in pipeline {
+                    let mut expanded = self
+                        .expand_command(scope, store, command, depth, count)
+                        .await?
+                        .into_iter();
+
+                    // the first part continues the pipeline the alias was in
+                    // and the last part is continued by the rest of it
+                    if let Some(first) = expanded.next() {
+                        out.last_mut().unwrap().extend(first);
+                    }
+                    out.extend(expanded);
+                }
+            }
+
+            Ok(out)
+        })
+    }
+
+    async fn expand_command(
+        &self,
+        scope: &MessageScope<C>,
+        store: &dyn AliasStore,
+        command: &str,
+        depth: usize,
+        count: &mut usize,
+    ) -> Result<Vec<Vec<String>>, CommandError> {
+        let max = self.config.max_chain_length;
+        let mut unchanged = || {
+            *count += 1;
+
+            if *count > max {
+                Err(CommandError::ChainTooLong { max })
+            } else {
+                Ok(vec![vec![command.to_owned()]])
+            }
+        };
+
+        let prefix = self.config.prefix.as_str();
+        let rest = command.strip_prefix(prefix).unwrap_or(command).trim_start();
+        let (name, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+
+        // commands win over aliases, even those the room has disabled
+        if name.is_empty() || scope.snapshot.meta.get(name).is_some() {
+            return unchanged();
+        }
+
+        let ctx = &scope.base_ctx;
+        let alias = match aliases::find(store, &ctx.room_id, &ctx.author, name).await {
+            Ok(Some(alias)) => alias,
+            Ok(None) => return unchanged(),
+            Err(err) => {
+                tracing::warn!(?err, room = %ctx.room_id, name, "Failed to look up alias");
+                return unchanged();
+            }
+        };
+
+        let max_depth = self.config.max_alias_depth;
+        if depth >= max_depth {
+            return Err(CommandError::AliasTooDeep {
+                name: name.to_owned(),
+                max: max_depth,
+            });
+        }
+
+        let expanded = format!("{}{}", prefix, aliases::substitute(&alias.expansion, args));
+        let chain = chain::split(&expanded, prefix).expect("The expansion starts with the prefix");
+
+        self.expand(scope, store, chain, depth + 1, count).await
+    }
+
+    /// Run the commands in a pipeline, feeding the output of each into the
+    /// next
+    async fn run_pipeline(
+        &self,
+        scope: &MessageScope<C>,
+        pipeline: &[String],
+        step: &mut usize,
+    ) -> Result<(), CommandError> {
+        let mut input = String::new();
+
+        for (i, command) in pipeline.iter().enumerate() {
+            *step += 1;
+
+            let text = if input.is_empty() {
+                command.to_string()
+            } else {
+                format!("{} {}", command, input)
+            };
+
+            let capture = (i + 1 < pipeline.len())
+                .then(|| Arc::new(CaptureTransport::new(scope.base_ctx.transport.clone())));
+            let transport = match &capture {
+                Some(capture) => capture.clone() as Arc<dyn Transport>,
+                None => scope.base_ctx.transport.clone(),
+            };
+
+            match self.run_step(scope, transport, &text, *step == 1).await {
+                Some(Ok(())) => {}
+                Some(Err(error)) => {
+                    return Err(CommandError::Step {
+                        step: *step,
+                        command: command.to_string(),
+                        error: Box::new(error),
+                    })
+                }
+                None => {
+                    return Err(CommandError::UnknownStep {
+                        step: *step,
+                        command: command.to_string(),
+                    })
+                }
+            }
+
+            input = capture.map(|c| c.output()).unwrap_or_default();
+        }
+
+        Ok(())
+    }
+
+    /// Run every trigger matching a message that isn't a command, is off
+    /// cooldown and allowed by the room's policy, returns `None` if none did
+    async fn run_triggers(
+        &self,
+        incoming: &Incoming,
+        body: &str,
+    ) -> Option<Result<(), CommandError>> {
+        let matching = self
+            .triggers
+            .iter()
+            .filter_map(|t| Some((t, t.input(body)?)))
+            .collect::<Vec<_>>();
+
+        if matching.is_empty() {
+            return None;
+        }
+
+        let scope = self.scope(incoming).await;
+        let now = incoming.transport.now();
+        let mut result = None;
+
+        for (trigger, input) in matching {
+            let path = trigger.path.iter().map(String::as_str).collect::<Vec<_>>();
+            let allowed = scope.policy.as_ref().map_or(true, |p| p.allows(&path));
+
+            if !allowed {
+                continue;
+            }
+
+            let ctx = (self.build_context)(BaseContext {
+                group_args: Arc::default(),
+                ..scope.base_ctx.clone()
+            });
+
+            // only a command that runs uses up the cooldown
+            let outcome = match trigger.command.parse(&ctx, &input) {
+                Ok(_) if !trigger.try_fire(incoming.room_id.as_str(), now) => continue,
+                Ok(parsed) => trigger.command.run(ctx, parsed).await,
+                Err(error) => Err(error),
+            };
+
+            if let Err(error) = &outcome {
+                tracing::warn!(%error, trigger = %trigger.name, room = %incoming.room_id, "Trigger failed");
+            }
+
+            // the first failure is the one worth returning
+            if !matches!(result, Some(Err(_))) {
+                result = Some(outcome);
+            }
+        }
+
+        result
+    }
+
+    /// Run the commands contained in a message, errors are reported to the
+    /// room before being returned. Messages without the prefix are given to
+    /// the triggers instead. Returns `None` if the message isn't a command,
+    /// or its first command doesn't exist, and no trigger ran.
+    pub async fn dispatch(&self, incoming: Incoming) -> Option<Result<(), CommandError>> {
+        let msg_body = match &incoming.event.content.msgtype {
+            MessageType::Text(TextMessageEventContent { body: msg_body, .. }) => msg_body.as_str(),
+            _ => return None,
+        };
+
+        if !msg_body.starts_with(self.config.prefix.as_str()) {
+            return self.run_triggers(&incoming, msg_body).await;
+        }
+
+        let split = if self.config.max_chain_length > 1 {
+            chain::split(msg_body, &self.config.prefix)?
+        } else {
+            vec![vec![msg_body]]
+        };
+
+        let scope = self.scope(&incoming).await;
+
+        let chain = match &self.aliases {
+            Some(store) => match self.expand(&scope, store.as_ref(), split, 0, &mut 0).await {
+                Ok(chain) => chain,
+                Err(error) => {
+                    self.report(&scope, &error).await;
+                    return Some(Err(error));
+                }
+            },
+            None => split
+                .into_iter()
+                .map(|p| p.into_iter().map(str::to_owned).collect())
+                .collect(),
+        };
+
+        if let [command] = chain.concat().as_slice() {
+            let result = self
+                .run_step(&scope, incoming.transport.clone(), command, true)
+                .await?;
+
+            if let Err(error) = &result {
+                self.report(&scope, error).await;
+            }
+
+            return Some(result);
+        }
+
+        let max = self.config.max_chain_length;
+        if chain.iter().map(Vec::len).sum::<usize>() > max {
+            let error = CommandError::ChainTooLong { max };
+            self.report(&scope, &error).await;
+            return Some(Err(error));
+        }
+
+        let mut step = 0;
+
+        for pipeline in &chain {
+            match self.run_pipeline(&scope, pipeline, &mut step).await {
+                Ok(()) => {}
+                // a message that doesn't start with a command isn't for the bot
+                Err(CommandError::UnknownStep { step: 1, .. }) => return None,
+                Err(error) => {
+                    self.report(&scope, &error).await;
+                    return Some(Err(error));
+                }
+            }
+        }
+
+        Some(Ok(()))
+    }
+}
@@ -0,0 +1,187 @@
+//! Reference documentation generated from a command tree, for wikis and for
+//! tools such as client-side autocompletion.
+
+use std::fmt::Write;
+
+use crate::framework::commands::{CommandMeta, GroupMeta, GroupOrCommandMeta};
+
+/// A command along with where it is in the tree
+#[derive(serde::Serialize)]
+struct CommandDoc<'a> {
+    /// Space separated, empty for the root group's fallback
+    path: String,
+    usages: Vec<String>,
+    #[serde(flatten)]
+    meta: &'a CommandMeta,
+}
+
+/// A command's path and how to invoke it
+struct Found<'a> {
+    path: String,
+    invocation: String,
+    meta: &'a CommandMeta,
+}
+
+/// Every command in the tree with its path, depth first and sorted by name.
+/// A group's fallback comes before its contents under the group's path.
+fn walk<'a>(
+    prefix: &str,
+    root: &GroupMeta,
+    path: &mut Vec<&'a str>,
+    meta: &'a GroupMeta,
+    out: &mut Vec<Found<'a>>,
+) {
+    if let Some(fallback) = &meta.fallback {
+        out.push(Found {
+            path: path.join(" "),
+            invocation: root.fallback_invocation(prefix, path),
+            meta: fallback,
+        });
+    }
+
+    for (name, child) in &meta.inner {
+        path.push(name);
+
+        match child {
+            GroupOrCommandMeta::Command(c) => out.push(Found {
+                path: path.join(" "),
+                invocation: root.invocation(prefix, path),
+                meta: c,
+            }),
+            GroupOrCommandMeta::Group(g) => walk(prefix, root, path, g, out),
+        }
+
+        path.pop();
+    }
+}
+
+fn commands<'a>(meta: &'a GroupMeta, prefix: &str) -> Vec<Found<'a>> {
+    let mut out = Vec::new();
+    walk(prefix, meta, &mut Vec::new(), meta, &mut out);
+    out
+}
+
+/// The tree as JSON: `tree` mirrors `GroupMeta`, and `commands` lists every
+/// command with its path and usage lines
+pub fn json(meta: &GroupMeta, prefix: &str) -> serde_json::Value {
+    let commands = commands(meta, prefix)
+        .into_iter()
+        .map(|found| CommandDoc {
+            usages: found.meta.usage_lines(&found.invocation),
+            path: found.path,
+            meta: found.meta,
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "prefix": prefix,
+        "tree": meta,
+        "commands": commands,
+    })
+}
+
+fn markdown_command(out: &mut String, invocation: &str, meta: &CommandMeta) {
+    let _ = writeln!(out, "### `{}`\n", invocation.trim_end());
+
+    if let Some(note) = &meta.deprecated {
+        let _ = writeln!(out, "**Deprecated:** {}\n", note);
+    }
+
+    if let Some(description) = &meta.description {
+        let _ = writeln!(out, "{}\n", description);
+    }
+
+    if let Some(long_description) = &meta.long_description {
+        let _ = writeln!(out, "{}\n", long_description);
+    }
+
+    out.push_str("Usage:\n\n");
+    for usage in meta.usage_lines(invocation) {
+        let _ = writeln!(out, "- `{}`", usage);
+    }
+    out.push('\n');
+
+    let params = meta.documented_params().collect::<Vec<_>>();
+    if !params.is_empty() {
+        out.push_str("Parameters:\n\n");
+        for param in params {
+            let _ = writeln!(out, "- {}", param);
+        }
+        out.push('\n');
+    }
+
+    if !meta.aliases.is_empty() {
+        let _ = writeln!(out, "Aliases: {}\n", meta.aliases.join(", "));
+    }
+
+    if !meta.examples.is_empty() {
+        out.push_str("Examples:\n\n");
+        for example in &meta.examples {
+            let _ = writeln!(out, "- `{}`", example);
+        }
+        out.push('\n');
+    }
+
+    if let Some(category) = &meta.category {
+        let _ = writeln!(out, "Category: {}\n", category);
+    }
+
+    if let Some(since) = &meta.since {
+        let _ = writeln!(out, "Since {}\n", since);
+    }
+}
+
+fn markdown_group<'a>(
+    out: &mut String,
+    prefix: &str,
+    root: &GroupMeta,
+    path: &mut Vec<&'a str>,
+    meta: &'a GroupMeta,
+) {
+    if !path.is_empty() {
+        let invocation = root.invocation(prefix, path);
+        let _ = writeln!(out, "## `{}`\n", invocation);
+
+        if let Some(description) = &meta.description {
+            let _ = writeln!(out, "{}\n", description);
+        }
+
+        let _ = writeln!(out, "Usage: `{}`\n", meta.usage_line(&invocation));
+    }
+
+    if let Some(fallback) = &meta.fallback {
+        markdown_command(out, &root.fallback_invocation(prefix, path), fallback);
+    }
+
+    // commands directly in the group come before its subgroups
+    for (name, child) in &meta.inner {
+        if let GroupOrCommandMeta::Command(c) = child {
+            path.push(name);
+            markdown_command(out, &root.invocation(prefix, path), c);
+            path.pop();
+        }
+    }
+
+    for (name, child) in &meta.inner {
+        if let GroupOrCommandMeta::Group(g) = child {
+            path.push(name);
+            markdown_group(out, prefix, root, path, g);
+            path.pop();
+        }
+    }
+}
+
+/// The tree as a Markdown reference page
+pub fn markdown(meta: &GroupMeta, prefix: &str) -> String {
+    let mut out = String::from("# Commands\n\n");
+
+    if let Some(description) = &meta.description {
+        let _ = writeln!(out, "{}\n", description);
+    }
+
+    markdown_group(&mut out, prefix, meta, &mut Vec::new(), meta);
+
+    out.truncate(out.trim_end().len());
+    out.push('\n');
+    out
+}
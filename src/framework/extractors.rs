@@ -0,0 +1,258 @@
+use std::{error::Error, sync::Mutex};
+
+use matrix_sdk::ruma::{
+    events::room::message::{
+        FileMessageEventContent, ImageMessageEventContent, MessageType, TextMessageEventContent,
+    },
+    EventId, MxcUri, RoomAliasId, RoomId, UserId,
+};
+
+use crate::framework::{
+    commands::{BoxFuture, GroupValue, Parameter, ResolveError},
+    context::{Context, ContextActions},
+};
+
+/// The content of a message a command was sent in reply to
+#[derive(Clone, Debug)]
+pub enum ReplyContent {
+    Text(TextMessageEventContent),
+    Image(ImageMessageEventContent),
+    File(FileMessageEventContent),
+    Other(MessageType),
+}
+
+impl From<MessageType> for ReplyContent {
+    fn from(msgtype: MessageType) -> Self {
+        match msgtype {
+            MessageType::Text(c) => ReplyContent::Text(c),
+            MessageType::Image(c) => ReplyContent::Image(c),
+            MessageType::File(c) => ReplyContent::File(c),
+            other => ReplyContent::Other(other),
+        }
+    }
+}
+
+/// The message the command was sent in reply to, fetched from the room before
+/// the command runs. Commands taking this can only be invoked by replying to
+/// a message.
+#[derive(Clone, Debug)]
+pub struct ReplyTarget {
+    pub event_id: Box<EventId>,
+    sender: Box<UserId>,
+    content: ReplyContent,
+}
+
+impl ReplyTarget {
+    pub fn sender(&self) -> &UserId {
+        &self.sender
+    }
+
+    pub fn content(&self) -> &ReplyContent {
+        &self.content
+    }
+
+    pub fn into_content(self) -> ReplyContent {
+        self.content
+    }
+}
+
+impl<C: Context> Parameter<C> for ReplyTarget {
+    const INFO: &'static str = "ReplyTarget";
+    const VISIBLE: bool = false;
+
+    /// The id of the message replied to
+    type Parsed = Box<EventId>;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Box<EventId>), Box<dyn Error + 'a>> {
+        let event_id = ctx
+            .in_reply_to()
+            .ok_or("This command must be sent as a reply to a message")?
+            .to_owned();
+
+        Ok((input, event_id))
+    }
+
+    fn resolve<'c>(event_id: Box<EventId>, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move {
+            let event = ctx
+                .transport()
+                .fetch_message(&event_id)
+                .await?
+                .ok_or("The message being replied to isn't a message I can read")?;
+
+            Ok(ReplyTarget {
+                event_id,
+                sender: event.sender,
+                content: event.content.msgtype.into(),
+            })
+        })
+    }
+}
+
+/// A room as given to a command, before any alias is resolved
+#[derive(Clone, Debug)]
+pub enum RoomInput {
+    Id(Box<RoomId>),
+    Alias(Box<RoomAliasId>),
+}
+
+/// A room given by id (`!abc:example.org`) or alias (`#room:example.org`),
+/// aliases are resolved through the homeserver
+#[derive(Clone, Debug)]
+pub struct RoomRef {
+    /// The alias the room was given by, if it was
+    pub alias: Option<Box<RoomAliasId>>,
+    room_id: Box<RoomId>,
+}
+
+impl RoomRef {
+    pub fn room_id(&self) -> &RoomId {
+        &self.room_id
+    }
+}
+
+impl<C: Context> Parameter<C> for RoomRef {
+    const INFO: &'static str = "Room";
+    const VISIBLE: bool = true;
+
+    type Parsed = RoomInput;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, RoomInput), Box<dyn Error + 'a>> {
+        let (input, room) = <String as Parameter<C>>::parse(ctx, input)?;
+
+        let room = if room.starts_with('#') {
+            RoomInput::Alias(RoomAliasId::parse(room)?)
+        } else {
+            RoomInput::Id(RoomId::parse(room)?)
+        };
+
+        Ok((input, room))
+    }
+
+    fn resolve<'c>(room: RoomInput, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move {
+            match room {
+                RoomInput::Id(room_id) => Ok(RoomRef {
+                    alias: None,
+                    room_id,
+                }),
+                RoomInput::Alias(alias) => {
+                    let room_id = ctx.transport().resolve_room_alias(&alias).await?;
+
+                    Ok(RoomRef {
+                        alias: Some(alias),
+                        room_id,
+                    })
+                }
+            }
+        })
+    }
+}
+
+/// A user id, along with their global profile fetched from the homeserver
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub user_id: Box<UserId>,
+    display_name: Option<String>,
+    avatar_url: Option<Box<MxcUri>>,
+}
+
+impl Profile {
+    pub fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    pub fn avatar_url(&self) -> Option<&MxcUri> {
+        self.avatar_url.as_deref()
+    }
+}
+
+impl<C: Context> Parameter<C> for Profile {
+    const INFO: &'static str = "User";
+    const VISIBLE: bool = true;
+
+    type Parsed = Box<UserId>;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Box<UserId>), Box<dyn Error + 'a>> {
+        let (input, user_id) = <String as Parameter<C>>::parse(ctx, input)?;
+
+        Ok((input, UserId::parse(user_id)?))
+    }
+
+    fn resolve<'c>(user_id: Box<UserId>, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move {
+            let (display_name, avatar_url) = ctx.transport().profile(&user_id).await?;
+
+            Ok(Profile {
+                user_id,
+                display_name,
+                avatar_url,
+            })
+        })
+    }
+}
+
+/// The values of the group parameters given before the command's name
+#[derive(Default)]
+pub struct GroupArgs {
+    values: Mutex<Vec<GroupValue>>,
+}
+
+impl GroupArgs {
+    pub fn push(&self, value: GroupValue) {
+        self.values.lock().unwrap().push(value);
+    }
+
+    /// The value of the innermost group parameter of type `T`
+    pub fn get<T: Clone + 'static>(&self) -> Option<T> {
+        self.values
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find_map(|v| v.downcast_ref::<T>())
+            .cloned()
+    }
+}
+
+/// The value of a parameter taken by one of the groups the command is in, `T`
+/// is the parameter type the group declared, e.g. `Named<RoomRef, "room">`
+pub struct GroupArg<T>(pub T);
+
+impl<C: Context, T: Clone + Send + Sync + 'static> Parameter<C> for GroupArg<T> {
+    const INFO: &'static str = std::any::type_name::<T>();
+    const VISIBLE: bool = false;
+
+    type Parsed = Self;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+        let value = ctx.group_args().get::<T>().ok_or_else(|| {
+            format!(
+                "None of the groups this command is in take a {}",
+                std::any::type_name::<T>()
+            )
+        })?;
+
+        Ok((input, GroupArg(value)))
+    }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
+}
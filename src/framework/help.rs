@@ -0,0 +1,479 @@
+//! The `help` command, added to a group with `GroupBuilder::with_help`.
+//!
+//! Help is rendered with the askama templates in `templates/` by default. To
+//! restyle it without recompiling, load templates from a directory with
+//! `DirectoryRenderer`, or implement `HelpRenderer`, and add it to the bot's
+//! state as an `Arc<dyn HelpRenderer>`.
+//!
+//! Descriptions are translated with the `command-<path>` and `group-<path>`
+//! messages of the locale help is asked for in, and headings with the
+//! `help-label-*` messages, see `i18n`.
+
+use std::{path::Path, sync::Arc};
+
+use matrix_sdk::ruma::RoomId;
+
+use crate::framework::{
+    commands::{
+        cmd, CommandMeta, ErasedCommand, GroupMeta, GroupOrCommandMeta, GroupOrCommandMetaRef,
+        Named,
+    },
+    context::{Context, ContextActions},
+    i18n::{self, LanguageIdentifier, Locales},
+};
+
+/// The headings and other fixed text of help, from the `help-label-*` messages
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HelpLabels {
+    pub command: String,
+    pub group: String,
+    pub usage: String,
+    pub params: String,
+    pub no_params: String,
+    pub aliases: String,
+    pub description: String,
+    pub examples: String,
+    pub category: String,
+    pub deprecated: String,
+    pub since: String,
+    pub subcommands: String,
+    pub commands: String,
+    pub no_subcommands: String,
+}
+
+impl HelpLabels {
+    pub fn new(locales: &Locales, locale: &LanguageIdentifier) -> Self {
+        let tr = |name: &str| locales.tr(locale, &format!("help-label-{}", name), &[]);
+
+        Self {
+            command: tr("command"),
+            group: tr("group"),
+            usage: tr("usage"),
+            params: tr("params"),
+            no_params: tr("no-params"),
+            aliases: tr("aliases"),
+            description: tr("description"),
+            examples: tr("examples"),
+            category: tr("category"),
+            deprecated: tr("deprecated"),
+            since: tr("since"),
+            subcommands: tr("subcommands"),
+            commands: tr("commands"),
+            no_subcommands: tr("no-subcommands"),
+        }
+    }
+}
+
+/// Everything shown in the help for a single command
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommandHelp {
+    /// The locale the help is in, `en-US` until localised
+    pub locale: String,
+    pub labels: HelpLabels,
+    /// The path of the command, space separated
+    pub name: String,
+    /// How to invoke each of the command's signatures
+    pub usages: Vec<String>,
+    pub aliases: Vec<String>,
+    /// Visible parameters of the first signature with their documentation
+    pub params: Vec<String>,
+    pub description: Option<String>,
+    pub long_description: Option<String>,
+    pub examples: Vec<String>,
+    pub category: Option<String>,
+    pub since: Option<String>,
+    pub deprecated: Option<String>,
+}
+
+impl CommandHelp {
+    /// The help for the command at `path` in `root`, with `labels` already in
+    /// the locale it'll be localised to
+    pub fn new(
+        prefix: &str,
+        root: &GroupMeta,
+        path: &[&str],
+        meta: &CommandMeta,
+        labels: HelpLabels,
+    ) -> Self {
+        Self {
+            locale: "en-US".to_owned(),
+            labels,
+            name: path.join(" "),
+            usages: meta.usage_lines(&root.invocation(prefix, path)),
+            aliases: meta.aliases.clone(),
+            params: meta.documented_params().collect(),
+            description: meta.description.clone(),
+            long_description: meta.long_description.clone(),
+            examples: meta.examples.clone(),
+            category: meta.category.clone(),
+            since: meta.since.clone(),
+            deprecated: meta.deprecated.clone(),
+        }
+    }
+
+    /// Translate the descriptions into `locale` where there are messages for
+    /// them
+    pub fn localise(mut self, locales: &Locales, locale: &LanguageIdentifier) -> Self {
+        let path = self.name.split_whitespace().collect::<Vec<_>>();
+        let id = i18n::message_id("command", &path);
+
+        self.description = locales.get(locale, &id, None).or(self.description);
+        self.long_description = locales
+            .get(locale, &id, Some("long"))
+            .or(self.long_description);
+        self.locale = locale.to_string();
+        self
+    }
+}
+
+/// A line in the overview of a group
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HelpEntry {
+    /// How deeply nested the entry is below the group the overview is of
+    pub depth: usize,
+    /// The message translating the description
+    pub message_id: String,
+    pub usage: String,
+    pub description: Option<String>,
+}
+
+impl HelpEntry {
+    /// Two spaces per level of nesting, for plain text
+    pub fn indent(&self) -> String {
+        "  ".repeat(self.depth)
+    }
+}
+
+/// Everything shown in the help for a group
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct GroupHelp {
+    /// The locale the help is in, `en-US` until localised
+    pub locale: String,
+    pub labels: HelpLabels,
+    /// The path of the group, space separated and empty for the root
+    pub name: String,
+    pub usage: String,
+    /// The group's own parameters, given before the name of a subcommand
+    pub params: Vec<String>,
+    pub description: Option<String>,
+    /// Every command and group below this one, sorted by name with each
+    /// group's contents following it
+    pub entries: Vec<HelpEntry>,
+}
+
+impl GroupHelp {
+    /// The help for the group at `path` in `root`, listing the names
+    /// fallbacks answer to in `room_id` if given. `labels` are already in the
+    /// locale it'll be localised to.
+    pub fn new(
+        prefix: &str,
+        room_id: Option<&RoomId>,
+        root: &GroupMeta,
+        path: &[&str],
+        meta: &GroupMeta,
+        labels: HelpLabels,
+    ) -> Self {
+        let mut entries = Vec::new();
+        overview(
+            prefix,
+            room_id,
+            root,
+            &mut path.to_vec(),
+            meta,
+            0,
+            &mut entries,
+        );
+
+        Self {
+            locale: "en-US".to_owned(),
+            labels,
+            name: path.join(" "),
+            usage: meta.usage_line(&root.invocation(prefix, path)),
+            params: meta.visible_params().map(str::to_owned).collect(),
+            description: meta.description.clone(),
+            entries,
+        }
+    }
+
+    /// Translate the descriptions into `locale` where there are messages for
+    /// them
+    pub fn localise(mut self, locales: &Locales, locale: &LanguageIdentifier) -> Self {
+        let path = self.name.split_whitespace().collect::<Vec<_>>();
+
+        self.description = locales
+            .get(locale, &i18n::message_id("group", &path), None)
+            .or(self.description);
+
+        for entry in &mut self.entries {
+            if let Some(description) = locales.get(locale, &entry.message_id, None) {
+                entry.description = Some(description);
+            }
+        }
+
+        self.locale = locale.to_string();
+        self
+    }
+}
+
+/// List the contents of a group, recursing into subgroups
+fn overview<'a>(
+    prefix: &str,
+    room_id: Option<&RoomId>,
+    root: &GroupMeta,
+    path: &mut Vec<&'a str>,
+    meta: &'a GroupMeta,
+    depth: usize,
+    out: &mut Vec<HelpEntry>,
+) {
+    if let Some(fallback) = &meta.fallback {
+        let entry = |usage| HelpEntry {
+            depth,
+            message_id: i18n::message_id("command", path),
+            usage,
+            description: fallback.description.clone(),
+        };
+
+        match (&fallback.names, room_id) {
+            // fallbacks standing in for commands added from chat list those
+            (Some(names), Some(room_id)) => {
+                out.extend(names.names(room_id).into_iter().map(|name| {
+                    let path = path
+                        .iter()
+                        .copied()
+                        .chain([name.as_str()])
+                        .collect::<Vec<_>>();
+                    entry(root.invocation(prefix, &path))
+                }))
+            }
+            _ => out.extend(
+                fallback
+                    .usage_lines(&root.fallback_invocation(prefix, path))
+                    .into_iter()
+                    .map(entry),
+            ),
+        }
+    }
+
+    for (name, child) in &meta.inner {
+        path.push(name);
+
+        match child {
+            GroupOrCommandMeta::Command(c) => {
+                out.extend(
+                    c.usage_lines(&root.invocation(prefix, path))
+                        .into_iter()
+                        .map(|usage| HelpEntry {
+                            depth,
+                            message_id: i18n::message_id("command", path),
+                            usage,
+                            description: c.description.clone(),
+                        }),
+                );
+            }
+            GroupOrCommandMeta::Group(g) => {
+                out.push(HelpEntry {
+                    depth,
+                    message_id: i18n::message_id("group", path),
+                    usage: g.usage_line(&root.invocation(prefix, path)),
+                    description: g.description.clone(),
+                });
+                overview(prefix, room_id, root, path, g, depth + 1, out);
+            }
+        }
+
+        path.pop();
+    }
+}
+
+/// Help rendered as plain text and HTML
+pub struct RenderedHelp {
+    pub plain: String,
+    pub html: String,
+}
+
+/// Turns help into messages, add an `Arc<dyn HelpRenderer>` to the bot's
+/// state to replace the default templates
+pub trait HelpRenderer: Send + Sync + 'static {
+    fn render_command(&self, help: &CommandHelp) -> RenderedHelp;
+
+    fn render_group(&self, help: &GroupHelp) -> RenderedHelp;
+}
+
+#[derive(askama::Template)]
+#[template(path = "command_help.html")]
+struct HtmlCommandHelpTemplate<'a> {
+    help: &'a CommandHelp,
+}
+
+#[derive(askama::Template)]
+#[template(path = "command_help.txt")]
+struct PlainCommandHelpTemplate<'a> {
+    help: &'a CommandHelp,
+}
+
+#[derive(askama::Template)]
+#[template(path = "group_help.html")]
+struct HtmlGroupHelpTemplate<'a> {
+    help: &'a GroupHelp,
+}
+
+#[derive(askama::Template)]
+#[template(path = "group_help.txt")]
+struct PlainGroupHelpTemplate<'a> {
+    help: &'a GroupHelp,
+}
+
+/// Renders help with the templates in `templates/`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TemplateRenderer;
+
+impl HelpRenderer for TemplateRenderer {
+    fn render_command(&self, help: &CommandHelp) -> RenderedHelp {
+        use askama::Template;
+
+        RenderedHelp {
+            plain: PlainCommandHelpTemplate { help }.render().unwrap(),
+            html: HtmlCommandHelpTemplate { help }.render().unwrap(),
+        }
+    }
+
+    fn render_group(&self, help: &GroupHelp) -> RenderedHelp {
+        use askama::Template;
+
+        RenderedHelp {
+            plain: PlainGroupHelpTemplate { help }.render().unwrap(),
+            html: HtmlGroupHelpTemplate { help }.render().unwrap(),
+        }
+    }
+}
+
+/// Renders help with Tera templates loaded when the bot starts, so operators
+/// can restyle help without recompiling. The directory holds any of
+/// `command_help.html`, `command_help.txt`, `group_help.html` and
+/// `group_help.txt`, optionally in subdirectories named after a locale to be
+/// preferred for it, such as `de/group_help.html`. Templates get the help as
+/// `help`, with the same fields as the built in ones, and any that are missing
+/// or fail to render fall back to them.
+pub struct DirectoryRenderer {
+    tera: tera::Tera,
+}
+
+impl DirectoryRenderer {
+    pub fn load(dir: impl AsRef<Path>) -> Result<Self, tera::Error> {
+        let glob = dir.as_ref().join("**").join("*");
+        let tera = tera::Tera::new(&glob.to_string_lossy())?;
+
+        Ok(Self { tera })
+    }
+
+    fn render<T: serde::Serialize>(&self, locale: &str, name: &str, help: &T) -> Option<String> {
+        let localised = format!("{}/{}", locale, name);
+        let template = [localised.as_str(), name]
+            .iter()
+            .copied()
+            .find(|t| self.tera.get_template_names().any(|n| n == *t))?;
+
+        let context = match tera::Context::from_serialize(serde_json::json!({ "help": help })) {
+            Ok(context) => context,
+            Err(err) => {
+                tracing::warn!(?err, template, "Failed to build help template context");
+                return None;
+            }
+        };
+
+        match self.tera.render(template, &context) {
+            Ok(rendered) => Some(rendered),
+            Err(err) => {
+                tracing::warn!(?err, template, "Failed to render help template");
+                None
+            }
+        }
+    }
+}
+
+impl HelpRenderer for DirectoryRenderer {
+    fn render_command(&self, help: &CommandHelp) -> RenderedHelp {
+        let fallback = || TemplateRenderer.render_command(help);
+
+        RenderedHelp {
+            plain: self
+                .render(&help.locale, "command_help.txt", help)
+                .unwrap_or_else(|| fallback().plain),
+            html: self
+                .render(&help.locale, "command_help.html", help)
+                .unwrap_or_else(|| fallback().html),
+        }
+    }
+
+    fn render_group(&self, help: &GroupHelp) -> RenderedHelp {
+        let fallback = || TemplateRenderer.render_group(help);
+
+        RenderedHelp {
+            plain: self
+                .render(&help.locale, "group_help.txt", help)
+                .unwrap_or_else(|| fallback().plain),
+            html: self
+                .render(&help.locale, "group_help.html", help)
+                .unwrap_or_else(|| fallback().html),
+        }
+    }
+}
+
+/// Render the help for the command or group at `path` in the tree visible
+/// from `c`, in its locale and using the renderer in the bot's state if there
+/// is one
+pub fn render<C: Context>(c: &C, path: &[&str]) -> Option<RenderedHelp> {
+    let renderer = c
+        .state()
+        .get::<Arc<dyn HelpRenderer>>()
+        .map_or(&TemplateRenderer as &dyn HelpRenderer, |r| r.as_ref());
+    let locales = c
+        .state()
+        .get::<Arc<Locales>>()
+        .map_or(Locales::builtin(), |l| l.as_ref());
+    let labels = HelpLabels::new(locales, c.locale());
+    let prefix = &c.config().prefix;
+    let root = c.root();
+
+    Some(match root.find_thing(path)? {
+        GroupOrCommandMetaRef::Command(meta) => renderer.render_command(
+            &CommandHelp::new(prefix, root, path, meta, labels).localise(locales, c.locale()),
+        ),
+        GroupOrCommandMetaRef::Group(meta) => renderer.render_group(
+            &GroupHelp::new(prefix, Some(c.room_id()), root, path, meta, labels)
+                .localise(locales, c.locale()),
+        ),
+    })
+}
+
+/// The `help` command, describing the command or group at the given path, or
+/// everything when given no path
+pub fn command<C: Context + Clone + Send + Sync + 'static>() -> ErasedCommand<C> {
+    command_in(&[])
+}
+
+/// The `help` command for the group at `group`, paths given to it are looked
+/// up from there. `GroupBuilder::with_help` adds this with the group's path.
+pub fn command_in<C: Context + Clone + Send + Sync + 'static>(group: &[&str]) -> ErasedCommand<C> {
+    let group = group.iter().map(|&s| s.to_owned()).collect::<Vec<_>>();
+
+    cmd(
+        move |c: C, Named(path): Named<Vec<String>, "path">| async move {
+            let path = group
+                .iter()
+                .chain(&path)
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>();
+
+            let _ = match render(&c, &path) {
+                Some(help) => c.send_html(&help.plain, &help.html).await,
+                None => {
+                    c.reply(&c.tr("help-not-found", &[("path", path.join(" ").into())]))
+                        .await
+                }
+            };
+        },
+    )
+    .with_description("get help with a command or group")
+    .with_param_docs(&[Some("the command or group to describe")])
+}
@@ -0,0 +1,491 @@
+//! Localisation of the framework's messages and of command descriptions.
+//!
+//! Messages are written in [Fluent](https://projectfluent.org), the framework's
+//! own English ones are in `locales/en-US/bleh.ftl` and show the ids to use to
+//! translate them. Each message is sent in the locale chosen by its author with
+//! `locale me`, or failing that the one chosen for the room with `locale room`,
+//! or failing that the bot's default.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use fluent_bundle::{concurrent::FluentBundle, FluentArgs, FluentResource, FluentValue};
+use matrix_sdk::ruma::{RoomId, UserId};
+use once_cell::sync::Lazy;
+pub use unic_langid::LanguageIdentifier;
+
+use crate::framework::{
+    commands::{cmd, GroupBuilder, Named},
+    context::{Context, ContextActions},
+    policy::{self, MODERATOR_LEVEL},
+    state::State,
+    transport,
+};
+
+const BUILTIN: &str = include_str!("../../locales/en-US/bleh.ftl");
+
+fn builtin_locale() -> LanguageIdentifier {
+    "en-US".parse().expect("en-US is a valid locale")
+}
+
+static BUILTIN_LOCALES: Lazy<Locales> = Lazy::new(Locales::new);
+
+#[derive(Debug)]
+pub enum LocaleError {
+    Io(std::io::Error),
+    /// A locale directory isn't named after a locale
+    InvalidLocale(PathBuf),
+    Parse {
+        file: PathBuf,
+        errors: Vec<String>,
+    },
+}
+
+impl std::fmt::Display for LocaleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocaleError::Io(err) => write!(f, "Couldn't read locales: {}", err),
+            LocaleError::InvalidLocale(dir) => {
+                write!(f, "{} isn't named after a locale", dir.display())
+            }
+            LocaleError::Parse { file, errors } => {
+                write!(
+                    f,
+                    "Couldn't parse {}: {}",
+                    file.display(),
+                    errors.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for LocaleError {}
+
+impl From<std::io::Error> for LocaleError {
+    fn from(err: std::io::Error) -> Self {
+        LocaleError::Io(err)
+    }
+}
+
+/// The id of the message translating the description of the command or group
+/// at `path`, such as `command-commands-enable`. `kind` is `command` or
+/// `group`, a `.long` attribute translates a command's long description.
+pub fn message_id(kind: &str, path: &[&str]) -> String {
+    if path.is_empty() {
+        format!("{}-root", kind)
+    } else {
+        format!("{}-{}", kind, path.join("-"))
+    }
+}
+
+/// Every message the bot knows, by locale
+pub struct Locales {
+    default: LanguageIdentifier,
+    bundles: HashMap<LanguageIdentifier, FluentBundle<FluentResource>>,
+}
+
+impl Default for Locales {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Locales {
+    /// Just the framework's own messages, in English
+    pub fn new() -> Self {
+        let mut locales = Self {
+            default: builtin_locale(),
+            bundles: HashMap::new(),
+        };
+
+        locales
+            .add_named(&builtin_locale(), BUILTIN.to_owned(), Path::new("bleh.ftl"))
+            .expect("The built in messages are valid");
+
+        locales
+    }
+
+    /// The same messages as `new`, parsed once and shared, for when the bot's
+    /// state has no `Arc<Locales>`
+    pub fn builtin() -> &'static Locales {
+        &BUILTIN_LOCALES
+    }
+
+    /// The locale used when neither the author nor the room has chosen one
+    pub fn with_default(mut self, locale: LanguageIdentifier) -> Self {
+        self.default = locale;
+        self
+    }
+
+    pub fn default_locale(&self) -> &LanguageIdentifier {
+        &self.default
+    }
+
+    fn add_named(
+        &mut self,
+        locale: &LanguageIdentifier,
+        source: String,
+        file: &Path,
+    ) -> Result<(), LocaleError> {
+        let resource =
+            FluentResource::try_new(source).map_err(|(_, errors)| LocaleError::Parse {
+                file: file.to_owned(),
+                errors: errors.iter().map(|e| format!("{:?}", e)).collect(),
+            })?;
+
+        self.bundles
+            .entry(locale.clone())
+            .or_insert_with(|| {
+                let mut bundle = FluentBundle::new_concurrent(vec![locale.clone()]);
+                // the isolation marks show up as junk in most clients
+                bundle.set_use_isolating(false);
+                bundle
+            })
+            .add_resource_overriding(resource);
+
+        Ok(())
+    }
+
+    /// Add Fluent messages for a locale, replacing existing messages with the
+    /// same ids
+    pub fn add_resource(
+        mut self,
+        locale: &LanguageIdentifier,
+        source: &str,
+    ) -> Result<Self, LocaleError> {
+        self.add_named(locale, source.to_owned(), Path::new("<resource>"))?;
+        Ok(self)
+    }
+
+    /// Load every `.ftl` file in each subdirectory of `dir`, the subdirectories
+    /// being named after the locale of the files in them, such as `de` or
+    /// `en-GB`
+    pub fn load_dir(mut self, dir: impl AsRef<Path>) -> Result<Self, LocaleError> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let locale = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+                .ok_or_else(|| LocaleError::InvalidLocale(entry.path()))?;
+
+            let mut files = std::fs::read_dir(entry.path())?
+                .map(|file| file.map(|file| file.path()))
+                .collect::<Result<Vec<_>, _>>()?;
+            files.retain(|file| file.extension().map_or(false, |ext| ext == "ftl"));
+            files.sort();
+
+            for file in files {
+                let source = std::fs::read_to_string(&file)?;
+                self.add_named(&locale, source, &file)?;
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Every locale with messages, sorted
+    pub fn available(&self) -> Vec<&LanguageIdentifier> {
+        let mut available = self.bundles.keys().collect::<Vec<_>>();
+        available.sort_by_key(|l| l.to_string());
+        available
+    }
+
+    /// The available locale closest to `requested`, the same locale or failing
+    /// that one for the same language
+    pub fn find(&self, requested: &LanguageIdentifier) -> Option<&LanguageIdentifier> {
+        self.bundles
+            .get_key_value(requested)
+            .map(|(locale, _)| locale)
+            .or_else(|| {
+                self.available()
+                    .into_iter()
+                    .find(|l| l.language == requested.language)
+            })
+    }
+
+    /// Like `find`, falling back to the default locale
+    pub fn negotiate(&self, requested: &LanguageIdentifier) -> LanguageIdentifier {
+        self.find(requested).unwrap_or(&self.default).clone()
+    }
+
+    /// A message or one of its attributes, looked up in `locale`, then the
+    /// default locale, then the framework's English messages
+    fn format(
+        &self,
+        locale: &LanguageIdentifier,
+        id: &str,
+        attribute: Option<&str>,
+        args: Option<&FluentArgs>,
+    ) -> Option<String> {
+        let builtin = builtin_locale();
+
+        [locale, &self.default, &builtin]
+            .iter()
+            .filter_map(|l| self.bundles.get(l))
+            .find_map(|bundle| {
+                let message = bundle.get_message(id)?;
+                let pattern = match attribute {
+                    Some(attribute) => message.get_attribute(attribute)?.value(),
+                    None => message.value()?,
+                };
+
+                let mut errors = Vec::new();
+                let formatted = bundle.format_pattern(pattern, args, &mut errors);
+
+                if !errors.is_empty() {
+                    tracing::warn!(?errors, id, "Failed to format message");
+                }
+
+                Some(formatted.into_owned())
+            })
+    }
+
+    /// A message without arguments, if any locale has it
+    pub fn get(
+        &self,
+        locale: &LanguageIdentifier,
+        id: &str,
+        attribute: Option<&str>,
+    ) -> Option<String> {
+        self.format(locale, id, attribute, None)
+    }
+
+    /// Translate a message, falling back to its id if no locale has it
+    pub fn tr(
+        &self,
+        locale: &LanguageIdentifier,
+        id: &str,
+        args: &[(&str, FluentValue)],
+    ) -> String {
+        let mut fluent_args = FluentArgs::new();
+        for (name, value) in args {
+            fluent_args.set(*name, value.clone());
+        }
+
+        self.format(locale, id, None, Some(&fluent_args))
+            .unwrap_or_else(|| id.to_owned())
+    }
+}
+
+/// Who a locale was chosen for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LocaleScope {
+    Room(Box<RoomId>),
+    User(Box<UserId>),
+}
+
+/// Where chosen locales are kept
+#[async_trait::async_trait]
+pub trait LocaleStore: Send + Sync + 'static {
+    async fn get(&self, scope: &LocaleScope) -> transport::Result<Option<LanguageIdentifier>>;
+
+    /// Choose a locale, `None` going back to the default
+    async fn set(
+        &self,
+        scope: LocaleScope,
+        locale: Option<LanguageIdentifier>,
+    ) -> transport::Result<()>;
+}
+
+/// A locale store that forgets everything when the bot stops
+#[derive(Default)]
+pub struct MemoryLocaleStore {
+    locales: Mutex<HashMap<LocaleScope, LanguageIdentifier>>,
+}
+
+impl MemoryLocaleStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl LocaleStore for MemoryLocaleStore {
+    async fn get(&self, scope: &LocaleScope) -> transport::Result<Option<LanguageIdentifier>> {
+        Ok(self.locales.lock().unwrap().get(scope).cloned())
+    }
+
+    async fn set(
+        &self,
+        scope: LocaleScope,
+        locale: Option<LanguageIdentifier>,
+    ) -> transport::Result<()> {
+        let mut locales = self.locales.lock().unwrap();
+
+        match locale {
+            Some(locale) => locales.insert(scope, locale),
+            None => locales.remove(&scope),
+        };
+
+        Ok(())
+    }
+}
+
+/// The locale a message from `author` in `room_id` should be answered in
+pub async fn choose(
+    locales: &Locales,
+    store: &dyn LocaleStore,
+    author: &UserId,
+    room_id: &RoomId,
+) -> LanguageIdentifier {
+    let scopes = [
+        LocaleScope::User(author.to_owned()),
+        LocaleScope::Room(room_id.to_owned()),
+    ];
+
+    for scope in &scopes {
+        match store.get(scope).await {
+            Ok(Some(locale)) => return locales.negotiate(&locale),
+            Ok(None) => {}
+            Err(err) => tracing::warn!(?err, ?scope, "Failed to load locale"),
+        }
+    }
+
+    locales.default_locale().clone()
+}
+
+fn available_list(locales: &Locales) -> String {
+    locales
+        .available()
+        .iter()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+async fn set_locale<C: Context + Sync>(
+    c: &C,
+    locales: &Locales,
+    store: &dyn LocaleStore,
+    scope: LocaleScope,
+    locale: Option<LanguageIdentifier>,
+) {
+    if matches!(scope, LocaleScope::Room(_))
+        && !policy::require_power_level(c, MODERATOR_LEVEL).await
+    {
+        return;
+    }
+
+    let locale = match locale {
+        Some(requested) => match locales.find(&requested) {
+            Some(locale) => Some(locale.clone()),
+            None => {
+                let _ = c
+                    .reply(&c.tr(
+                        "locale-unavailable",
+                        &[
+                            ("locale", requested.to_string().into()),
+                            ("available", available_list(locales).into()),
+                        ],
+                    ))
+                    .await;
+                return;
+            }
+        },
+        None => None,
+    };
+
+    match store.set(scope, locale).await {
+        Ok(()) => {
+            let _ = c.react("✅").await;
+        }
+        Err(err) => {
+            let _ = c
+                .reply(&c.tr("locale-save-failed", &[("error", err.to_string().into())]))
+                .await;
+        }
+    }
+}
+
+/// Commands for choosing the locale the bot answers in, add these to the
+/// command tree under a name such as `locale`. They need
+/// `DispatcherBuilder::with_locale_store` to have been used.
+pub fn commands<C: Context + Clone + Send + Sync + 'static>() -> GroupBuilder<C> {
+    type AllLocales = State<Arc<Locales>>;
+    type Store = State<Arc<dyn LocaleStore>>;
+    type Locale = Named<LanguageIdentifier, "locale">;
+
+    GroupBuilder::new()
+        .with_description("choose the language the bot answers in")
+        .fallback(
+            cmd(|c: C| async move {
+                let _ = c
+                    .reply(&c.tr(
+                        "locale-current",
+                        &[("locale", c.locale().to_string().into())],
+                    ))
+                    .await;
+            })
+            .with_description("show the locale used here"),
+        )
+        .command(
+            "list",
+            cmd(|c: C, State(locales): AllLocales| async move {
+                let _ = c
+                    .reply(&c.tr(
+                        "locale-available",
+                        &[("available", available_list(&locales).into())],
+                    ))
+                    .await;
+            })
+            .with_description("list the available locales"),
+        )
+        .group(
+            "me",
+            GroupBuilder::new()
+                .with_description("choose the locale the bot answers you in")
+                .fallback(cmd(
+                    |c: C,
+                     State(locales): AllLocales,
+                     State(store): Store,
+                     Named(locale): Locale| async move {
+                        let scope = LocaleScope::User(c.author().to_owned());
+                        set_locale(&c, &locales, store.as_ref(), scope, Some(locale)).await;
+                    },
+                ))
+                .command(
+                    "reset",
+                    cmd(
+                        |c: C, State(locales): AllLocales, State(store): Store| async move {
+                            let scope = LocaleScope::User(c.author().to_owned());
+                            set_locale(&c, &locales, store.as_ref(), scope, None).await;
+                        },
+                    )
+                    .with_description("go back to the room's locale"),
+                ),
+        )
+        .group(
+            "room",
+            GroupBuilder::new()
+                .with_description("choose the locale the bot answers in in this room")
+                .fallback(cmd(
+                    |c: C,
+                     State(locales): AllLocales,
+                     State(store): Store,
+                     Named(locale): Locale| async move {
+                        let scope = LocaleScope::Room(c.room_id().to_owned());
+                        set_locale(&c, &locales, store.as_ref(), scope, Some(locale)).await;
+                    },
+                ))
+                .command(
+                    "reset",
+                    cmd(
+                        |c: C, State(locales): AllLocales, State(store): Store| async move {
+                            let scope = LocaleScope::Room(c.room_id().to_owned());
+                            set_locale(&c, &locales, store.as_ref(), scope, None).await;
+                        },
+                    )
+                    .with_description("go back to the bot's default locale"),
+                ),
+        )
+}
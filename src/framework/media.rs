@@ -0,0 +1,151 @@
+use matrix_sdk::ruma::events::room::message::{MessageType, RoomMessageEventContent};
+use mime::Mime;
+
+use crate::framework::transport::{self, Transport};
+
+/// Thumbnails are scaled down to fit within this box
+const THUMBNAIL_SIZE: (u32, u32) = (800, 600);
+
+/// Media downloaded from an `m.image`, `m.file`, `m.audio` or `m.video` message.
+/// Commands are only read from `m.text` messages, so this comes from the
+/// message a command replies to rather than the command itself.
+pub struct Media {
+    pub body: String,
+    pub mimetype: Option<String>,
+    pub data: Vec<u8>,
+}
+
+struct ImageDetails {
+    width: u32,
+    height: u32,
+    blurhash: String,
+    /// Only present if the image is larger than the thumbnail size
+    thumbnail: Option<(u32, u32, Vec<u8>)>,
+}
+
+fn image_details(data: &[u8]) -> Option<ImageDetails> {
+    let img = image::load_from_memory(data).ok()?;
+    let (width, height) = (img.width(), img.height());
+
+    let small = img.thumbnail(THUMBNAIL_SIZE.0, THUMBNAIL_SIZE.1);
+    let blurhash = blurhash::encode(
+        4,
+        3,
+        small.width(),
+        small.height(),
+        &small.to_rgba8().into_raw(),
+    );
+
+    let thumbnail = if (width, height) != (small.width(), small.height()) {
+        let mut out = Vec::new();
+        small
+            .write_to(&mut out, image::ImageOutputFormat::Png)
+            .ok()?;
+        Some((small.width(), small.height(), out))
+    } else {
+        None
+    };
+
+    Some(ImageDetails {
+        width,
+        height,
+        blurhash,
+        thumbnail,
+    })
+}
+
+/// Upload an image to the media repository, building an `m.image` with its
+/// dimensions, a thumbnail and a blurhash. If the image can't be decoded it is
+/// sent without them.
+pub async fn upload_image(
+    transport: &dyn Transport,
+    name: &str,
+    mimetype: &Mime,
+    data: Vec<u8>,
+) -> transport::Result<MessageType> {
+    let (data, details) = tokio::task::spawn_blocking(move || {
+        let details = image_details(&data);
+        (data, details)
+    })
+    .await?;
+
+    let url = transport.upload(mimetype, &data).await?;
+
+    let mut info = serde_json::json!({
+        "mimetype": mimetype.as_ref(),
+        "size": data.len(),
+    });
+
+    if let Some(details) = details {
+        info["w"] = details.width.into();
+        info["h"] = details.height.into();
+        info["xyz.amorgan.blurhash"] = details.blurhash.into();
+
+        if let Some((width, height, thumbnail)) = details.thumbnail {
+            let thumbnail_url = transport.upload(&mime::IMAGE_PNG, &thumbnail).await?;
+
+            info["thumbnail_url"] = serde_json::json!(thumbnail_url);
+            info["thumbnail_info"] = serde_json::json!({
+                "mimetype": mime::IMAGE_PNG.as_ref(),
+                "size": thumbnail.len(),
+                "w": width,
+                "h": height,
+            });
+        }
+    }
+
+    let content: RoomMessageEventContent = serde_json::from_value(serde_json::json!({
+        "msgtype": "m.image",
+        "body": name,
+        "url": url,
+        "info": info,
+    }))?;
+
+    Ok(content.msgtype)
+}
+
+/// Upload a file to the media repository, building an `m.file` for it
+pub async fn upload_file(
+    transport: &dyn Transport,
+    name: &str,
+    mimetype: &Mime,
+    data: Vec<u8>,
+) -> transport::Result<MessageType> {
+    let url = transport.upload(mimetype, &data).await?;
+
+    let content: RoomMessageEventContent = serde_json::from_value(serde_json::json!({
+        "msgtype": "m.file",
+        "body": name,
+        "filename": name,
+        "url": url,
+        "info": {
+            "mimetype": mimetype.as_ref(),
+            "size": data.len(),
+        },
+    }))?;
+
+    Ok(content.msgtype)
+}
+
+/// Download the media attached to a message, returns `None` if the message
+/// type doesn't carry any
+pub async fn download(
+    transport: &dyn Transport,
+    msgtype: &MessageType,
+) -> transport::Result<Option<Media>> {
+    let (body, mimetype) = match msgtype {
+        MessageType::Image(c) => (&c.body, c.info.as_ref().and_then(|i| i.mimetype.clone())),
+        MessageType::File(c) => (&c.body, c.info.as_ref().and_then(|i| i.mimetype.clone())),
+        MessageType::Audio(c) => (&c.body, c.info.as_ref().and_then(|i| i.mimetype.clone())),
+        MessageType::Video(c) => (&c.body, c.info.as_ref().and_then(|i| i.mimetype.clone())),
+        _ => return Ok(None),
+    };
+
+    let data = transport.download(msgtype).await?;
+
+    Ok(data.map(|data| Media {
+        body: body.clone(),
+        mimetype,
+        data,
+    }))
+}
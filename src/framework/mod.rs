@@ -1,2 +1,16 @@
+pub mod chain;
 pub mod commands;
+pub mod config;
 pub mod context;
+pub mod dispatch;
+pub mod docs;
+pub mod extractors;
+pub mod help;
+pub mod i18n;
+pub mod media;
+pub mod params;
+pub mod policy;
+pub mod registry;
+pub mod state;
+pub mod transport;
+pub mod triggers;
@@ -0,0 +1,655 @@
+//! Parameters built out of other parameters, and parameters for values that
+//! need more than a number or a word.
+
+use std::{
+    convert::TryInto,
+    error::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+use crate::framework::commands::{char_str, int_str, str_join, BoxFuture, Parameter, ResolveError};
+
+/// A list of `T`s separated by `SEP`, such as `a, b, c`. Unlike `Vec<T>` it
+/// stops after the first item not followed by `SEP`, so more parameters can
+/// come after it.
+#[derive(Clone, Debug)]
+pub struct Sep<T, const SEP: char>(pub Vec<T>);
+
+/// Parse a single item of a `Sep`, first from the input up to the next
+/// separator so items like `String` don't swallow it, then from the whole
+/// input for items that can contain the separator, such as quoted strings
+fn parse_item<'a, T: Parameter<C>, C>(
+    ctx: &C,
+    input: &'a str,
+    sep: char,
+) -> Result<(&'a str, T::Parsed), Box<dyn Error + 'a>> {
+    let item = &input[..input.find(sep).unwrap_or(input.len())];
+
+    match T::parse(ctx, item) {
+        Ok((rest, v)) => Ok((&input[item.len() - rest.len()..], v)),
+        Err(err) => T::parse(ctx, input).map_err(|_| err),
+    }
+}
+
+impl<T: Parameter<C>, C, const SEP: char> Parameter<C> for Sep<T, SEP> {
+    const INFO: &'static str = str_join(&[T::INFO, char_str(SEP).as_str(), " ..."]).as_str();
+    const VISIBLE: bool = T::VISIBLE;
+    const REPEATED: bool = true;
+
+    type Parsed = Vec<T::Parsed>;
+
+    fn parse<'a>(
+        ctx: &C,
+        mut input: &'a str,
+    ) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>> {
+        let mut out = Vec::new();
+
+        if input.trim().is_empty() {
+            return Ok((input, out));
+        }
+
+        loop {
+            let (rest, v) = match parse_item(ctx, input, SEP) {
+                Ok(item) => item,
+                Err(err) if out.is_empty() => return Err(err),
+                Err(err) => {
+                    return Err(format!("Expected {} after `{}`: {}", T::INFO, SEP, err).into())
+                }
+            };
+            out.push(v);
+
+            match rest.trim_start().strip_prefix(SEP) {
+                Some(rest) => input = rest,
+                None => return Ok((rest, out)),
+            }
+        }
+    }
+
+    fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        Self::Parsed: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(Sep(<Vec<T> as Parameter<C>>::resolve(parsed, ctx).await?)) })
+    }
+}
+
+/// Either an `A` or, if that doesn't parse, a `B`
+#[derive(Clone, Debug)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A: Parameter<C>, B: Parameter<C>, C> Parameter<C> for Either<A, B> {
+    const INFO: &'static str = str_join(&[A::INFO, " | ", B::INFO]).as_str();
+    const VISIBLE: bool = A::VISIBLE || B::VISIBLE;
+
+    type Parsed = Either<A::Parsed, B::Parsed>;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>> {
+        let left = match A::parse(ctx, input) {
+            Ok((rest, a)) => return Ok((rest, Either::Left(a))),
+            Err(err) => err,
+        };
+
+        match B::parse(ctx, input) {
+            Ok((rest, b)) => Ok((rest, Either::Right(b))),
+            Err(right) => {
+                Err(format!("Expected {} or {}: {}; {}", A::INFO, B::INFO, left, right).into())
+            }
+        }
+    }
+
+    fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        Self::Parsed: 'c,
+        C: Sync,
+    {
+        Box::pin(async move {
+            Ok(match parsed {
+                Either::Left(a) => Either::Left(A::resolve(a, ctx).await?),
+                Either::Right(b) => Either::Right(B::resolve(b, ctx).await?),
+            })
+        })
+    }
+}
+
+macro_rules! tuple_param {
+    ($first:ident $(, $rest:ident)*) => {
+        #[allow(non_snake_case)]
+        impl<C, $first: Parameter<C>, $($rest: Parameter<C>),*> Parameter<C> for ($first, $($rest),*) {
+            const INFO: &'static str = str_join(&["(", $first::INFO, $(", ", $rest::INFO,)* ")"]).as_str();
+            const VISIBLE: bool = $first::VISIBLE $(|| $rest::VISIBLE)*;
+
+            type Parsed = (<$first as Parameter<C>>::Parsed, $(<$rest as Parameter<C>>::Parsed),*);
+
+            fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>> {
+                let (input, $first) = $first::parse(ctx, input)?;
+                $(let (input, $rest) = $rest::parse(ctx, input)?;)*
+
+                Ok((input, ($first, $($rest),*)))
+            }
+
+            fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+            where
+                Self: 'c,
+                Self::Parsed: 'c,
+                C: Sync,
+            {
+                let ($first, $($rest),*) = parsed;
+
+                Box::pin(async move {
+                    Ok((
+                        <$first as Parameter<C>>::resolve($first, ctx).await?,
+                        $(<$rest as Parameter<C>>::resolve($rest, ctx).await?),*
+                    ))
+                })
+            }
+        }
+    };
+}
+
+tuple_param!(T0, T1);
+tuple_param!(T0, T1, T2);
+tuple_param!(T0, T1, T2, T3);
+
+/// At least `N` `T`s, taking as many as parse like `Vec<T>`
+#[derive(Clone, Debug)]
+pub struct AtLeast<T, const N: usize>(pub Vec<T>);
+
+impl<T: Parameter<C>, C, const N: usize> Parameter<C> for AtLeast<T, N> {
+    const INFO: &'static str = str_join(&[
+        "Vec<",
+        T::INFO,
+        "> (at least ",
+        int_str(N as i64).as_str(),
+        ")",
+    ])
+    .as_str();
+    const VISIBLE: bool = T::VISIBLE;
+    const REPEATED: bool = true;
+
+    type Parsed = Vec<T::Parsed>;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>> {
+        let (rest, out) = <Vec<T> as Parameter<C>>::parse(ctx, input)?;
+
+        if out.len() < N {
+            return Err(format!("Expected at least {} {}, got {}", N, T::INFO, out.len()).into());
+        }
+
+        Ok((rest, out))
+    }
+
+    fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        Self::Parsed: 'c,
+        C: Sync,
+    {
+        Box::pin(async move {
+            Ok(AtLeast(
+                <Vec<T> as Parameter<C>>::resolve(parsed, ctx).await?,
+            ))
+        })
+    }
+}
+
+/// Up to `N` `T`s, anything after the `N`th is left for the next parameter
+#[derive(Clone, Debug)]
+pub struct AtMost<T, const N: usize>(pub Vec<T>);
+
+impl<T: Parameter<C>, C, const N: usize> Parameter<C> for AtMost<T, N> {
+    const INFO: &'static str = str_join(&[
+        "Vec<",
+        T::INFO,
+        "> (at most ",
+        int_str(N as i64).as_str(),
+        ")",
+    ])
+    .as_str();
+    const VISIBLE: bool = T::VISIBLE;
+    const REPEATED: bool = true;
+
+    type Parsed = Vec<T::Parsed>;
+
+    fn parse<'a>(
+        ctx: &C,
+        mut input: &'a str,
+    ) -> Result<(&'a str, Self::Parsed), Box<dyn Error + 'a>> {
+        let mut out = Vec::new();
+
+        while out.len() < N {
+            match T::parse(ctx, input) {
+                Ok((rest, v)) => {
+                    input = rest;
+                    out.push(v);
+                }
+                Err(_) => break,
+            }
+        }
+
+        Ok((input, out))
+    }
+
+    fn resolve<'c>(parsed: Self::Parsed, ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        Self::Parsed: 'c,
+        C: Sync,
+    {
+        Box::pin(async move {
+            Ok(AtMost(
+                <Vec<T> as Parameter<C>>::resolve(parsed, ctx).await?,
+            ))
+        })
+    }
+}
+
+/// The rest of the input like `Remainder`, but there has to be something
+/// other than whitespace
+#[derive(Clone, Debug)]
+pub struct NonEmptyRemainder(pub String);
+
+impl<C> Parameter<C> for NonEmptyRemainder {
+    const INFO: &'static str = "String+";
+    const VISIBLE: bool = true;
+    const REPEATED: bool = true;
+
+    type Parsed = Self;
+
+    fn parse<'a>(_ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+        if input.trim().is_empty() {
+            return Err("Expected some text".into());
+        }
+
+        Ok(("", Self(input.to_owned())))
+    }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
+}
+
+/// An integer between `MIN` and `MAX` inclusive, such as `Bounded<u8, 1, 10>`
+#[derive(Clone, Copy, Debug)]
+pub struct Bounded<T, const MIN: i64, const MAX: i64>(pub T);
+
+impl<T, C, const MIN: i64, const MAX: i64> Parameter<C> for Bounded<T, MIN, MAX>
+where
+    T: Parameter<C, Parsed = T> + Copy + TryInto<i128> + std::fmt::Display,
+{
+    const INFO: &'static str = str_join(&[
+        T::INFO,
+        " (",
+        int_str(MIN).as_str(),
+        "..=",
+        int_str(MAX).as_str(),
+        ")",
+    ])
+    .as_str();
+    const VISIBLE: bool = T::VISIBLE;
+
+    type Parsed = Self;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+        let (rest, v) = T::parse(ctx, input)?;
+
+        // anything too big for an i128 is certainly too big for MAX
+        let in_range = v
+            .try_into()
+            .map_or(false, |v| (MIN as i128..=MAX as i128).contains(&v));
+
+        if !in_range {
+            return Err(format!("{} isn't between {} and {}", v, MIN, MAX).into());
+        }
+
+        Ok((rest, Bounded(v)))
+    }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
+}
+
+/// Parse a word or quoted string with `FromStr`
+macro_rules! p_via_from_str {
+    ($T:ty, $info:expr) => {
+        impl<C> Parameter<C> for $T {
+            const INFO: &'static str = $info;
+            const VISIBLE: bool = true;
+
+            type Parsed = Self;
+
+            fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+                let (input, word) = <String as Parameter<C>>::parse(ctx, input)?;
+
+                Ok((input, word.parse()?))
+            }
+
+            fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+            where
+                Self: 'c,
+                C: Sync,
+            {
+                Box::pin(async move { Ok(parsed) })
+            }
+        }
+    };
+}
+
+p_via_from_str!(url::Url, "Url");
+p_via_from_str!(IpAddr, "IpAddr");
+p_via_from_str!(Ipv4Addr, "Ipv4Addr");
+p_via_from_str!(Ipv6Addr, "Ipv6Addr");
+p_via_from_str!(unic_langid::LanguageIdentifier, "Locale");
+
+/// The most memory a regex given to a command may compile to, so users can't
+/// make the bot build an enormous automaton with something like `\w{1000}`
+pub const REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+impl<C> Parameter<C> for regex::Regex {
+    const INFO: &'static str = "Regex";
+    const VISIBLE: bool = true;
+
+    type Parsed = Self;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+        let (input, pattern) = <String as Parameter<C>>::parse(ctx, input)?;
+
+        let regex = regex::RegexBuilder::new(&pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .dfa_size_limit(REGEX_SIZE_LIMIT)
+            .build()?;
+
+        Ok((input, regex))
+    }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
+}
+
+/// A colour written as `#rrggbb` or `#rgb`, the `#` being optional. Its
+/// `Display` gives the `#rrggbb` form `data-mx-color` expects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Colour {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl std::fmt::Display for Colour {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Debug)]
+pub struct InvalidColour(pub String);
+
+impl std::fmt::Display for InvalidColour {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` isn't a hex colour, expected #rrggbb or #rgb",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidColour {}
+
+impl std::str::FromStr for Colour {
+    type Err = InvalidColour;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(InvalidColour(s.to_owned()));
+        }
+
+        let channel = |i: usize, width: usize| {
+            let v = u8::from_str_radix(&hex[i * width..(i + 1) * width], 16).unwrap();
+            if width == 1 {
+                v * 0x11
+            } else {
+                v
+            }
+        };
+
+        match hex.len() {
+            3 => Ok(Colour {
+                r: channel(0, 1),
+                g: channel(1, 1),
+                b: channel(2, 1),
+            }),
+            6 => Ok(Colour {
+                r: channel(0, 2),
+                g: channel(1, 2),
+                b: channel(2, 2),
+            }),
+            _ => Err(InvalidColour(s.to_owned())),
+        }
+    }
+}
+
+p_via_from_str!(Colour, "Colour");
+
+/// A single emoji, including ones made of several code points such as flags,
+/// keycaps, skin tones and sequences joined with zero width joiners
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Emoji(pub String);
+
+fn is_emoji_base(c: char) -> bool {
+    matches!(c as u32,
+        0x00a9 | 0x00ae | 0x203c | 0x2049 | 0x2122 | 0x2139 | 0x2194..=0x21aa
+        | 0x231a..=0x23ff | 0x24c2 | 0x25aa..=0x25fe | 0x2600..=0x27bf
+        | 0x2934 | 0x2935 | 0x2b05..=0x2b55 | 0x3030 | 0x303d | 0x3297 | 0x3299
+        | 0x1f000..=0x1f1e5 | 0x1f200..=0x1faff)
+}
+
+fn is_emoji_modifier(c: char) -> bool {
+    // variation selector 16, keycap, skin tones and tag characters
+    matches!(c as u32, 0xfe0f | 0x20e3 | 0x1f3fb..=0x1f3ff | 0xe0020..=0xe007f)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    matches!(c as u32, 0x1f1e6..=0x1f1ff)
+}
+
+/// Whether `s` is a single emoji, without pulling in the full unicode tables
+fn is_single_emoji(s: &str) -> bool {
+    s.split('\u{200d}').all(|part| {
+        let mut chars = part.chars();
+
+        let first = match chars.next() {
+            Some(c) => c,
+            None => return false,
+        };
+        let rest = chars.as_str();
+
+        if is_regional_indicator(first) {
+            // flags are exactly two regional indicators
+            let mut rest = rest.chars();
+            return rest.next().map_or(false, is_regional_indicator) && rest.next().is_none();
+        }
+
+        let keycap = matches!(first, '0'..='9' | '#' | '*') && rest.ends_with('\u{20e3}');
+
+        (keycap || is_emoji_base(first)) && rest.chars().all(is_emoji_modifier)
+    })
+}
+
+impl<C> Parameter<C> for Emoji {
+    const INFO: &'static str = "Emoji";
+    const VISIBLE: bool = true;
+
+    type Parsed = Self;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+        let (input, word) = <String as Parameter<C>>::parse(ctx, input)?;
+
+        if !is_single_emoji(&word) {
+            return Err(format!("`{}` isn't a single emoji", word).into());
+        }
+
+        Ok((input, Emoji(word)))
+    }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse<T: Parameter<()>>(input: &str) -> Result<(&str, T::Parsed), String> {
+        T::parse(&(), input).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn sep_stops_at_the_first_item_without_a_separator() {
+        let (rest, items) = parse::<Sep<u32, ','>>("1, 2,3 4").unwrap();
+
+        assert_eq!(items, vec![1, 2, 3]);
+        assert_eq!(rest, "4");
+    }
+
+    #[test]
+    fn sep_of_strings_splits_words_at_the_separator() {
+        let (rest, items) = parse::<Sep<String, ','>>("a,b, c d").unwrap();
+
+        assert_eq!(items, vec!["a", "b", "c"]);
+        assert_eq!(rest, "d");
+    }
+
+    #[test]
+    fn sep_can_be_empty() {
+        let (_, items) = parse::<Sep<u32, ','>>("  ").unwrap();
+
+        assert!(items.is_empty());
+    }
+
+    #[test]
+    fn sep_needs_an_item_after_a_separator() {
+        let err = parse::<Sep<u32, ','>>("1, x").unwrap_err();
+
+        assert!(err.starts_with("Expected u32 after `,`"), "{}", err);
+    }
+
+    #[test]
+    fn vec_of_sep_stops_when_nothing_is_left() {
+        let (rest, items) = parse::<Vec<Sep<u32, ','>>>("1, 2 3").unwrap();
+        assert_eq!(items, vec![vec![1, 2], vec![3]]);
+        assert_eq!(rest, "");
+
+        assert_eq!(
+            parse::<Vec<Sep<u32, ','>>>("").unwrap().1,
+            Vec::<Vec<u32>>::new()
+        );
+    }
+
+    #[test]
+    fn at_least_of_sep_needs_items() {
+        assert_eq!(
+            parse::<AtLeast<Sep<u32, ','>, 1>>("1,2").unwrap().1,
+            vec![vec![1, 2]]
+        );
+        assert!(parse::<AtLeast<Sep<u32, ','>, 1>>("").is_err());
+    }
+
+    #[test]
+    fn bounded_accepts_the_range_inclusive() {
+        assert_eq!(parse::<Bounded<u32, 1, 10>>("1").unwrap().1 .0, 1);
+        assert_eq!(parse::<Bounded<u32, 1, 10>>("10").unwrap().1 .0, 10);
+        assert_eq!(parse::<Bounded<i64, -5, 5>>("-5").unwrap().1 .0, -5);
+    }
+
+    #[test]
+    fn bounded_rejects_values_outside_the_range() {
+        assert_eq!(
+            parse::<Bounded<u32, 1, 10>>("11").unwrap_err(),
+            "11 isn't between 1 and 10"
+        );
+        assert!(parse::<Bounded<i64, -5, 5>>("-6").is_err());
+    }
+
+    #[test]
+    fn bounded_takes_usize_and_isize() {
+        assert_eq!(parse::<Bounded<usize, 0, 3>>("3").unwrap().1 .0, 3);
+        assert!(parse::<Bounded<usize, 0, 3>>("4").is_err());
+        assert_eq!(parse::<Bounded<isize, -3, 3>>("-3").unwrap().1 .0, -3);
+    }
+
+    #[test]
+    fn bounded_info_shows_the_range() {
+        assert_eq!(<Bounded<u32, 1, 10> as Parameter<()>>::INFO, "u32 (1..=10)");
+    }
+
+    #[test]
+    fn colours() {
+        let colour = |r, g, b| Colour { r, g, b };
+
+        let parse = |s: &str| s.parse::<Colour>().unwrap();
+
+        assert_eq!(parse("#7a5901"), colour(0x7a, 0x59, 0x01));
+        assert_eq!(parse("7A5901"), colour(0x7a, 0x59, 0x01));
+        assert_eq!(parse("#fa0"), colour(0xff, 0xaa, 0x00));
+        assert_eq!(colour(0xff, 0xaa, 0x00).to_string(), "#ffaa00");
+    }
+
+    #[test]
+    fn invalid_colours() {
+        for s in ["", "#", "#ff", "#ffff", "#gggggg", "#ffé", "red"] {
+            assert!(s.parse::<Colour>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn single_emoji() {
+        for s in ["😀", "❤️", "👍🏽", "🇬🇧", "1️⃣", "#⃣", "👩‍👩‍👧", "🏴‍☠️"]
+        {
+            assert!(is_single_emoji(s), "{}", s);
+        }
+    }
+
+    #[test]
+    fn not_a_single_emoji() {
+        for s in [
+            "",
+            "a",
+            "1",
+            "😀😀",
+            "🇬",
+            "🇬🇧🇫🇷",
+            "👍 ",
+            "\u{200d}😀",
+            "😀\u{200d}",
+        ] {
+            assert!(!is_single_emoji(s), "{:?}", s);
+        }
+    }
+}
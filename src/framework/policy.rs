@@ -0,0 +1,467 @@
+//! Per-room and per-space enabling and disabling of commands.
+//!
+//! Rules apply to a command path and everything under it, the empty path
+//! being every command. The most specific rule wins, and a rule set on a room
+//! wins over one at the same path set on a space the room belongs to, so a
+//! room can disable everything and then enable just `help`.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    sync::Mutex,
+};
+
+use matrix_sdk::ruma::RoomId;
+
+use crate::framework::{
+    commands::{cmd, GroupBuilder, GroupMeta, GroupOrCommandMeta, Named},
+    context::{Context, ContextActions},
+    registry,
+    state::State,
+    transport,
+};
+
+/// Users need at least this power level in the room to change its policy
+pub const MODERATOR_LEVEL: i64 = 50;
+
+/// Changing the policy of a space needs this power level in the space itself
+pub const ADMIN_LEVEL: i64 = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Room(Box<RoomId>),
+    Space(Box<RoomId>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolicyRule {
+    pub scope: Scope,
+    /// Space separated command path, empty for every command
+    pub path: String,
+    pub enabled: bool,
+}
+
+/// Where policy rules are kept
+#[async_trait::async_trait]
+pub trait PolicyStore: Send + Sync + 'static {
+    /// Every rule set on any of the given scopes
+    async fn rules(&self, scopes: &[Scope]) -> transport::Result<Vec<PolicyRule>>;
+
+    /// Set a rule, replacing any existing rule for the same scope and path
+    async fn set(&self, rule: PolicyRule) -> transport::Result<()>;
+
+    /// Remove the rule for a scope and path, returns `false` if there wasn't one
+    async fn clear(&self, scope: &Scope, path: &str) -> transport::Result<bool>;
+}
+
+/// A policy store that forgets everything when the bot stops
+#[derive(Default)]
+pub struct MemoryPolicyStore {
+    rules: Mutex<HashMap<(Scope, String), bool>>,
+}
+
+impl MemoryPolicyStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait::async_trait]
+impl PolicyStore for MemoryPolicyStore {
+    async fn rules(&self, scopes: &[Scope]) -> transport::Result<Vec<PolicyRule>> {
+        Ok(self
+            .rules
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|((scope, _), _)| scopes.contains(scope))
+            .map(|((scope, path), &enabled)| PolicyRule {
+                scope: scope.clone(),
+                path: path.clone(),
+                enabled,
+            })
+            .collect())
+    }
+
+    async fn set(&self, rule: PolicyRule) -> transport::Result<()> {
+        self.rules
+            .lock()
+            .unwrap()
+            .insert((rule.scope, rule.path), rule.enabled);
+
+        Ok(())
+    }
+
+    async fn clear(&self, scope: &Scope, path: &str) -> transport::Result<bool> {
+        Ok(self
+            .rules
+            .lock()
+            .unwrap()
+            .remove(&(scope.clone(), path.to_owned()))
+            .is_some())
+    }
+}
+
+/// The rules that apply in a single room
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    rules: Vec<PolicyRule>,
+}
+
+/// Whether `prefix` is the same as or a parent of `path`
+fn covers(prefix: &str, path: &[&str]) -> bool {
+    let prefix = prefix.split_whitespace().collect::<Vec<_>>();
+
+    path.starts_with(&prefix)
+}
+
+impl Policy {
+    pub fn new(rules: Vec<PolicyRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Fetch the rules for a room and the spaces it belongs to
+    pub async fn load(
+        store: &dyn PolicyStore,
+        room_id: &RoomId,
+        spaces: Vec<Box<RoomId>>,
+    ) -> transport::Result<Self> {
+        let mut scopes = vec![Scope::Room(room_id.to_owned())];
+        scopes.extend(spaces.into_iter().map(Scope::Space));
+
+        Ok(Self::new(store.rules(&scopes).await?))
+    }
+
+    pub fn rules(&self) -> &[PolicyRule] {
+        &self.rules
+    }
+
+    /// Whether the command or group at `path` can be used
+    pub fn allows(&self, path: &[&str]) -> bool {
+        self.rules
+            .iter()
+            .filter(|r| covers(&r.path, path))
+            .max_by_key(|r| {
+                (
+                    r.path.split_whitespace().count(),
+                    matches!(r.scope, Scope::Room(_)),
+                )
+            })
+            .map_or(true, |r| r.enabled)
+    }
+
+    /// Whether anything at or under `path` is enabled
+    fn allows_any_under(&self, path: &[&str], meta: &GroupMeta) -> bool {
+        self.allows(path)
+            || meta.inner.iter().any(|(name, child)| {
+                let mut child_path = path.to_vec();
+                child_path.push(name);
+
+                match child {
+                    GroupOrCommandMeta::Command(c) => c.policy_exempt || self.allows(&child_path),
+                    GroupOrCommandMeta::Group(g) => self.allows_any_under(&child_path, g),
+                }
+            })
+    }
+
+    /// A copy of `meta` with everything this policy disables left out, so help
+    /// only lists what can be used in the room
+    pub fn filter_meta(&self, meta: &GroupMeta) -> GroupMeta {
+        self.filter_meta_at(&[], meta)
+    }
+
+    fn filter_meta_at(&self, path: &[&str], meta: &GroupMeta) -> GroupMeta {
+        let mut inner = BTreeMap::new();
+
+        for (name, child) in &meta.inner {
+            let mut child_path = path.to_vec();
+            child_path.push(name);
+
+            match child {
+                GroupOrCommandMeta::Command(c) if c.policy_exempt || self.allows(&child_path) => {
+                    inner.insert(name.clone(), child.clone());
+                }
+                GroupOrCommandMeta::Group(g) if self.allows_any_under(&child_path, g) => {
+                    inner.insert(
+                        name.clone(),
+                        GroupOrCommandMeta::Group(self.filter_meta_at(&child_path, g)),
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        GroupMeta {
+            description: meta.description.clone(),
+            params: meta.params.clone(),
+            aliases: meta
+                .aliases
+                .iter()
+                .filter(|(_, target)| inner.contains_key(*target))
+                .map(|(a, t)| (a.clone(), t.clone()))
+                .collect(),
+            inner,
+            fallback: meta
+                .fallback
+                .clone()
+                .filter(|c| c.policy_exempt || self.allows(path)),
+        }
+    }
+}
+
+/// Check that the author has at least `required` power level, telling them
+/// why not if they don't
+pub async fn require_power_level<C: Context + Sync>(c: &C, required: i64) -> bool {
+    match c.power_level(c.author()).await {
+        Ok(level) if level >= required => true,
+        Ok(_) => {
+            let _ = c
+                .reply(&c.tr("policy-power-level", &[("level", required.into())]))
+                .await;
+            false
+        }
+        Err(err) => {
+            let _ = c
+                .reply(&c.tr(
+                    "policy-power-level-failed",
+                    &[("error", err.to_string().into())],
+                ))
+                .await;
+            false
+        }
+    }
+}
+
+/// Check that the author has at least `required` power level in `space`,
+/// telling them why not if they don't
+async fn require_space_power_level<C: Context + Sync>(
+    c: &C,
+    space: &RoomId,
+    required: i64,
+) -> bool {
+    match c.transport().space_power_level(space, c.author()).await {
+        Ok(level) if level >= required => true,
+        Ok(_) => {
+            let _ = c
+                .reply(&c.tr(
+                    "policy-space-power-level",
+                    &[
+                        ("level", required.into()),
+                        ("space", space.to_string().into()),
+                    ],
+                ))
+                .await;
+            false
+        }
+        Err(err) => {
+            let _ = c
+                .reply(&c.tr(
+                    "policy-power-level-failed",
+                    &[("error", err.to_string().into())],
+                ))
+                .await;
+            false
+        }
+    }
+}
+
+async fn set_rule<C: Context + Sync + 'static>(
+    c: &C,
+    store: &dyn PolicyStore,
+    space: bool,
+    path: Vec<String>,
+    enabled: Option<bool>,
+) {
+    let scopes = if space {
+        let spaces = match c.transport().parent_spaces().await {
+            Ok(spaces) if !spaces.is_empty() => spaces,
+            Ok(_) => {
+                let _ = c.reply(&c.tr("policy-no-space", &[])).await;
+                return;
+            }
+            Err(err) => {
+                let _ = c
+                    .reply(&c.tr("policy-spaces-failed", &[("error", err.to_string().into())]))
+                    .await;
+                return;
+            }
+        };
+
+        for space in &spaces {
+            if !require_space_power_level(c, space, ADMIN_LEVEL).await {
+                return;
+            }
+        }
+
+        spaces.into_iter().map(Scope::Space).collect()
+    } else {
+        if !require_power_level(c, MODERATOR_LEVEL).await {
+            return;
+        }
+
+        vec![Scope::Room(c.room_id().to_owned())]
+    };
+
+    let path = path.iter().map(String::as_str).collect::<Vec<_>>();
+
+    // the room's view of the tree leaves out whatever is disabled, which is
+    // what `enable` needs to find
+    let root = registry::registered_root(c);
+
+    // rules are matched against the names commands were registered under, so
+    // aliases are replaced here. Rules for commands that have since gone away
+    // can still be reset.
+    let path = match root.canonical_path(&path) {
+        Some(canonical) => canonical.join(" "),
+        None if enabled.is_none() => path.join(" "),
+        None => {
+            let _ = c
+                .reply(&c.tr("policy-no-such-command", &[("path", path.join(" ").into())]))
+                .await;
+            return;
+        }
+    };
+
+    for scope in scopes {
+        let result = match enabled {
+            Some(enabled) => {
+                store
+                    .set(PolicyRule {
+                        scope,
+                        path: path.clone(),
+                        enabled,
+                    })
+                    .await
+            }
+            None => store.clear(&scope, &path).await.map(|_| ()),
+        };
+
+        if let Err(err) = result {
+            let _ = c
+                .reply(&c.tr("policy-update-failed", &[("error", err.to_string().into())]))
+                .await;
+            return;
+        }
+    }
+
+    let _ = c.react("✅").await;
+}
+
+/// `enable`, `disable`, `reset` and `list` commands for managing the policy of
+/// the room they're used in, add these to the command tree under a name such
+/// as `commands`. They need `DispatcherBuilder::with_policy` to have been
+/// used, and can't themselves be disabled.
+pub fn commands<C: Context + Clone + Send + Sync + 'static>() -> GroupBuilder<C> {
+    type Store = State<Arc<dyn PolicyStore>>;
+    type Path = Named<Vec<String>, "path">;
+
+    GroupBuilder::new()
+        .with_description("enable or disable commands in this room")
+        .command(
+            "enable",
+            cmd(|c: C, State(store): Store, Named(path): Path| async move {
+                set_rule(&c, store.as_ref(), false, path, Some(true)).await;
+            })
+            .exempt_from_policy()
+            .with_description("enable a command or group in this room"),
+        )
+        .command(
+            "disable",
+            cmd(|c: C, State(store): Store, Named(path): Path| async move {
+                set_rule(&c, store.as_ref(), false, path, Some(false)).await;
+            })
+            .exempt_from_policy()
+            .with_description(
+                "disable a command or group in this room, with no path everything is disabled",
+            ),
+        )
+        .command(
+            "reset",
+            cmd(|c: C, State(store): Store, Named(path): Path| async move {
+                set_rule(&c, store.as_ref(), false, path, None).await;
+            })
+            .exempt_from_policy()
+            .with_description("remove this room's rule for a command or group"),
+        )
+        .group(
+            "space",
+            GroupBuilder::new()
+                .with_description("manage the policy of the spaces this room is in")
+                .command(
+                    "enable",
+                    cmd(|c: C, State(store): Store, Named(path): Path| async move {
+                        set_rule(&c, store.as_ref(), true, path, Some(true)).await;
+                    })
+                    .exempt_from_policy()
+                    .with_description("enable a command or group in every space this room is in"),
+                )
+                .command(
+                    "disable",
+                    cmd(|c: C, State(store): Store, Named(path): Path| async move {
+                        set_rule(&c, store.as_ref(), true, path, Some(false)).await;
+                    })
+                    .exempt_from_policy()
+                    .with_description("disable a command or group in every space this room is in, with no path everything is disabled"),
+                )
+                .command(
+                    "reset",
+                    cmd(|c: C, State(store): Store, Named(path): Path| async move {
+                        set_rule(&c, store.as_ref(), true, path, None).await;
+                    })
+                    .exempt_from_policy()
+                    .with_description("remove the rule for a command or group from every space this room is in"),
+                ),
+        )
+        .command(
+            "list",
+            cmd(|c: C, State(store): Store| async move {
+                let spaces = c.transport().parent_spaces().await.unwrap_or_default();
+
+                let policy = match Policy::load(store.as_ref(), c.room_id(), spaces).await {
+                    Ok(policy) => policy,
+                    Err(err) => {
+                        let _ = c
+                            .reply(
+                                &c.tr("policy-load-failed", &[("error", err.to_string().into())]),
+                            )
+                            .await;
+                        return;
+                    }
+                };
+
+                if policy.rules().is_empty() {
+                    let _ = c.reply(&c.tr("policy-all-enabled", &[])).await;
+                    return;
+                }
+
+                let lines = policy
+                    .rules()
+                    .iter()
+                    .map(|r| {
+                        let path = if r.path.is_empty() {
+                            c.tr("policy-everything", &[])
+                        } else {
+                            r.path.clone()
+                        };
+                        let scope = match &r.scope {
+                            Scope::Room(_) => c.tr("policy-scope-room", &[]),
+                            Scope::Space(space) => {
+                                c.tr("policy-scope-space", &[("space", space.to_string().into())])
+                            }
+                        };
+                        let id = if r.enabled {
+                            "policy-rule-enabled"
+                        } else {
+                            "policy-rule-disabled"
+                        };
+
+                        c.tr(id, &[("path", path.into()), ("scope", scope.into())])
+                    })
+                    .collect::<Vec<_>>();
+
+                let _ = c.reply(&lines.join("\n")).await;
+            })
+            .exempt_from_policy()
+            .with_description("list the rules that apply in this room"),
+        )
+}
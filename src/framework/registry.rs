@@ -0,0 +1,238 @@
+use std::sync::{Arc, RwLock};
+
+use crate::framework::{
+    commands::{
+        BuildError, BuildErrors, BuilderEntry, ErasedCommand, Group, GroupBuilder, GroupMeta,
+        GroupOrCommand,
+    },
+    context::Context,
+};
+
+/// A version of the command tree along with its metadata, dispatching keeps
+/// using the snapshot it started with even if the registry changes meanwhile
+pub struct Snapshot<C> {
+    pub commands: Group<C>,
+    pub meta: Arc<GroupMeta>,
+}
+
+impl<C> Snapshot<C> {
+    fn new(commands: Group<C>) -> Self {
+        let meta = Arc::new(commands.meta());
+        Self { commands, meta }
+    }
+}
+
+/// The command tree of a running bot, commands and groups can be registered
+/// and unregistered without restarting the sync loop.
+///
+/// Changes are made to a copy of the tree which then replaces the current one,
+/// so lookups never wait on a change. Groups are shared between the copies,
+/// only those on the way to what changed are copied and only what's added is
+/// built. The dispatcher adds an `Arc<Registry<C>>` to the bot's state,
+/// commands can get it with `State<T>`.
+pub struct Registry<C> {
+    current: RwLock<Arc<Snapshot<C>>>,
+}
+
+/// The whole command tree `c`'s command was found in, including what the
+/// room's policy leaves out of `Context::root`. Check names against this
+/// before taking them, a disabled command can be enabled again.
+pub fn registered_root<C: Context + Sync + 'static>(c: &C) -> Arc<GroupMeta> {
+    match c.state().get::<Arc<Registry<C>>>() {
+        Some(registry) => registry.meta(),
+        None => Arc::new(c.root().clone()),
+    }
+}
+
+fn path_string(path: &[&str]) -> String {
+    path.join(" ")
+}
+
+impl<C> Registry<C> {
+    pub fn new(commands: Group<C>) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(Snapshot::new(commands))),
+        }
+    }
+
+    /// The current command tree
+    pub fn snapshot(&self) -> Arc<Snapshot<C>> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn meta(&self) -> Arc<GroupMeta> {
+        self.snapshot().meta.clone()
+    }
+
+    /// Apply a change to a shallow copy of the tree, replacing the current tree
+    /// if it succeeds
+    fn update<T>(
+        &self,
+        f: impl FnOnce(&mut Group<C>) -> Result<T, BuildErrors>,
+    ) -> Result<T, BuildErrors> {
+        let mut current = self.current.write().unwrap();
+
+        let mut commands = current.commands.clone();
+        let out = f(&mut commands)?;
+
+        *current = Arc::new(Snapshot::new(commands));
+
+        Ok(out)
+    }
+
+    /// Add `entry` to the group at `path`, checking it against what's already
+    /// there
+    fn add_at(&self, path: &[&str], name: &str, entry: BuilderEntry<C>) -> Result<(), BuildErrors> {
+        self.update(|commands| {
+            let group = commands.find_group_mut(path).ok_or_else(|| {
+                BuildErrors(vec![BuildError::NoSuchGroup {
+                    path: path_string(path),
+                }])
+            })?;
+
+            let mut errors = Vec::new();
+            group.insert(&path_string(path), name, entry, &mut errors);
+
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(BuildErrors(errors))
+            }
+        })
+    }
+
+    /// Add a command to the group at `path`, `&[]` being the root
+    pub fn register(
+        &self,
+        path: &[&str],
+        name: &str,
+        command: ErasedCommand<C>,
+    ) -> Result<(), BuildErrors> {
+        self.add_at(path, name, BuilderEntry::Command(command))
+    }
+
+    /// Add a group to the group at `path`, `&[]` being the root
+    pub fn register_group(
+        &self,
+        path: &[&str],
+        name: &str,
+        group: impl Into<GroupBuilder<C>>,
+    ) -> Result<(), BuildErrors> {
+        self.add_at(path, name, BuilderEntry::Group(group.into()))
+    }
+
+    /// Remove the command or group at `path`, returning it so it can be
+    /// registered again later
+    pub fn unregister(&self, path: &[&str]) -> Option<GroupOrCommand<C>> {
+        let (name, parent) = path.split_last()?;
+
+        self.update(|commands| {
+            Ok(commands
+                .find_group_mut(parent)
+                .and_then(|group| group.remove(name)))
+        })
+        .ok()
+        .flatten()
+    }
+
+    /// Replace the whole tree
+    pub fn replace(&self, commands: Group<C>) {
+        *self.current.write().unwrap() = Arc::new(Snapshot::new(commands));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framework::commands::cmd;
+
+    fn noop() -> ErasedCommand<()> {
+        cmd(|_: ()| async {})
+    }
+
+    fn registry() -> Registry<()> {
+        let commands = GroupBuilder::new()
+            .command("a", noop().with_aliases(&["b"]))
+            .group("g", GroupBuilder::new().command("c", noop()))
+            .group("h", GroupBuilder::new().command("d", noop()))
+            .build()
+            .unwrap();
+
+        Registry::new(commands)
+    }
+
+    fn group(registry: &Registry<()>, name: &str) -> Arc<Group<()>> {
+        match registry.snapshot().commands.get(name) {
+            Some(GroupOrCommand::Group(g)) => g.clone(),
+            _ => panic!("No group {}", name),
+        }
+    }
+
+    #[test]
+    fn only_groups_on_the_path_are_copied() {
+        let registry = registry();
+        let (g, h) = (group(&registry, "g"), group(&registry, "h"));
+
+        registry.register(&["g"], "e", noop()).unwrap();
+
+        assert!(!Arc::ptr_eq(&g, &group(&registry, "g")));
+        assert!(Arc::ptr_eq(&h, &group(&registry, "h")));
+        assert!(registry
+            .snapshot()
+            .commands
+            .find_command(&["g", "e"])
+            .is_some());
+    }
+
+    #[test]
+    fn conflicts_are_reported() {
+        let registry = registry();
+
+        let errors = |result: Result<(), BuildErrors>| result.unwrap_err().0;
+
+        assert_eq!(
+            errors(registry.register(&[], "a", noop())),
+            vec![BuildError::DuplicateCommand {
+                path: "a".to_owned()
+            }]
+        );
+        assert_eq!(
+            errors(registry.register(&[], "b", noop())),
+            vec![BuildError::AliasClash {
+                path: "a".to_owned(),
+                alias: "b".to_owned()
+            }]
+        );
+        assert_eq!(
+            errors(registry.register(&["nope"], "x", noop())),
+            vec![BuildError::NoSuchGroup {
+                path: "nope".to_owned()
+            }]
+        );
+
+        // nothing is changed when adding fails
+        assert!(registry.snapshot().commands.find_command(&["b"]).is_some());
+    }
+
+    #[test]
+    fn commands_sharing_a_groups_name_become_its_fallback() {
+        let registry = registry();
+
+        registry.register(&[], "g", noop()).unwrap();
+        assert!(group(&registry, "g").fallback.is_some());
+
+        registry
+            .register_group(&[], "a", GroupBuilder::new().command("f", noop()))
+            .unwrap();
+        assert!(group(&registry, "a").fallback.is_some());
+    }
+
+    #[test]
+    fn empty_groups_dont_stop_registering() {
+        let registry = registry();
+
+        assert!(registry.unregister(&["g", "c"]).is_some());
+        registry.register(&[], "e", noop()).unwrap();
+        registry.register(&["g"], "c", noop()).unwrap();
+    }
+}
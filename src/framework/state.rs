@@ -0,0 +1,63 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    error::Error,
+};
+
+use crate::framework::{
+    commands::{BoxFuture, Parameter, ResolveError},
+    context::Context,
+};
+
+/// Shared values attached to the bot, keyed by their type
+#[derive(Default)]
+pub struct StateMap {
+    inner: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl StateMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a value, replacing any existing value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.inner.insert(TypeId::of::<T>(), Box::new(value));
+    }
+
+    pub fn get<T: 'static>(&self) -> Option<&T> {
+        self.inner
+            .get(&TypeId::of::<T>())
+            .and_then(|v| v.downcast_ref())
+    }
+}
+
+/// A clone of a value registered on the bot with
+/// `DispatcherBuilder::with_state`
+pub struct State<T>(pub T);
+
+impl<C: Context, T: Clone + Send + Sync + 'static> Parameter<C> for State<T> {
+    const INFO: &'static str = std::any::type_name::<T>();
+    const VISIBLE: bool = false;
+
+    type Parsed = Self;
+
+    fn parse<'a>(ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+        let value = ctx.state().get::<T>().cloned().ok_or_else(|| {
+            format!(
+                "No state of type {} was registered",
+                std::any::type_name::<T>()
+            )
+        })?;
+
+        Ok((input, State(value)))
+    }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
+}
@@ -0,0 +1,198 @@
+use std::{error::Error, time::Duration};
+
+use chrono::{DateTime, Utc};
+use matrix_sdk::{
+    room::Joined,
+    ruma::{
+        api::client::r0::{
+            profile::get_profile,
+            room::get_room_event,
+            typing::create_typing_event::{self, Typing},
+        },
+        events::{
+            room::message::{MessageType, RoomMessageEventContent},
+            AnyMessageEvent, AnyRoomEvent, EventType, MessageEvent,
+        },
+        EventId, MxcUri, RoomAliasId, RoomId, UserId,
+    },
+    Client,
+};
+use mime::Mime;
+
+pub type TransportError = Box<dyn Error + Send + Sync>;
+
+pub type Result<T> = std::result::Result<T, TransportError>;
+
+/// Everything a command can do to the room it was invoked in.
+///
+/// The framework only talks to the network through this, so commands can be
+/// run against `MatrixTransport` or the mock transport in `bleh::testing`.
+#[async_trait::async_trait]
+pub trait Transport: Send + Sync + 'static {
+    /// Send an event to the room, returning its id
+    async fn send_event(
+        &self,
+        event_type: &str,
+        content: serde_json::Value,
+    ) -> Result<Box<EventId>>;
+
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> Result<()>;
+
+    /// Show a typing notice for `timeout`, or clear it with `None`
+    async fn typing(&self, timeout: Option<Duration>) -> Result<()>;
+
+    async fn read_receipt(&self, event_id: &EventId) -> Result<()>;
+
+    /// Fetch a message event from the room, returns `None` if the event isn't
+    /// an unencrypted `m.room.message`
+    async fn fetch_message(
+        &self,
+        event_id: &EventId,
+    ) -> Result<Option<MessageEvent<RoomMessageEventContent>>>;
+
+    /// Upload to the media repository, returning the `mxc://` uri
+    async fn upload(&self, mimetype: &Mime, data: &[u8]) -> Result<Box<MxcUri>>;
+
+    /// Download the media attached to a message, returns `None` if the message
+    /// type doesn't carry any
+    async fn download(&self, msgtype: &MessageType) -> Result<Option<Vec<u8>>>;
+
+    async fn resolve_room_alias(&self, alias: &RoomAliasId) -> Result<Box<RoomId>>;
+
+    /// The display name and avatar of a user
+    async fn profile(&self, user_id: &UserId) -> Result<(Option<String>, Option<Box<MxcUri>>)>;
+
+    /// The power level of a user in the room
+    async fn power_level(&self, user_id: &UserId) -> Result<i64>;
+
+    /// The spaces the room says it belongs to through `m.space.parent`
+    async fn parent_spaces(&self) -> Result<Vec<Box<RoomId>>> {
+        Ok(Vec::new())
+    }
+
+    /// The power level of a user in one of the room's spaces
+    async fn space_power_level(&self, space_id: &RoomId, user_id: &UserId) -> Result<i64>;
+
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// A transport sending to a joined matrix room
+#[derive(Clone)]
+pub struct MatrixTransport {
+    pub client: Client,
+    pub room: Joined,
+}
+
+impl MatrixTransport {
+    pub fn new(client: Client, room: Joined) -> Self {
+        Self { client, room }
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for MatrixTransport {
+    async fn send_event(
+        &self,
+        event_type: &str,
+        content: serde_json::Value,
+    ) -> Result<Box<EventId>> {
+        Ok(self
+            .room
+            .send_raw(content, event_type, None)
+            .await?
+            .event_id)
+    }
+
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> Result<()> {
+        self.room.redact(event_id, reason, None).await?;
+
+        Ok(())
+    }
+
+    // `Joined::typing_notice` uses a short timeout and throttles resends, so
+    // the request is sent directly
+    async fn typing(&self, timeout: Option<Duration>) -> Result<()> {
+        let user_id = self.client.user_id().await.ok_or("Not logged in")?;
+        let typing = timeout.map_or(Typing::No, Typing::Yes);
+        let request = create_typing_event::Request::new(&user_id, self.room.room_id(), typing);
+        self.client.send(request, None).await?;
+
+        Ok(())
+    }
+
+    async fn read_receipt(&self, event_id: &EventId) -> Result<()> {
+        Ok(self.room.read_receipt(event_id).await?)
+    }
+
+    async fn fetch_message(
+        &self,
+        event_id: &EventId,
+    ) -> Result<Option<MessageEvent<RoomMessageEventContent>>> {
+        let request = get_room_event::Request::new(self.room.room_id(), event_id);
+        let response = self.client.send(request, None).await?;
+
+        match response.event.deserialize()? {
+            AnyRoomEvent::Message(AnyMessageEvent::RoomMessage(event)) => Ok(Some(event)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn upload(&self, mimetype: &Mime, mut data: &[u8]) -> Result<Box<MxcUri>> {
+        Ok(self.client.upload(mimetype, &mut data).await?.content_uri)
+    }
+
+    async fn download(&self, msgtype: &MessageType) -> Result<Option<Vec<u8>>> {
+        let data = match msgtype {
+            MessageType::Image(c) => self.client.get_file(c.clone(), true).await?,
+            MessageType::File(c) => self.client.get_file(c.clone(), true).await?,
+            MessageType::Audio(c) => self.client.get_file(c.clone(), true).await?,
+            MessageType::Video(c) => self.client.get_file(c.clone(), true).await?,
+            _ => None,
+        };
+
+        Ok(data)
+    }
+
+    async fn resolve_room_alias(&self, alias: &RoomAliasId) -> Result<Box<RoomId>> {
+        Ok(self.client.resolve_room_alias(alias).await?.room_id)
+    }
+
+    async fn profile(&self, user_id: &UserId) -> Result<(Option<String>, Option<Box<MxcUri>>)> {
+        let request = get_profile::Request::new(user_id);
+        let response = self.client.send(request, None).await?;
+
+        Ok((response.displayname, response.avatar_url))
+    }
+
+    async fn power_level(&self, user_id: &UserId) -> Result<i64> {
+        let member = self.room.get_member(user_id).await?;
+
+        Ok(member.map_or(0, |m| m.power_level()))
+    }
+
+    async fn parent_spaces(&self) -> Result<Vec<Box<RoomId>>> {
+        let events = self.room.get_state_events(EventType::SpaceParent).await?;
+
+        let spaces = events
+            .into_iter()
+            .filter_map(|raw| {
+                let event: serde_json::Value = serde_json::from_str(raw.json().get()).ok()?;
+                RoomId::parse(event["state_key"].as_str()?).ok()
+            })
+            .collect();
+
+        Ok(spaces)
+    }
+
+    async fn space_power_level(&self, space_id: &RoomId, user_id: &UserId) -> Result<i64> {
+        let space = self
+            .client
+            .get_joined_room(space_id)
+            .ok_or_else(|| format!("I'm not in the space {}", space_id))?;
+        let member = space.get_member(user_id).await?;
+
+        Ok(member.map_or(0, |m| m.power_level()))
+    }
+}
@@ -0,0 +1,276 @@
+//! Commands run by text matching a pattern, without the prefix.
+//!
+//! A trigger pairs a regex with an ordinary command, added with
+//! `DispatcherBuilder::with_trigger`. Every trigger matching a text message
+//! that doesn't start with the prefix runs, with the capture groups as the
+//! command's input: each group is one argument, taken with `Capture`, and the
+//! last can be taken whole with `Remainder`. Without groups the command gets
+//! the whole message.
+//!
+//! Triggers only see `m.text` messages and never the bot's own, so they don't
+//! answer each other, and a cooldown keeps one from firing more than once in
+//! a while in each room. A room's policy disables a trigger along with the
+//! command path given with `Trigger::with_path`. Errors are logged rather than
+//! sent, as nobody asked for the trigger.
+
+use std::{collections::HashMap, error::Error, sync::Arc, sync::Mutex, time::Duration};
+
+use chrono::{DateTime, Utc};
+use regex::Regex;
+
+use crate::framework::commands::{BoxFuture, ErasedCommand, Parameter, ResolveError};
+
+/// When things last happened, for rate limiting them
+#[derive(Debug, Default)]
+pub struct Cooldowns {
+    last: Mutex<HashMap<String, DateTime<Utc>>>,
+}
+
+impl Cooldowns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `key` last happened at least `cooldown` before `now`, recording
+    /// that it happens now if so
+    pub fn try_start(&self, key: &str, now: DateTime<Utc>, cooldown: Duration) -> bool {
+        let cooldown =
+            chrono::Duration::from_std(cooldown).unwrap_or_else(|_| chrono::Duration::max_value());
+        let mut last = self.last.lock().unwrap();
+
+        match last.get(key) {
+            Some(&at) if now.signed_duration_since(at) < cooldown => false,
+            _ => {
+                last.insert(key.to_owned(), now);
+                true
+            }
+        }
+    }
+}
+
+/// Whether a group has to be quoted to be a single argument
+fn needs_quotes(c: char) -> bool {
+    c.is_whitespace() || c == '"'
+}
+
+/// A string in quotes, where `\"` is a quote that doesn't end it
+fn quoted_string(input: &str) -> nom::IResult<&str, String> {
+    let (rest, _) = nom::bytes::complete::tag("\"")(input)?;
+    let mut out = String::new();
+    let mut chars = rest.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&rest[i + 1..], out)),
+            '\\' if rest[i + 1..].starts_with('"') => {
+                chars.next();
+                out.push('"');
+            }
+            c => out.push(c),
+        }
+    }
+
+    Err(nom::Err::Error(nom::error::Error::new(
+        input,
+        nom::error::ErrorKind::TakeUntil,
+    )))
+}
+
+/// One capture group given to a trigger's command. Groups with spaces or
+/// quotes are passed in quotes with theirs escaped as `\"`, which `String`
+/// leaves alone so `"C:\"` is still a whole string in commands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Capture(pub String);
+
+impl<C> Parameter<C> for Capture {
+    const INFO: &'static str = "Capture";
+    const VISIBLE: bool = true;
+
+    type Parsed = Self;
+
+    fn parse<'a>(_ctx: &C, input: &'a str) -> Result<(&'a str, Self), Box<dyn Error + 'a>> {
+        nom::sequence::delimited(
+            nom::character::complete::multispace0,
+            nom::branch::alt((
+                quoted_string,
+                nom::combinator::map(
+                    nom::bytes::complete::take_till1(|c: char| c.is_whitespace()),
+                    String::from,
+                ),
+            )),
+            nom::character::complete::multispace0,
+        )(input)
+        .map(|(rest, s)| (rest, Capture(s)))
+        .map_err(|e: nom::Err<nom::error::Error<_>>| e.into())
+    }
+
+    fn resolve<'c>(parsed: Self, _ctx: &'c C) -> BoxFuture<'c, Result<Self, ResolveError>>
+    where
+        Self: 'c,
+        C: Sync,
+    {
+        Box::pin(async move { Ok(parsed) })
+    }
+}
+
+/// A command run when a message matches a pattern
+#[derive(derivative::Derivative)]
+#[derivative(Clone(bound = ""))]
+pub struct Trigger<C> {
+    pub name: String,
+    pub pattern: Regex,
+    pub command: ErasedCommand<C>,
+    pub cooldown: Duration,
+    /// The command path whose policy applies to the trigger, empty unless
+    /// set with `with_path`
+    pub path: Vec<String>,
+    cooldowns: Arc<Cooldowns>,
+}
+
+impl<C> Trigger<C> {
+    pub fn new(name: &str, pattern: Regex, command: ErasedCommand<C>) -> Self {
+        Self {
+            name: name.to_owned(),
+            pattern,
+            command,
+            cooldown: Duration::ZERO,
+            path: Vec::new(),
+            cooldowns: Arc::default(),
+        }
+    }
+
+    pub fn regex(
+        name: &str,
+        pattern: &str,
+        command: ErasedCommand<C>,
+    ) -> Result<Self, regex::Error> {
+        Ok(Self::new(name, Regex::new(pattern)?, command))
+    }
+
+    /// Match `keyword` as a whole word, ignoring case
+    pub fn keyword(name: &str, keyword: &str, command: ErasedCommand<C>) -> Self {
+        let pattern = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(keyword)))
+            .expect("Escaped keywords are valid regexes");
+
+        Self::new(name, pattern, command)
+    }
+
+    /// Fire at most once per `cooldown` in each room
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// The input to give the command for a message, if it matches
+    pub fn input(&self, body: &str) -> Option<String> {
+        let captures = self.pattern.captures(body)?;
+
+        if captures.len() == 1 {
+            return Some(body.to_owned());
+        }
+
+        let last = captures.len() - 1;
+        let args = captures
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, group)| match group.map(|m| m.as_str()) {
+                Some(group) if i == last => group.to_owned(),
+                Some(group) if !group.is_empty() && !group.contains(needs_quotes) => {
+                    group.to_owned()
+                }
+                // quoted, so empty groups and those that didn't take part
+                // still take their place
+                group => format!("\"{}\"", group.unwrap_or_default().replace('"', "\\\"")),
+            })
+            .collect::<Vec<_>>();
+
+        Some(args.join(" "))
+    }
+
+    /// Disable the trigger wherever the command or group at `path` is
+    /// disabled by the room's policy, rather than only when everything is
+    pub fn with_path(mut self, path: &[&str]) -> Self {
+        self.path = path.iter().map(|&s| s.to_owned()).collect();
+        self
+    }
+
+    /// Whether the trigger is off cooldown in `room`, starting the cooldown
+    /// if so. Only called once the command has parsed, so messages it can't
+    /// use don't hold it back.
+    pub(crate) fn try_fire(&self, room: &str, now: DateTime<Utc>) -> bool {
+        self.cooldowns.try_start(room, now, self.cooldown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::framework::commands::cmd;
+
+    fn trigger(pattern: &str) -> Trigger<()> {
+        Trigger::regex("test", pattern, cmd(|_: ()| async {})).unwrap()
+    }
+
+    #[test]
+    fn whole_message_without_groups() {
+        assert_eq!(
+            trigger("hi").input("oh hi there"),
+            Some("oh hi there".to_owned())
+        );
+        assert_eq!(trigger("hi").input("hello"), None);
+    }
+
+    #[test]
+    fn groups_are_arguments() {
+        let input = trigger(r"(\w+) says (.+)").input("bob says hi there");
+
+        assert_eq!(input, Some("bob hi there".to_owned()));
+    }
+
+    #[test]
+    fn groups_with_spaces_or_quotes_are_quoted() {
+        let input = trigger(r"(.+) = (.+)").input(r#"a "b" c = d e"#);
+
+        assert_eq!(input, Some(r#""a \"b\" c" d e"#.to_owned()));
+    }
+
+    #[test]
+    fn captures_unescape_quotes() {
+        let parse = |input| <Capture as Parameter<()>>::parse(&(), input).unwrap();
+
+        assert_eq!(
+            parse(r#""a \"b\" c" d e"#),
+            ("d e", Capture(r#"a "b" c"#.to_owned()))
+        );
+        assert_eq!(parse(r#""" b"#), ("b", Capture(String::new())));
+        assert_eq!(parse("a b"), ("b", Capture("a".to_owned())));
+    }
+
+    #[test]
+    fn empty_groups_keep_their_place() {
+        let input = trigger(r"(a)?(x*)-(\w+)").input("-b");
+
+        assert_eq!(input, Some(r#""" "" b"#.to_owned()));
+    }
+
+    #[test]
+    fn keywords_are_whole_words() {
+        let t = Trigger::keyword("test", "ping", cmd(|_: ()| async {}));
+
+        assert!(t.input("PING me").is_some());
+        assert!(t.input("pinged").is_none());
+    }
+
+    #[test]
+    fn cooldowns() {
+        let cooldowns = Cooldowns::new();
+        let now = Utc::now();
+        let minute = Duration::from_secs(60);
+
+        assert!(cooldowns.try_start("a", now, minute));
+        assert!(!cooldowns.try_start("a", now + chrono::Duration::seconds(59), minute));
+        assert!(cooldowns.try_start("b", now, minute));
+        assert!(cooldowns.try_start("a", now + chrono::Duration::seconds(60), minute));
+    }
+}
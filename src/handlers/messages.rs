@@ -1,54 +1,55 @@
 use std::sync::Arc;
 
 use matrix_sdk::{
+    event_handler::RawEvent,
     room::Room,
-    ruma::events::room::message::{MessageType, SyncRoomMessageEvent, TextMessageEventContent},
+    ruma::{events::room::message::SyncRoomMessageEvent, EventId},
     Client,
 };
 
 use crate::framework::{
-    commands::{Group, GroupMeta},
-    context::BaseContext,
+    dispatch::{Dispatcher, Incoming},
+    transport::MatrixTransport,
 };
 
 pub struct OnMessage<C> {
-    prefix: Arc<String>,
     client: Client,
-    commands: Arc<Group<C>>,
-    commands_meta: Arc<GroupMeta>,
-    build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
+    dispatcher: Arc<Dispatcher<C>>,
 }
 
-impl<C: Send + 'static> OnMessage<C> {
-    pub fn new(
-        prefix: String,
-        client: Client,
-        commands: Group<C>,
-        build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
-    ) -> Self {
-        let commands_meta = Arc::new(commands.meta());
+/// Pull the thread root out of the raw event, ruma doesn't know about
+/// `m.thread` relations yet
+fn thread_root(raw: &RawEvent) -> Option<Box<EventId>> {
+    let event: serde_json::Value = serde_json::from_str(raw.0.get()).ok()?;
+    let relation = &event["content"]["m.relates_to"];
+
+    match relation["rel_type"].as_str()? {
+        "m.thread" | "io.element.thread" => EventId::parse(relation["event_id"].as_str()?).ok(),
+        _ => None,
+    }
+}
+
+impl<C: Send + Sync + 'static> OnMessage<C> {
+    pub fn new(client: Client, dispatcher: Dispatcher<C>) -> Self {
         Self {
-            prefix: Arc::new(prefix),
             client,
-            commands: Arc::new(commands),
-            commands_meta,
-            build_context,
+            dispatcher: Arc::new(dispatcher),
         }
     }
 
     pub async fn register(self, client: Client) {
         let self_ = Arc::new(self);
         client
-            .register_event_handler(move |message, room| {
+            .register_event_handler(move |message, room, raw| {
                 let self_ = self_.clone();
                 async move {
-                    self_.on_room_message(room, message).await;
+                    self_.on_room_message(room, message, raw).await;
                 }
             })
             .await;
     }
 
-    async fn on_room_message(&self, room: Room, message: SyncRoomMessageEvent) {
+    async fn on_room_message(&self, room: Room, message: SyncRoomMessageEvent, raw: RawEvent) {
         let room = match room {
             Room::Joined(room) => room,
             _ => return,
@@ -58,31 +59,13 @@ impl<C: Send + 'static> OnMessage<C> {
             return;
         }
 
-        let msg_body = match &message.content.msgtype {
-            MessageType::Text(TextMessageEventContent { body: msg_body, .. }) => msg_body.as_str(),
-            _ => return,
-        };
-
-        let rest = match msg_body.strip_prefix(self.prefix.as_str()) {
-            Some(rest) => rest,
-            None => return,
-        };
-
-        let (cmd, rest) = match self.commands.find_command_parsing(rest) {
-            Some(x) => x,
-            None => return,
+        let incoming = Incoming {
+            room_id: room.room_id().to_owned(),
+            transport: Arc::new(MatrixTransport::new(self.client.clone(), room)),
+            thread_root: thread_root(&raw),
+            event: message,
         };
 
-        let base_ctx = BaseContext {
-            client: self.client.clone(),
-            author: message.sender.clone(),
-            room,
-            original_event: message.clone(),
-            root: self.commands_meta.clone(),
-        };
-
-        let ctx = (self.build_context)(base_ctx);
-
-        let _ = cmd.invoke(ctx, rest).await;
+        self.dispatcher.dispatch(incoming).await;
     }
 }
@@ -2,20 +2,59 @@ use std::sync::Arc;
 
 use matrix_sdk::Client;
 
-use crate::framework::{commands::Group, context::BaseContext};
+use crate::framework::{
+    commands::Group,
+    context::BaseContext,
+    dispatch::{Dispatcher, DispatcherBuilder},
+    registry::Registry,
+};
 
 mod autojoin;
 mod messages;
 
-pub async fn register_handlers<C: Send + 'static>(
+/// Everything needed to run a bot on top of a logged in client
+pub struct Bot<C> {
     client: Client,
-    commands: Group<C>,
-    build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
-) {
-    autojoin::OnJoin::new(client.clone())
-        .register(client.clone())
-        .await;
-    messages::OnMessage::new("!".to_owned(), client.clone(), commands, build_context)
-        .register(client)
-        .await;
+    dispatcher: Dispatcher<C>,
+}
+
+impl Bot<BaseContext> {
+    pub fn new(client: Client, commands: Group<BaseContext>) -> Self {
+        Self::with_context(client, commands, Arc::new(|base| base))
+    }
+}
+
+impl<C> DispatcherBuilder<C> for Bot<C> {
+    fn dispatcher_mut(&mut self) -> &mut Dispatcher<C> {
+        &mut self.dispatcher
+    }
+}
+
+impl<C: Send + Sync + 'static> Bot<C> {
+    /// Use a custom context type, built from the `BaseContext` of each message
+    pub fn with_context(
+        client: Client,
+        commands: Group<C>,
+        build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
+    ) -> Self {
+        Self {
+            client,
+            dispatcher: Dispatcher::new(commands, build_context),
+        }
+    }
+
+    /// The command tree, keep this around to change commands once the bot is
+    /// running
+    pub fn registry(&self) -> Arc<Registry<C>> {
+        self.dispatcher.registry().clone()
+    }
+
+    pub async fn register(self) {
+        autojoin::OnJoin::new(self.client.clone())
+            .register(self.client.clone())
+            .await;
+        messages::OnMessage::new(self.client.clone(), self.dispatcher)
+            .register(self.client)
+            .await;
+    }
 }
@@ -8,3 +8,7 @@
 #[macro_use]
 pub mod framework;
 pub mod handlers;
+pub mod repl;
+pub mod testing;
+
+pub use bleh_macros::{command, group};
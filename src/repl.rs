@@ -0,0 +1,390 @@
+//! Drive a command tree from a terminal instead of a matrix room.
+//!
+//! Each line read is sent as a text message, anything the bot sends back is
+//! printed with its HTML rendered as plain text. Lines starting with `:` change
+//! who is talking and where, see `:help`.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use matrix_sdk::ruma::{
+    events::{
+        room::message::{MessageType, RoomMessageEventContent},
+        MessageEvent,
+    },
+    EventId, MxcUri, RoomAliasId, RoomId, UserId,
+};
+use mime::Mime;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::{
+    framework::{
+        commands::Group,
+        context::BaseContext,
+        dispatch::{Dispatcher, DispatcherBuilder, Incoming},
+        transport::{self, Transport},
+    },
+    testing::{Action, MockTransport},
+};
+
+const HELP: &str = "\
+:user <user id>   send following messages as another user
+:room <room id>   send following messages to another room
+:power <level>    set the current user's power level in the current room
+:reply <message>  send a message replying to the bot's last message
+:quit             exit";
+
+/// Render the HTML subset matrix clients send as plain text, reply fallbacks
+/// are dropped
+pub fn html_to_plain(html: &str) -> String {
+    let mut out = String::new();
+    let mut rest = html;
+    let mut in_reply = false;
+
+    while !rest.is_empty() {
+        let (text, tag) = match rest.find('<') {
+            Some(start) => {
+                let end = rest[start..]
+                    .find('>')
+                    .map_or(rest.len(), |end| start + end + 1);
+                (&rest[..start], &rest[start..end])
+            }
+            None => (rest, ""),
+        };
+        rest = &rest[text.len() + tag.len()..];
+
+        if !in_reply {
+            out.push_str(
+                &text
+                    .replace("&lt;", "<")
+                    .replace("&gt;", ">")
+                    .replace("&quot;", "\"")
+                    .replace("&#39;", "'")
+                    .replace("&nbsp;", " ")
+                    .replace("&amp;", "&"),
+            );
+        }
+
+        let name = tag
+            .trim_start_matches('<')
+            .trim_end_matches('>')
+            .trim_end_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+
+        match name.as_str() {
+            "mx-reply" => in_reply = true,
+            "/mx-reply" => in_reply = false,
+            _ if in_reply => {}
+            "br" | "/p" | "/div" | "/li" | "/pre" | "/blockquote" | "/h1" | "/h2" | "/h3"
+            | "/h4" | "/h5" | "/h6" => out.push('\n'),
+            "li" => out.push_str("- "),
+            "blockquote" => out.push_str("> "),
+            _ => {}
+        }
+    }
+
+    out.trim_end().to_owned()
+}
+
+fn render_message(content: &serde_json::Value) -> String {
+    // edits are shown as their new content
+    let (content, edited) = match content.get("m.new_content") {
+        Some(new_content) => (new_content, " (edited)"),
+        None => (content, ""),
+    };
+
+    let body = match content["formatted_body"].as_str() {
+        Some(html) if content["format"] == "org.matrix.custom.html" => html_to_plain(html),
+        _ => content["body"].as_str().unwrap_or_default().to_owned(),
+    };
+
+    match content["msgtype"].as_str() {
+        Some("m.emote") => format!("* bot {}{}", body, edited),
+        Some("m.image") | Some("m.file") | Some("m.audio") | Some("m.video") => format!(
+            "[{}: {} {}]",
+            content["msgtype"].as_str().unwrap_or_default(),
+            body,
+            content["url"].as_str().unwrap_or_default()
+        ),
+        _ => format!("{}{}", body, edited),
+    }
+}
+
+/// A transport that prints everything the bot sends, keeping messages and
+/// media in memory like `MockTransport`
+pub struct ReplTransport {
+    inner: MockTransport,
+}
+
+impl ReplTransport {
+    pub fn new(room_id: &RoomId) -> Self {
+        Self {
+            inner: MockTransport::new(room_id),
+        }
+    }
+
+    pub fn inner(&self) -> &MockTransport {
+        &self.inner
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for ReplTransport {
+    async fn send_event(
+        &self,
+        event_type: &str,
+        content: serde_json::Value,
+    ) -> transport::Result<Box<EventId>> {
+        let line = match event_type {
+            "m.room.message" => render_message(&content),
+            "m.reaction" => format!(
+                "[reacted {} to {}]",
+                content["m.relates_to"]["key"].as_str().unwrap_or_default(),
+                content["m.relates_to"]["event_id"]
+                    .as_str()
+                    .unwrap_or_default()
+            ),
+            _ => format!("[{}: {}]", event_type, content),
+        };
+
+        let event_id = self.inner.send_event(event_type, content).await?;
+
+        println!("{} {}", event_id, line);
+
+        Ok(event_id)
+    }
+
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> transport::Result<()> {
+        println!(
+            "[redacted {}{}]",
+            event_id,
+            reason.map_or(String::new(), |r| format!(": {}", r))
+        );
+
+        self.inner.redact(event_id, reason).await
+    }
+
+    async fn typing(&self, timeout: Option<Duration>) -> transport::Result<()> {
+        self.inner.typing(timeout).await
+    }
+
+    async fn read_receipt(&self, event_id: &EventId) -> transport::Result<()> {
+        self.inner.read_receipt(event_id).await
+    }
+
+    async fn fetch_message(
+        &self,
+        event_id: &EventId,
+    ) -> transport::Result<Option<MessageEvent<RoomMessageEventContent>>> {
+        self.inner.fetch_message(event_id).await
+    }
+
+    async fn upload(&self, mimetype: &Mime, data: &[u8]) -> transport::Result<Box<MxcUri>> {
+        self.inner.upload(mimetype, data).await
+    }
+
+    async fn download(&self, msgtype: &MessageType) -> transport::Result<Option<Vec<u8>>> {
+        self.inner.download(msgtype).await
+    }
+
+    async fn resolve_room_alias(&self, alias: &RoomAliasId) -> transport::Result<Box<RoomId>> {
+        self.inner.resolve_room_alias(alias).await
+    }
+
+    async fn profile(
+        &self,
+        user_id: &UserId,
+    ) -> transport::Result<(Option<String>, Option<Box<MxcUri>>)> {
+        self.inner.profile(user_id).await
+    }
+
+    async fn power_level(&self, user_id: &UserId) -> transport::Result<i64> {
+        self.inner.power_level(user_id).await
+    }
+
+    async fn parent_spaces(&self) -> transport::Result<Vec<Box<RoomId>>> {
+        self.inner.parent_spaces().await
+    }
+
+    async fn space_power_level(
+        &self,
+        space_id: &RoomId,
+        user_id: &UserId,
+    ) -> transport::Result<i64> {
+        self.inner.space_power_level(space_id, user_id).await
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.inner.now()
+    }
+}
+
+/// Reads messages from a terminal and feeds them to a command tree
+pub struct Repl<C> {
+    dispatcher: Dispatcher<C>,
+    author: Box<UserId>,
+    room_id: Box<RoomId>,
+}
+
+impl Repl<BaseContext> {
+    pub fn new(commands: Group<BaseContext>) -> Self {
+        Self::with_context(commands, Arc::new(|base| base))
+    }
+}
+
+impl<C> DispatcherBuilder<C> for Repl<C> {
+    fn dispatcher_mut(&mut self) -> &mut Dispatcher<C> {
+        &mut self.dispatcher
+    }
+}
+
+impl<C: Send + Sync + 'static> Repl<C> {
+    pub fn with_context(
+        commands: Group<C>,
+        build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
+    ) -> Self {
+        Self {
+            dispatcher: Dispatcher::new(commands, build_context),
+            author: UserId::parse("@user:localhost").unwrap(),
+            room_id: RoomId::parse("!repl:localhost").unwrap(),
+        }
+    }
+
+    /// The user messages are sent as until changed with `:user`
+    pub fn with_author(mut self, user_id: Box<UserId>) -> Self {
+        self.author = user_id;
+        self
+    }
+
+    /// The room messages are sent to until changed with `:room`
+    pub fn with_room(mut self, room_id: Box<RoomId>) -> Self {
+        self.room_id = room_id;
+        self
+    }
+
+    /// Read lines until `:quit` or the end of the input
+    pub async fn run<R: AsyncBufRead + Unpin>(self, input: R) -> std::io::Result<()> {
+        let mut session = Session {
+            dispatcher: Arc::new(self.dispatcher),
+            transport: Arc::new(ReplTransport::new(&self.room_id)),
+            author: self.author,
+            room_id: self.room_id,
+        };
+
+        let mut lines = input.lines();
+
+        while let Some(line) = lines.next_line().await? {
+            if !session.line(&line).await {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct Session<C> {
+    dispatcher: Arc<Dispatcher<C>>,
+    transport: Arc<ReplTransport>,
+    author: Box<UserId>,
+    room_id: Box<RoomId>,
+}
+
+impl<C: Send + Sync + 'static> Session<C> {
+    /// Send a message to the bot and wait for it to be handled, so each line
+    /// sees what the ones before it did
+    async fn send(&mut self, content: serde_json::Value) {
+        let event_id = self.transport.inner.insert_message(&self.author, content);
+        let event = self
+            .transport
+            .inner
+            .message(&event_id)
+            .expect("The message was just inserted");
+
+        let incoming = Incoming {
+            transport: self.transport.clone(),
+            room_id: self.room_id.clone(),
+            event: serde_json::from_value(event).expect("Repl events are valid"),
+            thread_root: None,
+        };
+
+        // a task of its own so a panicking command doesn't end the session
+        let dispatcher = self.dispatcher.clone();
+        let task = tokio::spawn(async move {
+            if let Some(Err(err)) = dispatcher.dispatch(incoming).await {
+                tracing::debug!(%err, "Command failed");
+            }
+        });
+
+        if let Err(err) = task.await {
+            tracing::warn!(%err, "Command panicked");
+        }
+    }
+
+    /// The last message the bot sent in the current room
+    fn last_sent(&self) -> Option<Box<EventId>> {
+        self.transport
+            .inner
+            .actions()
+            .into_iter()
+            .rev()
+            .find_map(|action| match action {
+                Action::Send {
+                    event_id,
+                    event_type,
+                    ..
+                } if event_type == "m.room.message" => Some(event_id),
+                _ => None,
+            })
+    }
+
+    /// Handle a single line of input, returns `false` when the repl should exit
+    async fn line(&mut self, line: &str) -> bool {
+        let (directive, arg) = match line.strip_prefix(':') {
+            Some(directive) => directive.split_once(' ').unwrap_or((directive, "")),
+            None => {
+                if !line.is_empty() {
+                    self.send(serde_json::json!({ "msgtype": "m.text", "body": line }))
+                        .await;
+                }
+                return true;
+            }
+        };
+
+        match directive {
+            "quit" | "q" => return false,
+            "user" => match UserId::parse(arg.trim()) {
+                Ok(user_id) => self.author = user_id,
+                Err(err) => println!("Invalid user id: {}", err),
+            },
+            "room" => match RoomId::parse(arg.trim()) {
+                Ok(room_id) => {
+                    self.transport = Arc::new(ReplTransport::new(&room_id));
+                    self.room_id = room_id;
+                }
+                Err(err) => println!("Invalid room id: {}", err),
+            },
+            "power" => match arg.trim().parse() {
+                Ok(level) => self.transport.inner.set_power_level(&self.author, level),
+                Err(err) => println!("Invalid power level: {}", err),
+            },
+            "reply" => match self.last_sent() {
+                Some(event_id) => {
+                    self.send(serde_json::json!({
+                        "msgtype": "m.text",
+                        "body": arg,
+                        "m.relates_to": { "m.in_reply_to": { "event_id": event_id } },
+                    }))
+                    .await
+                }
+                None => println!("The bot hasn't sent anything to reply to"),
+            },
+            _ => println!("{}", HELP),
+        }
+
+        true
+    }
+}
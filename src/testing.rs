@@ -0,0 +1,478 @@
+//! Run commands without a homeserver.
+//!
+//! ```ignore
+//! use bleh::framework::dispatch::DispatcherBuilder;
+//!
+//! let tags = Arc::new(TagNames::load(&pool).await?);
+//! let bot = TestBot::new(make_commands(tags.clone())?)
+//!     .with_state(pool)
+//!     .with_state(tags);
+//!
+//! bot.send("!recur FREQ=DAILY hi").await;
+//!
+//! assert_eq!(bot.transport().actions()[0].reaction(), Some("✅"));
+//! ```
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use matrix_sdk::ruma::{
+    events::{
+        room::message::{MessageType, RoomMessageEventContent},
+        MessageEvent,
+    },
+    EventId, MxcUri, RoomAliasId, RoomId, UserId,
+};
+use mime::Mime;
+
+use crate::framework::{
+    commands::{CommandError, Group},
+    context::BaseContext,
+    dispatch::{Dispatcher, DispatcherBuilder, Incoming},
+    registry::Registry,
+    transport::{self, Transport},
+};
+
+/// Something the bot did through the mock transport
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Send {
+        event_id: Box<EventId>,
+        event_type: String,
+        content: serde_json::Value,
+    },
+    Redact {
+        event_id: Box<EventId>,
+        reason: Option<String>,
+    },
+    Typing(bool),
+    ReadReceipt(Box<EventId>),
+    Upload {
+        uri: Box<MxcUri>,
+        mimetype: String,
+        size: usize,
+    },
+}
+
+impl Action {
+    fn sent(&self, kind: &str) -> Option<&serde_json::Value> {
+        match self {
+            Action::Send {
+                event_type,
+                content,
+                ..
+            } if event_type == kind => Some(content),
+            _ => None,
+        }
+    }
+
+    /// The plain text body of a sent message
+    pub fn body(&self) -> Option<&str> {
+        self.sent("m.room.message")?["body"].as_str()
+    }
+
+    /// The `msgtype` of a sent message
+    pub fn msgtype(&self) -> Option<&str> {
+        self.sent("m.room.message")?["msgtype"].as_str()
+    }
+
+    /// The event a sent message replies to
+    pub fn in_reply_to(&self) -> Option<&str> {
+        self.sent("m.room.message")?["m.relates_to"]["m.in_reply_to"]["event_id"].as_str()
+    }
+
+    /// The key of a sent reaction
+    pub fn reaction(&self) -> Option<&str> {
+        self.sent("m.reaction")?["m.relates_to"]["key"].as_str()
+    }
+}
+
+/// A transport for a single room that records everything sent to it
+pub struct MockTransport {
+    bot_user: Box<UserId>,
+    room_id: Box<RoomId>,
+    next_id: AtomicU64,
+    actions: Mutex<Vec<Action>>,
+    messages: Mutex<HashMap<Box<EventId>, serde_json::Value>>,
+    media: Mutex<HashMap<String, Vec<u8>>>,
+    power_levels: Mutex<HashMap<Box<UserId>, i64>>,
+    aliases: Mutex<HashMap<Box<RoomAliasId>, Box<RoomId>>>,
+    profiles: Mutex<HashMap<Box<UserId>, (Option<String>, Option<Box<MxcUri>>)>>,
+    parent_spaces: Mutex<Vec<Box<RoomId>>>,
+    space_power_levels: Mutex<HashMap<(Box<RoomId>, Box<UserId>), i64>>,
+    now: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl MockTransport {
+    pub fn new(room_id: &RoomId) -> Self {
+        Self {
+            bot_user: UserId::parse("@bot:localhost").unwrap(),
+            room_id: room_id.to_owned(),
+            next_id: AtomicU64::new(0),
+            actions: Mutex::default(),
+            messages: Mutex::default(),
+            media: Mutex::default(),
+            power_levels: Mutex::default(),
+            aliases: Mutex::default(),
+            profiles: Mutex::default(),
+            parent_spaces: Mutex::default(),
+            space_power_levels: Mutex::default(),
+            now: Mutex::default(),
+        }
+    }
+
+    fn next_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn record(&self, action: Action) {
+        self.actions.lock().unwrap().push(action);
+    }
+
+    /// Everything the bot has done so far, in order
+    pub fn actions(&self) -> Vec<Action> {
+        self.actions.lock().unwrap().clone()
+    }
+
+    /// Everything the bot has done since the last call
+    pub fn take_actions(&self) -> Vec<Action> {
+        std::mem::take(&mut *self.actions.lock().unwrap())
+    }
+
+    /// Store a message in the room as if `sender` had sent it, returns its id
+    pub fn insert_message(&self, sender: &UserId, content: serde_json::Value) -> Box<EventId> {
+        let event_id = EventId::parse(format!("$mock{}:localhost", self.next_id())).unwrap();
+
+        let event = serde_json::json!({
+            "type": "m.room.message",
+            "event_id": event_id,
+            "room_id": self.room_id,
+            "sender": sender,
+            "origin_server_ts": self.now().timestamp_millis(),
+            "content": content,
+        });
+
+        self.messages
+            .lock()
+            .unwrap()
+            .insert(event_id.clone(), event);
+
+        event_id
+    }
+
+    pub(crate) fn message(&self, event_id: &EventId) -> Option<serde_json::Value> {
+        self.messages.lock().unwrap().get(event_id).cloned()
+    }
+
+    pub fn set_power_level(&self, user_id: &UserId, level: i64) {
+        self.power_levels
+            .lock()
+            .unwrap()
+            .insert(user_id.to_owned(), level);
+    }
+
+    pub fn add_alias(&self, alias: &RoomAliasId, room_id: &RoomId) {
+        self.aliases
+            .lock()
+            .unwrap()
+            .insert(alias.to_owned(), room_id.to_owned());
+    }
+
+    pub fn set_profile(
+        &self,
+        user_id: &UserId,
+        display_name: Option<&str>,
+        avatar_url: Option<&MxcUri>,
+    ) {
+        self.profiles.lock().unwrap().insert(
+            user_id.to_owned(),
+            (
+                display_name.map(str::to_owned),
+                avatar_url.map(ToOwned::to_owned),
+            ),
+        );
+    }
+
+    pub fn set_parent_spaces(&self, spaces: &[&RoomId]) {
+        *self.parent_spaces.lock().unwrap() = spaces.iter().map(|&s| s.to_owned()).collect();
+    }
+
+    pub fn set_space_power_level(&self, space_id: &RoomId, user_id: &UserId, level: i64) {
+        self.space_power_levels
+            .lock()
+            .unwrap()
+            .insert((space_id.to_owned(), user_id.to_owned()), level);
+    }
+
+    /// Freeze the clock at a point in time
+    pub fn set_now(&self, now: DateTime<Utc>) {
+        *self.now.lock().unwrap() = Some(now);
+    }
+
+    /// Move the clock forward, freezing it if it wasn't already
+    pub fn advance(&self, by: chrono::Duration) {
+        let now = self.now() + by;
+        self.set_now(now);
+    }
+}
+
+#[async_trait::async_trait]
+impl Transport for MockTransport {
+    async fn send_event(
+        &self,
+        event_type: &str,
+        content: serde_json::Value,
+    ) -> transport::Result<Box<EventId>> {
+        let event_id = if event_type == "m.room.message" {
+            self.insert_message(&self.bot_user, content.clone())
+        } else {
+            EventId::parse(format!("$mock{}:localhost", self.next_id()))?
+        };
+
+        self.record(Action::Send {
+            event_id: event_id.clone(),
+            event_type: event_type.to_owned(),
+            content,
+        });
+
+        Ok(event_id)
+    }
+
+    async fn redact(&self, event_id: &EventId, reason: Option<&str>) -> transport::Result<()> {
+        self.record(Action::Redact {
+            event_id: event_id.to_owned(),
+            reason: reason.map(str::to_owned),
+        });
+
+        Ok(())
+    }
+
+    async fn typing(&self, timeout: Option<Duration>) -> transport::Result<()> {
+        self.record(Action::Typing(timeout.is_some()));
+
+        Ok(())
+    }
+
+    async fn read_receipt(&self, event_id: &EventId) -> transport::Result<()> {
+        self.record(Action::ReadReceipt(event_id.to_owned()));
+
+        Ok(())
+    }
+
+    async fn fetch_message(
+        &self,
+        event_id: &EventId,
+    ) -> transport::Result<Option<MessageEvent<RoomMessageEventContent>>> {
+        match self.message(event_id) {
+            Some(event) => Ok(Some(serde_json::from_value(event)?)),
+            None => Err(format!("No event {} in the mock room", event_id).into()),
+        }
+    }
+
+    async fn upload(&self, mimetype: &Mime, data: &[u8]) -> transport::Result<Box<MxcUri>> {
+        let uri: Box<MxcUri> = format!("mxc://localhost/mock{}", self.next_id()).into();
+
+        self.media
+            .lock()
+            .unwrap()
+            .insert(uri.to_string(), data.to_owned());
+        self.record(Action::Upload {
+            uri: uri.clone(),
+            mimetype: mimetype.to_string(),
+            size: data.len(),
+        });
+
+        Ok(uri)
+    }
+
+    async fn download(&self, msgtype: &MessageType) -> transport::Result<Option<Vec<u8>>> {
+        let msgtype = serde_json::to_value(msgtype)?;
+
+        let data = msgtype["url"]
+            .as_str()
+            .and_then(|url| self.media.lock().unwrap().get(url).cloned());
+
+        Ok(data)
+    }
+
+    async fn resolve_room_alias(&self, alias: &RoomAliasId) -> transport::Result<Box<RoomId>> {
+        self.aliases
+            .lock()
+            .unwrap()
+            .get(alias)
+            .cloned()
+            .ok_or_else(|| format!("Unknown room alias {}", alias).into())
+    }
+
+    async fn profile(
+        &self,
+        user_id: &UserId,
+    ) -> transport::Result<(Option<String>, Option<Box<MxcUri>>)> {
+        Ok(self
+            .profiles
+            .lock()
+            .unwrap()
+            .get(user_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn power_level(&self, user_id: &UserId) -> transport::Result<i64> {
+        Ok(self
+            .power_levels
+            .lock()
+            .unwrap()
+            .get(user_id)
+            .copied()
+            .unwrap_or(0))
+    }
+
+    async fn parent_spaces(&self) -> transport::Result<Vec<Box<RoomId>>> {
+        Ok(self.parent_spaces.lock().unwrap().clone())
+    }
+
+    async fn space_power_level(
+        &self,
+        space_id: &RoomId,
+        user_id: &UserId,
+    ) -> transport::Result<i64> {
+        Ok(self
+            .space_power_levels
+            .lock()
+            .unwrap()
+            .get(&(space_id.to_owned(), user_id.to_owned()))
+            .copied()
+            .unwrap_or(0))
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.now.lock().unwrap().unwrap_or_else(Utc::now)
+    }
+}
+
+/// Feeds messages to a command tree as if they were sent to a room, recording
+/// what the bot does in response
+pub struct TestBot<C> {
+    dispatcher: Dispatcher<C>,
+    transport: Arc<MockTransport>,
+    author: Box<UserId>,
+    room_id: Box<RoomId>,
+}
+
+impl TestBot<BaseContext> {
+    pub fn new(commands: Group<BaseContext>) -> Self {
+        Self::with_context(commands, Arc::new(|base| base))
+    }
+}
+
+impl<C> DispatcherBuilder<C> for TestBot<C> {
+    fn dispatcher_mut(&mut self) -> &mut Dispatcher<C> {
+        &mut self.dispatcher
+    }
+}
+
+impl<C: Send + Sync + 'static> TestBot<C> {
+    pub fn with_context(
+        commands: Group<C>,
+        build_context: Arc<dyn Fn(BaseContext) -> C + Send + Sync + 'static>,
+    ) -> Self {
+        let room_id = RoomId::parse("!room:localhost").unwrap();
+
+        Self {
+            dispatcher: Dispatcher::new(commands, build_context),
+            transport: Arc::new(MockTransport::new(&room_id)),
+            author: UserId::parse("@user:localhost").unwrap(),
+            room_id,
+        }
+    }
+
+    pub fn transport(&self) -> &MockTransport {
+        &self.transport
+    }
+
+    pub fn registry(&self) -> &Arc<Registry<C>> {
+        self.dispatcher.registry()
+    }
+
+    pub fn author(&self) -> &UserId {
+        &self.author
+    }
+
+    /// Send following messages as another user
+    pub fn set_author(&mut self, user_id: &str) -> &mut Self {
+        self.author = UserId::parse(user_id).expect("Invalid user id");
+        self
+    }
+
+    /// Send following messages to another room, this resets the transport
+    pub fn set_room(&mut self, room_id: &str) -> &mut Self {
+        self.room_id = RoomId::parse(room_id).expect("Invalid room id");
+        self.transport = Arc::new(MockTransport::new(&self.room_id));
+        self
+    }
+
+    async fn run(
+        &self,
+        content: serde_json::Value,
+        thread_root: Option<&EventId>,
+    ) -> Option<Result<(), CommandError>> {
+        let event_id = self.transport.insert_message(&self.author, content);
+        let event = self
+            .transport
+            .message(&event_id)
+            .expect("The message was just inserted");
+
+        let incoming = Incoming {
+            transport: self.transport.clone(),
+            room_id: self.room_id.clone(),
+            event: serde_json::from_value(event).expect("Mock events are valid"),
+            thread_root: thread_root.map(ToOwned::to_owned),
+        };
+
+        self.dispatcher.dispatch(incoming).await
+    }
+
+    /// Send a text message, returns `None` if it didn't invoke a command
+    pub async fn send(&self, body: &str) -> Option<Result<(), CommandError>> {
+        let content = serde_json::json!({ "msgtype": "m.text", "body": body });
+
+        self.run(content, None).await
+    }
+
+    /// Send a text message as a reply to another event
+    pub async fn reply_to(
+        &self,
+        event_id: &EventId,
+        body: &str,
+    ) -> Option<Result<(), CommandError>> {
+        let content = serde_json::json!({
+            "msgtype": "m.text",
+            "body": body,
+            "m.relates_to": { "m.in_reply_to": { "event_id": event_id } },
+        });
+
+        self.run(content, None).await
+    }
+
+    /// Send a text message inside a thread
+    pub async fn send_in_thread(
+        &self,
+        thread_root: &EventId,
+        body: &str,
+    ) -> Option<Result<(), CommandError>> {
+        let content = serde_json::json!({
+            "msgtype": "m.text",
+            "body": body,
+            "m.relates_to": { "rel_type": "m.thread", "event_id": thread_root },
+        });
+
+        self.run(content, Some(thread_root)).await
+    }
+}
@@ -0,0 +1,179 @@
+#![feature(adt_const_params)]
+#![feature(generic_const_exprs)]
+#![feature(const_fn_trait_bound)]
+#![feature(const_raw_ptr_deref)]
+#![feature(const_slice_from_raw_parts)]
+#![feature(const_type_name)]
+
+use std::sync::Arc;
+
+use bleh::{
+    framework::{
+        aliases::{self, MemoryAliasStore},
+        commands::{cmd, CommandError, Group, GroupBuilder, Named, Remainder},
+        config::BotConfig,
+        context::{BaseContext, ContextActions},
+        dispatch::DispatcherBuilder,
+        policy::{self, MemoryPolicyStore, MODERATOR_LEVEL},
+    },
+    testing::TestBot,
+};
+
+fn commands() -> Group<BaseContext> {
+    GroupBuilder::new()
+        .command(
+            "say",
+            cmd(
+                |c: BaseContext, Named(Remainder(text)): Named<Remainder, "text">| async move {
+                    let _ = c.send(&text).await;
+                },
+            ),
+        )
+        .group("alias", aliases::commands())
+        .group("commands", policy::commands())
+        .build()
+        .unwrap()
+}
+
+fn bot() -> TestBot<BaseContext> {
+    TestBot::new(commands())
+        .with_config(BotConfig::default().with_max_chain_length(3))
+        .with_aliases(Arc::new(MemoryAliasStore::new()))
+        .with_policy(Arc::new(MemoryPolicyStore::new()))
+}
+
+/// The bodies of the messages sent since the last call
+fn sent(bot: &TestBot<BaseContext>) -> Vec<String> {
+    bot.transport()
+        .take_actions()
+        .iter()
+        .filter_map(|a| a.body().map(str::to_owned))
+        .collect()
+}
+
+/// Add an alias, checking that it was saved
+async fn add(bot: &TestBot<BaseContext>, command: &str) {
+    assert!(matches!(bot.send(command).await, Some(Ok(()))));
+
+    let actions = bot.transport().take_actions();
+    assert_eq!(
+        actions.last().and_then(|a| a.reaction()),
+        Some("✅"),
+        "{}",
+        command
+    );
+}
+
+#[tokio::test]
+async fn aliases_are_expanded() {
+    let bot = bot();
+
+    add(&bot, "!alias add greet say hello $1!").await;
+    assert!(matches!(bot.send("!greet bob").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["hello bob!"]);
+
+    add(&bot, "!alias add shout !say").await;
+    bot.send("!shout a b").await;
+    assert_eq!(sent(&bot), ["a b"]);
+}
+
+#[tokio::test]
+async fn aliases_can_chain_commands() {
+    let bot = bot();
+
+    add(&bot, "!alias add both \"say a; !say $@\"").await;
+    bot.send("!both b").await;
+    assert_eq!(sent(&bot), ["a", "b"]);
+
+    // an alias used in a chain takes the place of the command it replaced
+    bot.send("!both b; !say c").await;
+    assert_eq!(sent(&bot), ["a", "b", "c"]);
+}
+
+#[tokio::test]
+async fn expanded_chains_are_limited() {
+    let bot = bot();
+
+    bot.send("!alias add many \"say 1; !say 2; !say 3; !say 4\"")
+        .await;
+    assert_eq!(sent(&bot), ["An alias can stand for at most 3 commands"]);
+
+    add(&bot, "!alias add two \"say a; !say b\"").await;
+    add(&bot, "!alias add four \"two; !two\"").await;
+    assert!(matches!(
+        bot.send("!four").await,
+        Some(Err(CommandError::ChainTooLong { max: 3 }))
+    ));
+    assert_eq!(sent(&bot).len(), 1);
+}
+
+#[tokio::test]
+async fn aliases_are_limited_in_depth() {
+    let bot = bot();
+
+    add(&bot, "!alias add ping pong").await;
+    add(&bot, "!alias add pong ping").await;
+
+    match bot.send("!ping").await {
+        Some(Err(CommandError::AliasTooDeep { max, .. })) => {
+            assert_eq!(max, BotConfig::default().max_alias_depth)
+        }
+        other => panic!("Expected AliasTooDeep, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn commands_win_over_aliases() {
+    let bot = bot();
+
+    bot.send("!alias add say say no").await;
+    assert_eq!(sent(&bot), ["`say` is already a command"]);
+}
+
+#[tokio::test]
+async fn disabled_commands_keep_their_names() {
+    let bot = bot();
+    bot.transport()
+        .set_power_level(bot.author(), MODERATOR_LEVEL);
+
+    add(&bot, "!commands disable say").await;
+    bot.send("!alias add say echo").await;
+    assert_eq!(sent(&bot), ["`say` is already a command"]);
+}
+
+#[tokio::test]
+async fn users_aliases_win_over_the_rooms() {
+    let mut bot = bot();
+
+    add(&bot, "!alias add hi say the room's").await;
+    add(&bot, "!alias me add hi say mine").await;
+
+    bot.send("!hi").await;
+    assert_eq!(sent(&bot), ["mine"]);
+
+    bot.set_author("@other:localhost");
+    bot.send("!hi").await;
+    assert_eq!(sent(&bot), ["the room's"]);
+}
+
+#[tokio::test]
+async fn only_the_author_or_a_moderator_can_replace_a_rooms_alias() {
+    let mut bot = bot();
+
+    add(&bot, "!alias add hi say first").await;
+
+    bot.set_author("@other:localhost");
+    bot.send("!alias add hi say second").await;
+    bot.send("!alias remove hi").await;
+    assert_eq!(sent(&bot).len(), 2);
+
+    bot.send("!hi").await;
+    assert_eq!(sent(&bot), ["first"]);
+
+    bot.transport()
+        .set_power_level(bot.author(), MODERATOR_LEVEL);
+    add(&bot, "!alias add hi say second").await;
+
+    bot.send("!hi").await;
+    assert_eq!(sent(&bot), ["second"]);
+}
@@ -0,0 +1,237 @@
+#![feature(adt_const_params)]
+#![feature(generic_const_exprs)]
+#![feature(const_fn_trait_bound)]
+#![feature(const_raw_ptr_deref)]
+#![feature(const_slice_from_raw_parts)]
+#![feature(const_type_name)]
+
+use std::sync::Arc;
+
+use bleh::{
+    framework::{
+        commands::{cmd, CommandError, FallbackNames, Group, GroupBuilder, Named, Remainder},
+        config::BotConfig,
+        context::{BaseContext, ContextActions},
+        dispatch::DispatcherBuilder,
+        help,
+    },
+    testing::TestBot,
+};
+use matrix_sdk::ruma::RoomId;
+
+fn commands() -> Group<BaseContext> {
+    GroupBuilder::new()
+        .command(
+            "say",
+            cmd(
+                |c: BaseContext, Named(Remainder(text)): Named<Remainder, "text">| async move {
+                    let _ = c.send(&text).await;
+                },
+            ),
+        )
+        .command(
+            "upper",
+            cmd(
+                |c: BaseContext, Named(Remainder(text)): Named<Remainder, "text">| async move {
+                    let _ = c.send(&text.to_uppercase()).await;
+                },
+            ),
+        )
+        .command(
+            "add",
+            cmd(
+                |c: BaseContext, Named(a): Named<i64, "a">, Named(b): Named<i64, "b">| async move {
+                    let _ = c.send(&(a + b).to_string()).await;
+                },
+            ),
+        )
+        .command(
+            "kind",
+            cmd(|c: BaseContext, Named(n): Named<i64, "n">| async move {
+                let _ = c.send(&format!("number {}", n)).await;
+            })
+            .overload(cmd(
+                |c: BaseContext, Named(b): Named<bool, "b">| async move {
+                    let _ = c.send(&format!("bool {}", b)).await;
+                },
+            )),
+        )
+        .group(
+            "math",
+            GroupBuilder::new().command(
+                "double",
+                cmd(|c: BaseContext, Named(n): Named<i64, "n">| async move {
+                    let _ = c.send(&(n * 2).to_string()).await;
+                }),
+            ),
+        )
+        .build()
+        .unwrap()
+}
+
+/// The bodies of the messages sent since the last call
+fn sent(bot: &TestBot<BaseContext>) -> Vec<String> {
+    bot.transport()
+        .take_actions()
+        .iter()
+        .filter_map(|a| a.body().map(str::to_owned))
+        .collect()
+}
+
+#[tokio::test]
+async fn runs_commands() {
+    let bot = TestBot::new(commands());
+
+    assert!(matches!(bot.send("!say hi there").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["hi there"]);
+
+    assert!(matches!(bot.send("!math double 4").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["8"]);
+}
+
+#[tokio::test]
+async fn ignores_what_isnt_a_command() {
+    let bot = TestBot::new(commands());
+
+    assert!(bot.send("say hi").await.is_none());
+    assert!(bot.send("!nope").await.is_none());
+    assert!(bot.send("!").await.is_none());
+    assert!(bot.transport().actions().is_empty());
+}
+
+#[tokio::test]
+async fn reports_parse_errors() {
+    let bot = TestBot::new(commands());
+
+    assert!(matches!(
+        bot.send("!add 1 x").await,
+        Some(Err(CommandError::Parse { .. }))
+    ));
+
+    let replies = sent(&bot);
+    assert_eq!(replies.len(), 1);
+    assert!(replies[0].starts_with("Couldn't parse"), "{}", replies[0]);
+}
+
+#[tokio::test]
+async fn overloads_are_tried_in_order() {
+    let bot = TestBot::new(commands());
+
+    bot.send("!kind 5").await;
+    bot.send("!kind yes").await;
+    assert_eq!(sent(&bot), ["number 5", "bool true"]);
+
+    match bot.send("!kind x").await {
+        Some(Err(CommandError::NoMatch(candidates))) => {
+            let usages = candidates
+                .iter()
+                .map(|(u, _)| u.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(usages, ["n: i64", "b: bool"]);
+        }
+        other => panic!("Expected NoMatch, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn chains_commands() {
+    let bot = TestBot::new(commands());
+
+    assert!(matches!(bot.send("!say a; !say b").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["a", "b"]);
+
+    // only the end of a pipeline is sent
+    assert!(matches!(bot.send("!say a | !upper").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["A"]);
+
+    // separators that aren't followed by the prefix are part of the text
+    bot.send("!say a; b | c").await;
+    assert_eq!(sent(&bot), ["a; b | c"]);
+}
+
+#[tokio::test]
+async fn chains_stop_at_the_first_failure() {
+    let bot = TestBot::new(commands());
+
+    assert!(matches!(
+        bot.send("!say a; !add 1 x; !say b").await,
+        Some(Err(CommandError::Step { step: 2, .. }))
+    ));
+
+    // the first step's output and the error
+    let messages = sent(&bot);
+    assert_eq!(messages[0], "a");
+    assert_eq!(messages.len(), 2, "{:?}", messages);
+
+    assert!(matches!(
+        bot.send("!say a; !nope").await,
+        Some(Err(CommandError::UnknownStep { step: 2, .. }))
+    ));
+}
+
+#[tokio::test]
+async fn chains_not_starting_with_a_command_are_ignored() {
+    let bot = TestBot::new(commands());
+
+    assert!(bot.send("!nope; !say a").await.is_none());
+    assert!(bot.transport().actions().is_empty());
+}
+
+#[tokio::test]
+async fn chains_are_limited() {
+    let bot = TestBot::new(commands()).with_config(BotConfig::default().with_max_chain_length(2));
+
+    assert!(matches!(
+        bot.send("!say a; !say b; !say c").await,
+        Some(Err(CommandError::ChainTooLong { max: 2 }))
+    ));
+    assert_eq!(sent(&bot).len(), 1);
+
+    // without chaining separators are part of the text
+    let bot = TestBot::new(commands()).with_config(BotConfig::default().with_max_chain_length(1));
+
+    bot.send("!say a; !say b").await;
+    assert_eq!(sent(&bot), ["a; !say b"]);
+}
+
+/// A fallback only answering to `rules`
+struct Rules;
+
+impl FallbackNames for Rules {
+    fn contains(&self, _room_id: &RoomId, name: &str) -> bool {
+        name == "rules"
+    }
+
+    fn names(&self, _room_id: &RoomId) -> Vec<String> {
+        vec!["rules".to_owned()]
+    }
+}
+
+#[tokio::test]
+async fn fallbacks_with_names_only_take_those() {
+    let commands = GroupBuilder::new()
+        .command("help", help::command())
+        .fallback(
+            cmd(
+                |c: BaseContext, Named(Remainder(text)): Named<Remainder, "text">| async move {
+                    let _ = c.send(&format!("fallback: {}", text)).await;
+                },
+            )
+            .with_description("send a rule")
+            .with_names(Arc::new(Rules)),
+        )
+        .build()
+        .unwrap();
+    let bot = TestBot::new(commands);
+
+    assert!(matches!(bot.send("!rules 1").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["fallback: rules 1"]);
+
+    assert!(bot.send("!nope").await.is_none());
+    assert!(bot.transport().actions().is_empty());
+
+    bot.send("!help").await;
+    let help = sent(&bot);
+    assert!(help[0].contains("!rules"), "{}", help[0]);
+    assert!(!help[0].contains("<text...>"), "{}", help[0]);
+}
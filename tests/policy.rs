@@ -0,0 +1,158 @@
+#![feature(adt_const_params)]
+#![feature(generic_const_exprs)]
+#![feature(const_fn_trait_bound)]
+#![feature(const_raw_ptr_deref)]
+#![feature(const_slice_from_raw_parts)]
+#![feature(const_type_name)]
+
+use std::sync::Arc;
+
+use bleh::{
+    framework::{
+        commands::{cmd, Group, GroupBuilder, Named, Remainder},
+        context::{BaseContext, ContextActions},
+        dispatch::DispatcherBuilder,
+        policy::{self, MemoryPolicyStore, ADMIN_LEVEL, MODERATOR_LEVEL},
+    },
+    testing::TestBot,
+};
+use matrix_sdk::ruma::RoomId;
+
+fn commands() -> Group<BaseContext> {
+    GroupBuilder::new()
+        .command(
+            "say",
+            cmd(
+                |c: BaseContext, Named(Remainder(text)): Named<Remainder, "text">| async move {
+                    let _ = c.send(&text).await;
+                },
+            )
+            .with_aliases(&["echo"]),
+        )
+        .command(
+            "double",
+            cmd(|c: BaseContext, Named(n): Named<i64, "n">| async move {
+                let _ = c.send(&(n * 2).to_string()).await;
+            }),
+        )
+        .group("commands", policy::commands())
+        .build()
+        .unwrap()
+}
+
+fn bot() -> TestBot<BaseContext> {
+    TestBot::new(commands()).with_policy(Arc::new(MemoryPolicyStore::new()))
+}
+
+/// Send a message that should succeed, checking that it was acknowledged
+async fn ok(bot: &TestBot<BaseContext>, body: &str) {
+    assert!(matches!(bot.send(body).await, Some(Ok(()))), "{}", body);
+
+    let actions = bot.transport().take_actions();
+    assert_eq!(
+        actions.last().and_then(|a| a.reaction()),
+        Some("✅"),
+        "{}",
+        body
+    );
+}
+
+/// The one reply to a message
+async fn reply(bot: &TestBot<BaseContext>, body: &str) -> String {
+    bot.send(body).await;
+
+    let actions = bot.transport().take_actions();
+    assert_eq!(actions.len(), 1, "{:?}", actions);
+    actions[0].body().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn disabled_commands_are_ignored() {
+    let bot = bot();
+    bot.transport()
+        .set_power_level(bot.author(), MODERATOR_LEVEL);
+
+    ok(&bot, "!commands disable say").await;
+    assert!(bot.send("!say hi").await.is_none());
+    assert!(bot.send("!echo hi").await.is_none());
+
+    ok(&bot, "!commands enable say").await;
+    assert!(matches!(bot.send("!say hi").await, Some(Ok(()))));
+}
+
+#[tokio::test]
+async fn disabled_commands_are_ignored_even_if_they_dont_parse() {
+    let bot = bot();
+    bot.transport()
+        .set_power_level(bot.author(), MODERATOR_LEVEL);
+
+    ok(&bot, "!commands disable double").await;
+    assert!(bot.send("!double x").await.is_none());
+    assert!(bot.transport().actions().is_empty());
+}
+
+#[tokio::test]
+async fn the_most_specific_rule_wins() {
+    let bot = bot();
+    bot.transport()
+        .set_power_level(bot.author(), MODERATOR_LEVEL);
+
+    ok(&bot, "!commands disable").await;
+    assert!(bot.send("!say hi").await.is_none());
+
+    // the policy commands can't be disabled, so they can undo it
+    ok(&bot, "!commands enable say").await;
+    assert!(matches!(bot.send("!say hi").await, Some(Ok(()))));
+    assert!(bot.send("!double 2").await.is_none());
+}
+
+#[tokio::test]
+async fn rules_are_saved_under_the_command_name() {
+    let bot = bot();
+    bot.transport()
+        .set_power_level(bot.author(), MODERATOR_LEVEL);
+
+    ok(&bot, "!commands disable echo").await;
+    assert!(bot.send("!say hi").await.is_none());
+
+    assert_eq!(
+        reply(&bot, "!commands disable nope").await,
+        "There's no command or group `nope`"
+    );
+}
+
+#[tokio::test]
+async fn changing_the_policy_needs_a_moderator() {
+    let bot = bot();
+
+    assert_eq!(
+        reply(&bot, "!commands disable say").await,
+        format!(
+            "You need a power level of at least {} to do that",
+            MODERATOR_LEVEL
+        )
+    );
+    assert!(matches!(bot.send("!say hi").await, Some(Ok(()))));
+}
+
+#[tokio::test]
+async fn space_rules_need_an_admin_of_the_space() {
+    let bot = bot();
+    let space = RoomId::parse("!space:localhost").unwrap();
+    bot.transport().set_parent_spaces(&[space.as_ref()]);
+    bot.transport().set_power_level(bot.author(), ADMIN_LEVEL);
+
+    let needs_admin = reply(&bot, "!commands space disable say").await;
+    assert!(needs_admin.contains(space.as_str()), "{}", needs_admin);
+    assert!(matches!(bot.send("!say hi").await, Some(Ok(()))));
+    bot.transport().take_actions();
+
+    bot.transport()
+        .set_space_power_level(&space, bot.author(), ADMIN_LEVEL);
+    ok(&bot, "!commands space disable say").await;
+    assert!(bot.send("!say hi").await.is_none());
+
+    // the room's own rules win over the space's
+    ok(&bot, "!commands enable say").await;
+    assert!(matches!(bot.send("!say hi").await, Some(Ok(()))));
+}
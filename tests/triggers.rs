@@ -0,0 +1,156 @@
+#![feature(adt_const_params)]
+#![feature(generic_const_exprs)]
+#![feature(const_fn_trait_bound)]
+#![feature(const_raw_ptr_deref)]
+#![feature(const_slice_from_raw_parts)]
+#![feature(const_type_name)]
+
+use std::{sync::Arc, time::Duration};
+
+use bleh::{
+    framework::{
+        commands::{cmd, ErasedCommand, Group, GroupBuilder, Named, Remainder},
+        context::{BaseContext, ContextActions},
+        dispatch::DispatcherBuilder,
+        policy::{self, MemoryPolicyStore, MODERATOR_LEVEL},
+        triggers::{Capture, Trigger},
+    },
+    testing::TestBot,
+};
+
+fn say() -> ErasedCommand<BaseContext> {
+    cmd(
+        |c: BaseContext, Named(Remainder(text)): Named<Remainder, "text">| async move {
+            let _ = c.send(&text).await;
+        },
+    )
+}
+
+/// Sends its arguments back in brackets
+fn brackets() -> ErasedCommand<BaseContext> {
+    cmd(
+        |c: BaseContext,
+         Named(Capture(a)): Named<Capture, "a">,
+         Named(Capture(b)): Named<Capture, "b">,
+         Named(Remainder(rest)): Named<Remainder, "rest">| async move {
+            let _ = c.send(&format!("[{}] [{}] [{}]", a, b, rest)).await;
+        },
+    )
+}
+
+fn commands() -> Group<BaseContext> {
+    GroupBuilder::new()
+        .command("say", say())
+        .group("commands", policy::commands())
+        .build()
+        .unwrap()
+}
+
+/// The bodies of the messages sent since the last call
+fn sent(bot: &TestBot<BaseContext>) -> Vec<String> {
+    bot.transport()
+        .take_actions()
+        .iter()
+        .filter_map(|a| a.body().map(str::to_owned))
+        .collect()
+}
+
+#[tokio::test]
+async fn triggers_run_on_matching_messages() {
+    let bot = TestBot::new(commands())
+        .with_trigger(Trigger::keyword(
+            "pong",
+            "ping",
+            cmd(|c: BaseContext| async move {
+                let _ = c.send("pong").await;
+            }),
+        ))
+        .with_trigger(Trigger::regex("echo", r"^echo (.+)$", say()).unwrap());
+
+    assert!(matches!(bot.send("PING?").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["pong"]);
+
+    bot.send("echo hi there").await;
+    assert_eq!(sent(&bot), ["hi there"]);
+
+    assert!(bot.send("pinging").await.is_none());
+    assert!(bot.transport().actions().is_empty());
+}
+
+#[tokio::test]
+async fn triggers_dont_see_commands() {
+    let bot = TestBot::new(commands())
+        .with_trigger(Trigger::regex("echo", r"echo (.+)$", say()).unwrap());
+
+    bot.send("!say echo hi").await;
+    assert_eq!(sent(&bot), ["echo hi"]);
+}
+
+#[tokio::test]
+async fn groups_are_arguments() {
+    let bot = TestBot::new(commands())
+        .with_trigger(Trigger::regex("brackets", r"^(\w+)?-(.+) to (.+)$", brackets()).unwrap());
+
+    // groups that didn't match are empty, and quotes in groups are kept
+    bot.send("-\"hi\" there to bob and alice").await;
+    assert_eq!(sent(&bot), ["[] [\"hi\" there] [bob and alice]"]);
+}
+
+#[tokio::test]
+async fn triggers_have_cooldowns() {
+    let bot = TestBot::new(commands()).with_trigger(
+        Trigger::regex("echo", r"^echo (.+)$", say())
+            .unwrap()
+            .with_cooldown(Duration::from_secs(60)),
+    );
+    bot.transport().set_now(chrono::Utc::now());
+
+    assert!(bot.send("echo a").await.is_some());
+    assert!(bot.send("echo b").await.is_none());
+
+    bot.transport().advance(chrono::Duration::seconds(60));
+    assert!(bot.send("echo c").await.is_some());
+    assert_eq!(sent(&bot), ["a", "c"]);
+}
+
+#[tokio::test]
+async fn messages_that_dont_parse_leave_the_cooldown() {
+    let double = cmd(|c: BaseContext, Named(n): Named<i64, "n">| async move {
+        let _ = c.send(&(n * 2).to_string()).await;
+    });
+    let bot = TestBot::new(commands()).with_trigger(
+        Trigger::regex("double", r"^double (\S+)$", double)
+            .unwrap()
+            .with_cooldown(Duration::from_secs(60)),
+    );
+    bot.transport().set_now(chrono::Utc::now());
+
+    assert!(matches!(bot.send("double x").await, Some(Err(_))));
+    assert!(matches!(bot.send("double 2").await, Some(Ok(()))));
+    assert_eq!(sent(&bot), ["4"]);
+}
+
+#[tokio::test]
+async fn triggers_follow_the_policy() {
+    let bot = TestBot::new(commands())
+        .with_policy(Arc::new(MemoryPolicyStore::new()))
+        .with_trigger(
+            Trigger::regex("echo", r"^echo (.+)$", say())
+                .unwrap()
+                .with_path(&["say"]),
+        );
+    bot.transport()
+        .set_power_level(bot.author(), MODERATOR_LEVEL);
+
+    bot.send("!commands disable say").await;
+    bot.transport().take_actions();
+
+    assert!(bot.send("echo hi").await.is_none());
+    assert!(bot.transport().actions().is_empty());
+
+    bot.send("!commands enable say").await;
+    bot.transport().take_actions();
+
+    bot.send("echo hi").await;
+    assert_eq!(sent(&bot), ["hi"]);
+}